@@ -4,7 +4,7 @@ set -e
 
 
 if [ "$1" = "--help" ] || [ "$1" = "-h" ]; then
-    echo "usage: $0 <path-to-binary.elf>" >&2
+    echo "usage: $0 <path-to-binary.elf> <device> [uno|nano|mega2560]" >&2
     exit 1
 fi
 
@@ -15,13 +15,21 @@ fi
 
 elf_file="$1"
 device="$2"
+board="${3:-uno}"
+
+case "$board" in
+    uno) mcu=atmega328p; programmer=arduino ;;
+    nano) mcu=atmega328p; programmer=arduino ;;
+    mega2560) mcu=atmega2560; programmer=wiring ;;
+    *) echo "$0: Unknown board $board" >&2; exit 1 ;;
+esac
 
 # Build project
 # rustup run nightly cargo build --release
 docker run -it -v "${PWD}":/usr/src/myapp dolly
 
 # Flash into board
-avrdude -q -patmega328p -carduino -P${device} -D "-Uflash:w:${elf_file}:e"
+avrdude -q -p${mcu} -c${programmer} -P${device} -D "-Uflash:w:${elf_file}:e"
 
 # Opens serial console
-ravedude uno --open-console --baudrate 57600
+ravedude ${board} --open-console --baudrate 57600
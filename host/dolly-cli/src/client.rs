@@ -0,0 +1,138 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use dolly_protocol::{Demux, Event, Message, Packet, MAX_FRAME_LEN};
+
+use crate::{transport::Transport, units::describe_infeasible};
+
+pub enum Incoming {
+    Line(String),
+    Packet(Packet),
+}
+
+pub struct Client {
+    transport: Box<dyn Transport>,
+    demux: Demux,
+    line: Vec<u8>,
+    incoming: VecDeque<Incoming>,
+    seq: u8,
+}
+
+impl Client {
+    const TIMEOUT: Duration = Duration::from_millis(500);
+    const RETRIES: usize = 3;
+
+    pub fn new(transport: Box<dyn Transport>) -> Self {
+        Self {
+            transport,
+            demux: Demux::new(),
+            line: Vec::new(),
+            incoming: VecDeque::new(),
+            // the dolly answers a repeat of its last request from memory, a
+            // new session must not start on the number the last one ended on
+            seq: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |t| t.subsec_nanos() as u8),
+        }
+    }
+
+    fn fill(&mut self, timeout: Duration) -> Result<()> {
+        let mut buf = [0u8; 256];
+        let n = self.transport.receive(&mut buf, timeout)?;
+
+        for byte in &buf[..n] {
+            match self.demux.push(*byte) {
+                Some(Event::Text(b'\n')) => {
+                    let line = String::from_utf8_lossy(&self.line).trim_end().to_owned();
+                    self.line.clear();
+                    self.incoming.push_back(Incoming::Line(line));
+                }
+                Some(Event::Text(byte)) => self.line.push(byte),
+                Some(Event::Packet(packet)) => self.incoming.push_back(Incoming::Packet(packet)),
+                // a corrupted reply is simply retried
+                Some(Event::Error(_)) | None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn take_reply(&mut self, seq: u8) -> Option<Message> {
+        // telemetry is unsolicited and has its own sequence numbers
+        let index = self.incoming.iter().position(|i| {
+            matches!(i, Incoming::Packet(p) if p.seq == seq && !matches!(p.message, Message::Telemetry(_)))
+        })?;
+
+        match self.incoming.remove(index) {
+            Some(Incoming::Packet(packet)) => Some(packet.message),
+            _ => None,
+        }
+    }
+
+    pub fn request(&mut self, message: Message) -> Result<Message> {
+        self.exchange(message, Self::TIMEOUT, Self::RETRIES)
+    }
+
+    // Sent once, for a message the dolly takes a long time to carry out
+    pub fn request_within(&mut self, message: Message, timeout: Duration) -> Result<Message> {
+        self.exchange(message, timeout, 1)
+    }
+
+    fn exchange(&mut self, message: Message, timeout: Duration, tries: usize) -> Result<Message> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        let len = Packet::new(self.seq, message)
+            .encode(&mut frame)
+            .map_err(|e| anyhow!("encoding {message:?}: {}", e.as_str()))?;
+
+        // a resend keeps its number, so a reply that is only late is not
+        // carried out a second time
+        for _ in 0..tries {
+            self.transport.send(&frame[..len])?;
+
+            let deadline = Instant::now() + timeout;
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                if let Some(reply) = self.take_reply(self.seq) {
+                    return Ok(reply);
+                }
+                self.fill(remaining)?;
+            }
+            if let Some(reply) = self.take_reply(self.seq) {
+                return Ok(reply);
+            }
+        }
+
+        bail!("no reply from the dolly to {message:?}")
+    }
+
+    pub fn command(&mut self, message: Message) -> Result<()> {
+        let reply = self.request(message)?;
+        Self::accepted(message, reply)
+    }
+
+    pub fn command_within(&mut self, message: Message, timeout: Duration) -> Result<()> {
+        let reply = self.request_within(message, timeout)?;
+        Self::accepted(message, reply)
+    }
+
+    fn accepted(message: Message, reply: Message) -> Result<()> {
+        match reply {
+            Message::Ack => Ok(()),
+            Message::Nack(code) => bail!("dolly refused {message:?}: {code:?}"),
+            Message::Infeasible(e) => {
+                bail!("dolly refused {message:?}: {}", describe_infeasible(&e))
+            }
+            other => bail!("unexpected reply to {message:?}: {other:?}"),
+        }
+    }
+
+    pub fn next(&mut self, timeout: Duration) -> Result<Option<Incoming>> {
+        if self.incoming.is_empty() {
+            self.fill(timeout)?;
+        }
+        Ok(self.incoming.pop_front())
+    }
+}
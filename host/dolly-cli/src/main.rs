@@ -0,0 +1,523 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use dolly_protocol::{
+    Aim, ErrorCode, FocusStack, Lens, Message, Panorama, SettingId, Spacing, Step, StopMotion,
+};
+
+use crate::{
+    client::{Client, Incoming},
+    transport::{Loopback, Serial, Transport},
+    units::{format_milli, parse_milli, parse_milli_pair},
+};
+
+mod client;
+mod sequence_file;
+mod sim;
+mod transport;
+mod units;
+
+/// Companion tool for the camera dolly
+#[derive(Parser)]
+struct Cli {
+    /// Serial port the dolly is connected to
+    #[arg(short, long, required_unless_present = "loopback")]
+    port: Option<String>,
+
+    #[arg(short, long, default_value_t = 57_600)]
+    baud: u32,
+
+    /// Talk to a simulated dolly instead of a board
+    #[arg(long, conflicts_with = "port")]
+    loopback: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that the dolly answers
+    Ping,
+    /// Allow motion again after a watchdog reset
+    Ack,
+    /// Home the axes again after homing failed at power on
+    Home {
+        /// Trust the positions as they are instead, e.g. without stall detection
+        #[arg(long)]
+        skip: bool,
+    },
+    /// Replace the sequence on the dolly with the keyframes in a file
+    Upload { file: PathBuf },
+    /// Save the sequence on the dolly to a file
+    Download { file: PathBuf },
+    /// Read a setting
+    Get { setting: Setting },
+    /// Write a setting
+    Set { setting: Setting, value: i32 },
+    /// Start the uploaded sequence
+    Start,
+    /// Move from the first keyframe to the last in exactly this many seconds
+    Video {
+        seconds: String,
+
+        /// Seconds to count down on the LEDs once at the start
+        #[arg(long, default_value_t = 0)]
+        preroll: u8,
+    },
+    /// Shoot a grid of frames over a pan and tilt area
+    Panorama(PanoramaArgs),
+    /// Keep the camera pointed at a subject while the slider moves
+    Track {
+        #[command(subcommand)]
+        aim: AimCommand,
+    },
+    /// Cut the move from the first keyframe to the last into frames for stop motion
+    Animate {
+        /// Frames after the first one
+        steps: u16,
+
+        /// Rest after each shooting step before the shutter
+        #[arg(long, default_value_t = 500)]
+        settle_ms: u16,
+
+        /// How long to hold the shutter, 0 leaves the camera alone
+        #[arg(long, default_value_t = 100)]
+        exposure_ms: u16,
+
+        /// Start at the first frame instead of where the dolly left off
+        #[arg(long)]
+        restart: bool,
+    },
+    /// Take one step of a stop-motion animation
+    Frame { step: FrameStep },
+    /// Shoot a focus stack with the slider as a macro rail
+    Stack(StackArgs),
+    /// Brake whatever is playing to a stop and hold it there
+    Pause,
+    /// Pick up what was paused, on a schedule late by the pause
+    Resume,
+    /// Brake whatever is playing to a stop and abort it
+    Abort,
+    /// Print the log output of the dolly
+    Logs {
+        /// Stop after this many lines
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+    },
+    /// Run one command per line from a file over the same connection
+    Script { file: PathBuf },
+    /// Print the log output of the dolly for a while
+    Wait { ms: u64 },
+    /// Show live telemetry
+    Telemetry {
+        #[arg(long, default_value_t = 500)]
+        period_ms: u16,
+
+        /// Stop after this many samples
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AimCommand {
+    /// Remember where the camera looks now, a second sighting from elsewhere on the rail locates the subject
+    Sighting {
+        /// Follow the subject with tilt too
+        #[arg(long)]
+        tilt: bool,
+    },
+    /// The subject is this many millimetres along the current line of sight
+    Distance {
+        mm: String,
+
+        /// Follow the subject with tilt too
+        #[arg(long)]
+        tilt: bool,
+    },
+    /// Stop tracking
+    Off,
+}
+
+#[derive(Args)]
+struct PanoramaArgs {
+    /// Field of view of one frame in degrees, as WIDTHxHEIGHT
+    #[arg(long, required_unless_present = "focal", conflicts_with = "focal")]
+    fov: Option<String>,
+
+    /// Focal length of the lens in millimetres
+    #[arg(long)]
+    focal: Option<String>,
+
+    /// Sensor size in millimetres, as WIDTHxHEIGHT
+    #[arg(long, default_value = "36x24")]
+    sensor: String,
+
+    /// Overlap of neighbouring frames in percent
+    #[arg(long, default_value_t = 30)]
+    overlap: u8,
+
+    /// Pan range to cover in degrees, as FROM:TO, 360 degrees go all the way round
+    #[arg(long, default_value = "-180:180", allow_hyphen_values = true)]
+    pan: String,
+
+    /// Tilt range to cover in degrees, as FROM:TO
+    #[arg(long, default_value = "-30:30", allow_hyphen_values = true)]
+    tilt: String,
+
+    /// Rest after each move before the shutter
+    #[arg(long, default_value_t = 500)]
+    settle_ms: u16,
+
+    /// How long to hold the shutter
+    #[arg(long, default_value_t = 100)]
+    exposure_ms: u16,
+}
+
+#[derive(Args)]
+struct StackArgs {
+    /// Slider position of the first shot in millimetres
+    #[arg(long)]
+    near: String,
+
+    /// Slider position of the last shot in millimetres
+    #[arg(long)]
+    far: String,
+
+    /// Micrometres between shots
+    #[arg(long, required_unless_present = "shots", conflicts_with = "shots")]
+    step_um: Option<u32>,
+
+    /// Number of shots spread evenly from near to far
+    #[arg(long)]
+    shots: Option<u16>,
+
+    /// Micrometres to overshoot behind the near limit before the first shot
+    #[arg(long, default_value_t = 200)]
+    backlash_um: u16,
+
+    /// Rest after each move before the shutter
+    #[arg(long, default_value_t = 1000)]
+    settle_ms: u16,
+
+    /// How long to hold the shutter
+    #[arg(long, default_value_t = 100)]
+    exposure_ms: u16,
+
+    /// Go back to the near limit after the last shot
+    #[arg(long = "return")]
+    return_to_start: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FrameStep {
+    /// One frame back without shooting
+    Back,
+    /// One frame on without shooting
+    Forward,
+    /// One frame on, then the shutter
+    Shoot,
+}
+
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct ScriptLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Setting {
+    /// 0 off, 1 error, 2 warn, 3 info, 4 debug, 5 trace
+    LogLevel,
+    /// 0 drop output when the buffer is full, 1 wait for room
+    TxFullPolicy,
+    /// Milliseconds between telemetry samples, 0 off
+    TelemetryPeriod,
+    /// 0 text lines, 1 binary frames
+    TelemetryFormat,
+    /// 0 once, 1 return to start, 2 ping-pong, 3 loop
+    PlaybackMode,
+    /// Cycles to play in ping-pong and loop, 0 until aborted
+    PlaybackRepeat,
+    /// Milliseconds to rest at the first keyframe between cycles
+    DwellStart,
+    /// Milliseconds to rest at the last keyframe before turning back
+    DwellEnd,
+}
+
+impl From<Setting> for SettingId {
+    fn from(setting: Setting) -> Self {
+        match setting {
+            Setting::LogLevel => SettingId::LogLevel,
+            Setting::TxFullPolicy => SettingId::TxFullPolicy,
+            Setting::TelemetryPeriod => SettingId::TelemetryPeriod,
+            Setting::TelemetryFormat => SettingId::TelemetryFormat,
+            Setting::PlaybackMode => SettingId::PlaybackMode,
+            Setting::PlaybackRepeat => SettingId::PlaybackRepeat,
+            Setting::DwellStart => SettingId::DwellStart,
+            Setting::DwellEnd => SettingId::DwellEnd,
+        }
+    }
+}
+
+const POLL: Duration = Duration::from_millis(100);
+// The dolly answers once every axis has found its end, a whole rail at homing speed
+const HOMING: Duration = Duration::from_secs(180);
+
+fn upload(client: &mut Client, file: &PathBuf) -> Result<()> {
+    let text = fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+    let keyframes = sequence_file::parse(&text)?;
+
+    client.command(Message::ClearKeyframes)?;
+    for (index, keyframe) in keyframes.iter().enumerate() {
+        let index = u8::try_from(index).context("too many keyframes")?;
+        client
+            .command(Message::UploadKeyframe {
+                index,
+                keyframe: *keyframe,
+            })
+            .with_context(|| format!("keyframe {index}"))?;
+    }
+
+    println!("Uploaded {} keyframes", keyframes.len());
+    Ok(())
+}
+
+fn download(client: &mut Client, file: &PathBuf) -> Result<()> {
+    let mut keyframes = Vec::new();
+    for index in 0..=u8::MAX {
+        match client.request(Message::ReadKeyframe { index })? {
+            Message::Keyframe { keyframe, .. } => keyframes.push(keyframe),
+            Message::Nack(ErrorCode::OutOfRange) => break,
+            other => bail!("unexpected reply: {other:?}"),
+        }
+    }
+
+    fs::write(file, sequence_file::format(&keyframes))
+        .with_context(|| format!("writing {}", file.display()))?;
+    println!("Downloaded {} keyframes", keyframes.len());
+    Ok(())
+}
+
+fn panorama(client: &mut Client, args: PanoramaArgs) -> Result<()> {
+    let positive = |v: i32| u32::try_from(v).context("sizes can't be negative");
+    let lens = match (&args.fov, &args.focal) {
+        (Some(fov), _) => {
+            let (horizontal, vertical) = parse_milli_pair(fov, 'x')?;
+            Lens::Fov {
+                horizontal: positive(horizontal)?,
+                vertical: positive(vertical)?,
+            }
+        }
+        (None, Some(focal)) => {
+            let (width, height) = parse_milli_pair(&args.sensor, 'x')?;
+            Lens::Optics {
+                focal_um: positive(parse_milli(focal)?)?,
+                sensor_width_um: positive(width)?,
+                sensor_height_um: positive(height)?,
+            }
+        }
+        (None, None) => bail!("either --fov or --focal is needed"),
+    };
+    let (pan_from, pan_to) = parse_milli_pair(&args.pan, ':')?;
+    let (tilt_from, tilt_to) = parse_milli_pair(&args.tilt, ':')?;
+
+    client.command(Message::StartPanorama(Panorama {
+        lens,
+        overlap_pct: args.overlap,
+        pan_from,
+        pan_to,
+        tilt_from,
+        tilt_to,
+        settle_ms: args.settle_ms,
+        exposure_ms: args.exposure_ms,
+    }))
+}
+
+fn stack(client: &mut Client, args: StackArgs) -> Result<()> {
+    let spacing = match (args.step_um, args.shots) {
+        (Some(step_um), _) => Spacing::Step { step_um },
+        (None, Some(shots)) => Spacing::Shots { shots },
+        (None, None) => bail!("either --step-um or --shots is needed"),
+    };
+
+    client.command(Message::StartFocusStack(FocusStack {
+        near_um: parse_milli(&args.near)?,
+        far_um: parse_milli(&args.far)?,
+        spacing,
+        backlash_um: args.backlash_um,
+        settle_ms: args.settle_ms,
+        exposure_ms: args.exposure_ms,
+        return_to_start: args.return_to_start,
+    }))
+}
+
+fn logs(client: &mut Client, count: Option<usize>) -> Result<()> {
+    let mut printed = 0;
+    while count.is_none_or(|count| printed < count) {
+        if let Some(Incoming::Line(line)) = client.next(POLL)? {
+            println!("{line}");
+            printed += 1;
+        }
+    }
+    Ok(())
+}
+
+fn wait(client: &mut Client, ms: u64) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_millis(ms);
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if let Some(Incoming::Line(line)) = client.next(remaining.min(POLL))? {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+fn telemetry(client: &mut Client, period_ms: u16, count: Option<usize>) -> Result<()> {
+    client.command(Message::StreamTelemetry { period_ms })?;
+
+    let mut samples = 0;
+    while count.is_none_or(|count| samples < count) {
+        let t = match client.next(POLL)? {
+            Some(Incoming::Packet(packet)) => match packet.message {
+                Message::Telemetry(t) => t,
+                _ => continue,
+            },
+            Some(Incoming::Line(line)) => {
+                println!("\r{line}");
+                continue;
+            }
+            None => continue,
+        };
+
+        let [slider, pan, tilt] = t.position.map(format_milli);
+        let [v_slider, v_pan, v_tilt] = t.velocity.map(format_milli);
+        print!(
+            "\r{:>10.1}s {:<10}  slider {slider:>9}mm ({v_slider:>8}/s)  \
+             pan {pan:>8}deg ({v_pan:>8}/s)  tilt {tilt:>8}deg ({v_tilt:>8}/s)  frame {:>5}  {:>5}s left  {:>5.2}V  {:>5}Hz  \
+             overruns {}  late {}ms",
+            t.uptime_ms as f32 / 1000.0,
+            t.state.as_str(),
+            t.frame,
+            t.remaining_s,
+            t.battery_mv as f32 / 1000.0,
+            t.loop_hz,
+            t.overruns,
+            t.max_lateness_ms
+        );
+        io::stdout().flush()?;
+        samples += 1;
+    }
+    println!();
+
+    client.command(Message::StreamTelemetry { period_ms: 0 })
+}
+
+fn script(client: &mut Client, file: &PathBuf) -> Result<()> {
+    let text = fs::read_to_string(file).with_context(|| format!("reading {}", file.display()))?;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = ScriptLine::try_parse_from(line.split_whitespace())
+            .with_context(|| format!("line {}", number + 1))?;
+        run(client, parsed.command).with_context(|| format!("line {}: {line}", number + 1))?;
+    }
+
+    Ok(())
+}
+
+fn run(client: &mut Client, command: Command) -> Result<()> {
+    match command {
+        Command::Ping => {
+            client.command(Message::Ping)?;
+            println!("pong");
+        }
+        Command::Ack => client.command(Message::AcknowledgeReset)?,
+        Command::Home { skip: true } => client.command(Message::SkipHoming)?,
+        Command::Home { skip: false } => client.command_within(Message::Home, HOMING)?,
+        Command::Upload { file } => upload(client, &file)?,
+        Command::Download { file } => download(client, &file)?,
+        Command::Get { setting } => {
+            match client.request(Message::ReadSetting { id: setting.into() })? {
+                Message::Setting { value, .. } => println!("{value}"),
+                other => bail!("unexpected reply: {other:?}"),
+            }
+        }
+        Command::Set { setting, value } => client.command(Message::WriteSetting {
+            id: setting.into(),
+            value,
+        })?,
+        Command::Start => client.command(Message::StartSequence)?,
+        Command::Video { seconds, preroll } => {
+            let duration_ms = u32::try_from(parse_milli(&seconds)?)
+                .with_context(|| format!("{seconds}s is not a duration"))?;
+            client.command(Message::StartVideo {
+                duration_ms,
+                preroll_s: preroll,
+            })?
+        }
+        Command::Panorama(args) => panorama(client, args)?,
+        Command::Track { aim } => {
+            let (aim, follow_tilt) = match aim {
+                AimCommand::Sighting { tilt } => (Aim::Sighting, tilt),
+                AimCommand::Distance { mm, tilt } => {
+                    let distance_um = u32::try_from(parse_milli(&mm)?)
+                        .with_context(|| format!("{mm}mm is not a distance"))?;
+                    (Aim::Distance { distance_um }, tilt)
+                }
+                AimCommand::Off => (Aim::Off, false),
+            };
+            client.command(Message::AimTarget { aim, follow_tilt })?
+        }
+        Command::Animate {
+            steps,
+            settle_ms,
+            exposure_ms,
+            restart,
+        } => client.command(Message::StartAnimation(StopMotion {
+            steps,
+            settle_ms,
+            exposure_ms,
+            resume: !restart,
+        }))?,
+        Command::Frame { step } => client.command(Message::StepFrame(match step {
+            FrameStep::Back => Step::Back,
+            FrameStep::Forward => Step::Forward,
+            FrameStep::Shoot => Step::Shoot,
+        }))?,
+        Command::Stack(args) => stack(client, args)?,
+        Command::Pause => client.command(Message::PauseSequence)?,
+        Command::Resume => client.command(Message::ResumeSequence)?,
+        Command::Abort => client.command(Message::StopSequence)?,
+        Command::Logs { count } => logs(client, count)?,
+        Command::Script { file } => script(client, &file)?,
+        Command::Wait { ms } => wait(client, ms)?,
+        Command::Telemetry { period_ms, count } => telemetry(client, period_ms, count)?,
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let transport: Box<dyn Transport> = match &cli.port {
+        Some(port) => Box::new(Serial::open(port, cli.baud)?),
+        None => Box::new(Loopback::new()),
+    };
+    let mut client = Client::new(transport);
+
+    run(&mut client, cli.command)
+}
@@ -0,0 +1,54 @@
+// One keyframe per line: `time_ms slider pan tilt`, the slider in millimetres
+// and pan and tilt in degrees, up to three decimals each. Blank lines and
+// lines starting with # are ignored.
+
+use anyhow::{bail, Context, Result};
+use dolly_protocol::Keyframe;
+
+use crate::units::{format_milli, parse_milli};
+
+pub const HEADER: &str = "# time_ms slider_mm pan_deg tilt_deg";
+
+pub fn parse(text: &str) -> Result<Vec<Keyframe>> {
+    let mut keyframes = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [time_ms, slider, pan, tilt] = fields.as_slice() else {
+            bail!(
+                "line {}: expected 4 fields, found {}",
+                number + 1,
+                fields.len()
+            );
+        };
+
+        let context = || format!("line {}", number + 1);
+        keyframes.push(Keyframe {
+            time_ms: time_ms.parse().with_context(context)?,
+            slider: parse_milli(slider).with_context(context)?,
+            pan: parse_milli(pan).with_context(context)?,
+            tilt: parse_milli(tilt).with_context(context)?,
+        });
+    }
+
+    Ok(keyframes)
+}
+
+pub fn format(keyframes: &[Keyframe]) -> String {
+    let mut text = format!("{HEADER}\n");
+    for k in keyframes {
+        text += &format!(
+            "{} {} {} {}\n",
+            k.time_ms,
+            format_milli(k.slider),
+            format_milli(k.pan),
+            format_milli(k.tilt)
+        );
+    }
+    text
+}
@@ -0,0 +1,801 @@
+use std::{convert::Infallible, fmt::Write};
+
+use dolly_protocol::{
+    machine::{Hardware, Machine, Note, Sink},
+    plan::AXES,
+    profile::{Move, Path, UPDATE_MS},
+    rig::{self, LIMITS},
+    shell::{Command, Format, Level},
+    stopmotion::RECORD_LEN,
+    tracking::Target,
+    units::Unit,
+    Demux, ErrorCode, Event, Limits, Message, Mode, Packet, Replay, SettingId, Telemetry,
+    MAX_FRAME_LEN,
+};
+
+use crate::units::format_milli;
+
+const BATTERY_MV: u16 = 12_100;
+
+// A note as the dolly prints it
+struct Line(String);
+
+impl Sink for Line {
+    fn text(&mut self, text: &str) {
+        self.0.push_str(text);
+    }
+
+    fn number(&mut self, n: u32) {
+        let _ = write!(self.0, "{n}");
+    }
+
+    fn quantity(&mut self, value: i32, unit: Unit) {
+        let _ = write!(self.0, "{}{}", format_milli(value), unit.symbol());
+    }
+}
+
+// The dolly's axes, shutter and EEPROM as the machine sees them. Every
+// update the axes get to where the move will be by the next one, as the
+// executor's step channels do when they keep up.
+struct SimRig {
+    position: [i32; AXES],
+    motion: Option<Move>,
+    shutter: bool,
+    eeprom: [u8; RECORD_LEN],
+    // what the machine had to say, logged by the dolly once it's done
+    lines: Vec<(Level, String)>,
+}
+
+impl SimRig {
+    fn new() -> Self {
+        Self {
+            position: [0; AXES],
+            motion: None,
+            shutter: false,
+            eeprom: [0xFF; RECORD_LEN],
+            lines: Vec::new(),
+        }
+    }
+}
+
+impl Hardware for SimRig {
+    // the simulated rail has nothing to run into
+    type HomingError = Infallible;
+
+    fn positions(&self) -> [i32; AXES] {
+        self.position
+    }
+
+    fn limits(&self) -> [Limits; AXES] {
+        LIMITS
+    }
+
+    fn slider_step_um(&self) -> i32 {
+        rig::SLIDER.to_units(1)
+    }
+
+    fn start(&mut self, to: &[i32; AXES], duration_ms: u32, started: u32, track: Option<Target>) {
+        let path = Path::new(&LIMITS, &self.position, to, duration_ms, track);
+        self.motion = Some(Move::new(path, duration_ms, started));
+    }
+
+    fn update(&mut self, now: u32) -> bool {
+        let Some(m) = &mut self.motion else {
+            return false;
+        };
+
+        let next = m.positions(now.wrapping_add(UPDATE_MS));
+        let idle = next == self.position;
+        self.position = next;
+        match m.settle(now, idle) {
+            Some(arrived) => {
+                self.motion = None;
+                arrived
+            }
+            None => false,
+        }
+    }
+
+    fn pause(&mut self, now: u32) {
+        if let Some(m) = &mut self.motion {
+            m.pause(&LIMITS, now);
+        }
+    }
+
+    fn resume(&mut self, now: u32) {
+        if let Some(m) = &mut self.motion {
+            m.resume(&LIMITS, now);
+        }
+    }
+
+    fn abort(&mut self, now: u32) {
+        if let Some(m) = &mut self.motion {
+            m.abort(&LIMITS, now);
+        }
+    }
+
+    // there is no joystick to jog with
+    fn halt(&mut self) {}
+
+    fn is_running(&self) -> bool {
+        self.motion.is_some()
+    }
+
+    fn is_jogging(&self) -> bool {
+        false
+    }
+
+    fn is_easing(&self, now: u32) -> bool {
+        self.motion.as_ref().is_some_and(|m| m.is_easing(now))
+    }
+
+    fn remaining_ms(&self, now: u32) -> u32 {
+        self.motion.as_ref().map_or(0, |m| m.remaining_ms(now))
+    }
+
+    fn shutter(&mut self, pressed: bool) {
+        self.shutter = pressed;
+    }
+
+    fn leds(&mut self, _in_on: bool, _out_on: bool) {}
+
+    fn load(&self) -> [u8; RECORD_LEN] {
+        self.eeprom
+    }
+
+    fn store(&mut self, record: &[u8; RECORD_LEN]) {
+        self.eeprom = *record;
+    }
+
+    fn home(&mut self) -> Result<(), Infallible> {
+        self.lines.push((Level::Info, "Homed slider".into()));
+        Ok(())
+    }
+
+    fn note(&mut self, note: Note) {
+        let mut line = Line(String::new());
+        note.write(&mut line);
+        self.lines.push((note.level(), line.0));
+    }
+}
+
+// Firmware stand-in for the loopback transport. It speaks the real protocol
+// and shell, and runs the dolly's own state machine out of dolly-protocol on
+// a virtual clock, which only moves when `tick` is called.
+pub struct SimulatedDolly {
+    demux: Demux,
+    line: Vec<u8>,
+    tx: Vec<u8>,
+    // milliseconds since boot
+    now: u32,
+    next_update: u32,
+    seq: u8,
+    replay: Replay,
+    log_level: Level,
+    tx_full_policy: i32,
+    // period and when the next sample is due
+    telemetry: Option<(u32, u32)>,
+    telemetry_format: Format,
+    last_sample: Option<(u32, [i32; AXES])>,
+    machine: Machine,
+    rig: SimRig,
+}
+
+impl SimulatedDolly {
+    pub fn new() -> Self {
+        let mut dolly = Self {
+            demux: Demux::new(),
+            line: Vec::new(),
+            tx: Vec::new(),
+            now: 0,
+            next_update: 0,
+            seq: 0,
+            replay: Replay::new(),
+            log_level: Level::Info,
+            tx_full_policy: 0,
+            telemetry: None,
+            telemetry_format: Format::Line,
+            last_sample: None,
+            machine: Machine::new(false),
+            rig: SimRig::new(),
+        };
+        dolly.log(Level::Info, "Started ...");
+        // as after a failed boot homing
+        dolly.log(
+            Level::Error,
+            "Not homed, motion blocked until `home` or `home skip`",
+        );
+        dolly
+    }
+
+    fn log(&mut self, level: Level, text: &str) {
+        if level > self.log_level {
+            return;
+        }
+        let line = format!("[{} {}] {}\n", self.now, level.tag(), text);
+        self.tx.extend_from_slice(line.as_bytes());
+    }
+
+    // Logs what the machine said since the last time
+    fn flush(&mut self) {
+        for (level, line) in std::mem::take(&mut self.rig.lines) {
+            self.log(level, &line);
+        }
+    }
+
+    fn send(&mut self, seq: u8, message: Message) {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        if let Ok(n) = Packet::new(seq, message).encode(&mut frame) {
+            self.tx.extend_from_slice(&frame[..n]);
+        }
+    }
+
+    pub fn receive(&mut self, byte: u8) {
+        match self.demux.push(byte) {
+            Some(Event::Text(b'\r' | b'\n')) => {
+                let line = String::from_utf8_lossy(&std::mem::take(&mut self.line)).into_owned();
+                if !line.trim().is_empty() {
+                    self.handle_command(Command::parse(&line));
+                }
+            }
+            Some(Event::Text(byte)) => self.line.push(byte),
+            Some(Event::Packet(packet)) => match self.replay.get(&packet) {
+                Some(reply) => self.send(packet.seq, reply),
+                None => self.handle_packet(packet),
+            },
+            Some(Event::Error(e)) => {
+                self.log(Level::Warn, &format!("Frame dropped: {}", e.as_str()))
+            }
+            None => {}
+        }
+        self.flush();
+    }
+
+    // Moves the clock on by `ms`, with every motion update and telemetry
+    // sample due on the way, as the dolly's scheduler runs them
+    pub fn tick(&mut self, ms: u32) {
+        let end = self.now + ms;
+        while self.next_update <= end {
+            self.now = self.next_update;
+            self.machine.update(&mut self.rig, self.now);
+            self.flush();
+            self.next_update += UPDATE_MS;
+
+            if let Some((period, due)) = self.telemetry {
+                if self.now >= due {
+                    self.telemetry = Some((period, due + period));
+                    self.send_telemetry();
+                }
+            }
+        }
+        self.now = end;
+    }
+
+    // Everything the dolly transmitted since the last call
+    pub fn output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.tx)
+    }
+
+    fn send_telemetry(&mut self) {
+        let uptime_ms = self.now;
+        let position = self.rig.position;
+        let mut velocity = [0; AXES];
+        if let Some((last_ms, last)) = self.last_sample {
+            let dt = uptime_ms.saturating_sub(last_ms).max(1) as i64;
+            for (v, (pos, last)) in velocity.iter_mut().zip(position.iter().zip(last)) {
+                *v = ((*pos as i64 - last as i64) * 1000 / dt) as i32;
+            }
+        }
+        self.last_sample = Some((uptime_ms, position));
+
+        let t = Telemetry {
+            uptime_ms,
+            state: self.machine.state(),
+            position,
+            velocity,
+            frame: self.machine.frame(),
+            remaining_s: self.machine.remaining_ms(&self.rig, self.now) / 1000,
+            battery_mv: BATTERY_MV,
+            loop_hz: 0,
+            overruns: 0,
+            max_lateness_ms: 0,
+        };
+
+        match self.telemetry_format {
+            Format::Line => {
+                let [slider, pan, tilt] = t.position;
+                let [v_slider, v_pan, v_tilt] = t.velocity;
+                let line = format!(
+                    "TLM {} {} {slider} {pan} {tilt} {v_slider} {v_pan} {v_tilt} {} {} {} {} {} {}\n",
+                    t.uptime_ms,
+                    t.state as u8,
+                    t.frame,
+                    t.remaining_s,
+                    t.battery_mv,
+                    t.loop_hz,
+                    t.overruns,
+                    t.max_lateness_ms
+                );
+                self.tx.extend_from_slice(line.as_bytes());
+            }
+            Format::Binary => {
+                self.seq = self.seq.wrapping_add(1);
+                self.send(self.seq, Message::Telemetry(t));
+            }
+        }
+    }
+
+    fn set_telemetry_period(&mut self, period_ms: u16) {
+        let period_ms = period_ms as u32;
+        self.telemetry = (period_ms > 0).then_some((period_ms, self.now + period_ms));
+        self.last_sample = None;
+    }
+
+    fn telemetry_period_ms(&self) -> u16 {
+        self.telemetry.map_or(0, |(period, _)| period as u16)
+    }
+
+    fn show_telemetry(&mut self) {
+        let line = format!(
+            "Telemetry: {}ms {}",
+            self.telemetry_period_ms(),
+            self.telemetry_format.as_str()
+        );
+        self.log(Level::Info, &line);
+    }
+
+    fn handle_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::ShowLogLevel => {
+                let line = format!("Log level: {}", self.log_level.tag());
+                self.log(Level::Info, &line);
+            }
+            Command::SetLogLevel(level) => {
+                self.log_level = level;
+                self.log(Level::Info, &format!("Log level: {}", level.tag()));
+            }
+            Command::ShowCrash => self.log(Level::Info, "No crash recorded"),
+            Command::ClearCrash => self.log(Level::Info, "Crash report cleared"),
+            Command::Acknowledge => {
+                self.machine.acknowledge(&mut self.rig);
+            }
+            Command::ShowPosition => {
+                let [slider, pan, tilt] = self.rig.position;
+                let line = format!(
+                    "Position: slider {}mm pan {}deg tilt {}deg",
+                    format_milli(slider),
+                    format_milli(pan),
+                    format_milli(tilt)
+                );
+                self.log(Level::Info, &line);
+            }
+            Command::ShowTelemetry => self.show_telemetry(),
+            Command::SetTelemetry { period_ms, format } => {
+                if let Some(format) = format {
+                    self.telemetry_format = format;
+                }
+                self.set_telemetry_period(period_ms);
+                self.show_telemetry();
+            }
+            // runs, playback and homing
+            cmd => {
+                if !self.machine.command(&mut self.rig, &cmd, self.now) {
+                    self.log(Level::Warn, "Unknown command");
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: Packet) {
+        let reply = match packet.message {
+            Message::Ping => Message::Ack,
+            Message::AcknowledgeReset => {
+                self.machine.acknowledge(&mut self.rig);
+                Message::Ack
+            }
+            Message::ReadSetting { id } => Message::Setting {
+                id,
+                value: self.read_setting(id),
+            },
+            Message::WriteSetting { id, value } => match self.write_setting(id, value) {
+                Ok(()) => Message::Ack,
+                Err(e) => Message::Nack(e),
+            },
+            Message::StreamTelemetry { period_ms } => {
+                self.telemetry_format = Format::Binary;
+                self.set_telemetry_period(period_ms);
+                Message::Ack
+            }
+            message => self
+                .machine
+                .handle(&mut self.rig, &message, self.now)
+                .unwrap_or(Message::Nack(ErrorCode::UnexpectedMessage)),
+        };
+
+        self.replay.record(&packet, reply);
+        self.send(packet.seq, reply);
+    }
+
+    fn read_setting(&self, id: SettingId) -> i32 {
+        let playback = &self.machine.playback;
+        match id {
+            SettingId::LogLevel => self.log_level as i32,
+            SettingId::TxFullPolicy => self.tx_full_policy,
+            SettingId::TelemetryPeriod => self.telemetry_period_ms() as i32,
+            SettingId::TelemetryFormat => self.telemetry_format as i32,
+            SettingId::PlaybackMode => playback.mode as i32,
+            SettingId::PlaybackRepeat => playback.repeat as i32,
+            SettingId::DwellStart => playback.dwell_start_ms as i32,
+            SettingId::DwellEnd => playback.dwell_end_ms as i32,
+        }
+    }
+
+    fn write_setting(&mut self, id: SettingId, value: i32) -> Result<(), ErrorCode> {
+        let playback = &mut self.machine.playback;
+        match id {
+            SettingId::LogLevel => {
+                self.log_level = u8::try_from(value)
+                    .ok()
+                    .and_then(Level::from_u8)
+                    .ok_or(ErrorCode::InvalidValue)?;
+            }
+            SettingId::TxFullPolicy => match value {
+                0 | 1 => self.tx_full_policy = value,
+                _ => return Err(ErrorCode::InvalidValue),
+            },
+            SettingId::TelemetryPeriod => {
+                let period_ms = u16::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+                self.set_telemetry_period(period_ms);
+            }
+            SettingId::TelemetryFormat => {
+                self.telemetry_format = u8::try_from(value)
+                    .ok()
+                    .and_then(Format::from_u8)
+                    .ok_or(ErrorCode::InvalidValue)?;
+            }
+            // take effect when the running pass ends
+            SettingId::PlaybackMode => {
+                playback.mode = u8::try_from(value)
+                    .ok()
+                    .and_then(Mode::from_u8)
+                    .ok_or(ErrorCode::InvalidValue)?;
+            }
+            SettingId::PlaybackRepeat => {
+                playback.repeat = u16::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+            }
+            SettingId::DwellStart => {
+                playback.dwell_start_ms =
+                    u32::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+            }
+            SettingId::DwellEnd => {
+                playback.dwell_end_ms =
+                    u32::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use dolly_protocol::{
+        units::{deg, mm},
+        FocusStack, Keyframe, Spacing, State,
+    };
+
+    use super::*;
+    use crate::{
+        client::{Client, Incoming},
+        transport::Loopback,
+    };
+
+    type Shared = Rc<RefCell<SimulatedDolly>>;
+
+    // The dolly's clock only moves when the test ticks it
+    fn connect() -> (Client, Shared) {
+        let dolly = Rc::new(RefCell::new(SimulatedDolly::new()));
+        let client = Client::new(Box::new(Loopback::manual(dolly.clone())));
+        (client, dolly)
+    }
+
+    fn tick(dolly: &Shared, ms: u32) {
+        dolly.borrow_mut().tick(ms);
+    }
+
+    fn state(dolly: &Shared) -> State {
+        dolly.borrow().machine.state()
+    }
+
+    // Ticks one update at a time until the dolly gets there
+    fn tick_until(dolly: &Shared, mut done: impl FnMut(&SimulatedDolly) -> bool) {
+        for _ in 0..10_000 {
+            if done(&dolly.borrow()) {
+                return;
+            }
+            tick(dolly, UPDATE_MS);
+        }
+        panic!("stuck in {:?}", state(dolly));
+    }
+
+    // Pauses what is playing, lets it stand for a while and picks it up again
+    fn pause_and_resume(client: &mut Client, dolly: &Shared) {
+        client.command(Message::PauseSequence).unwrap();
+        tick_until(dolly, |d| d.machine.state() == State::Paused);
+        let remaining_ms = |d: &SimulatedDolly| d.machine.remaining_ms(&d.rig, d.now);
+        let held = dolly.borrow().rig.position;
+        let left_ms = remaining_ms(&dolly.borrow());
+        tick(dolly, 3_000);
+        assert_eq!(state(dolly), State::Paused);
+        assert_eq!(dolly.borrow().rig.position, held);
+        assert_eq!(remaining_ms(&dolly.borrow()), left_ms);
+        client.command(Message::ResumeSequence).unwrap();
+    }
+
+    fn upload(client: &mut Client, keyframes: &[Keyframe]) {
+        for (index, keyframe) in keyframes.iter().enumerate() {
+            let upload = Message::UploadKeyframe {
+                index: index as u8,
+                keyframe: *keyframe,
+            };
+            client.command(upload).unwrap();
+        }
+    }
+
+    // The latest telemetry the dolly streamed
+    fn telemetry(client: &mut Client) -> Telemetry {
+        let mut latest = None;
+        while let Some(incoming) = client.next(Duration::ZERO).unwrap() {
+            if let Incoming::Packet(Packet {
+                message: Message::Telemetry(t),
+                ..
+            }) = incoming
+            {
+                latest = Some(t);
+            }
+        }
+        latest.expect("no telemetry from the dolly")
+    }
+
+    const TAKE: [Keyframe; 2] = [
+        Keyframe {
+            time_ms: 0,
+            slider: 0,
+            pan: 0,
+            tilt: 0,
+        },
+        Keyframe {
+            time_ms: 5_000,
+            slider: mm(100),
+            pan: deg(20),
+            tilt: 0,
+        },
+    ];
+
+    #[test]
+    fn answers_a_ping() {
+        let (mut client, _) = connect();
+        assert_eq!(client.request(Message::Ping).unwrap(), Message::Ack);
+    }
+
+    #[test]
+    fn keyframes_round_trip() {
+        let (mut client, _) = connect();
+        upload(&mut client, &TAKE);
+
+        for (index, keyframe) in TAKE.iter().enumerate() {
+            let read = Message::ReadKeyframe { index: index as u8 };
+            let reply = Message::Keyframe {
+                index: index as u8,
+                keyframe: *keyframe,
+            };
+            assert_eq!(client.request(read).unwrap(), reply);
+        }
+        let past = Message::ReadKeyframe { index: 2 };
+        let refused = Message::Nack(ErrorCode::OutOfRange);
+        assert_eq!(client.request(past).unwrap(), refused);
+
+        client.command(Message::ClearKeyframes).unwrap();
+        let first = Message::ReadKeyframe { index: 0 };
+        assert_eq!(client.request(first).unwrap(), refused);
+    }
+
+    #[test]
+    fn settings_read_back() {
+        let (mut client, _) = connect();
+        let write = Message::WriteSetting {
+            id: SettingId::DwellStart,
+            value: 1_500,
+        };
+        client.command(write).unwrap();
+
+        let read = Message::ReadSetting {
+            id: SettingId::DwellStart,
+        };
+        let setting = Message::Setting {
+            id: SettingId::DwellStart,
+            value: 1_500,
+        };
+        assert_eq!(client.request(read).unwrap(), setting);
+
+        let invalid = Message::WriteSetting {
+            id: SettingId::PlaybackMode,
+            value: 99,
+        };
+        let refused = Message::Nack(ErrorCode::InvalidValue);
+        assert_eq!(client.request(invalid).unwrap(), refused);
+    }
+
+    #[test]
+    fn start_pause_resume_abort() {
+        let (mut client, dolly) = connect();
+        let not_ready = Message::Nack(ErrorCode::NotReady);
+        assert_eq!(client.request(Message::StartSequence).unwrap(), not_ready);
+
+        upload(&mut client, &TAKE);
+        // homing failed at boot until it is retried
+        assert_eq!(client.request(Message::StartSequence).unwrap(), not_ready);
+        client.command(Message::Home).unwrap();
+        client.command(Message::StartSequence).unwrap();
+        assert_eq!(state(&dolly), State::GotoInit);
+        let clear = client.request(Message::ClearKeyframes).unwrap();
+        assert_eq!(clear, not_ready);
+
+        // already at the first keyframe, the pass starts on the next update
+        tick(&dolly, 1_000);
+        assert_eq!(state(&dolly), State::Moving);
+        client.command(Message::PauseSequence).unwrap();
+        assert_eq!(state(&dolly), State::Pausing);
+        let pause = client.request(Message::PauseSequence).unwrap();
+        assert_eq!(pause, not_ready);
+
+        // brakes to a stop on the path before it counts as paused
+        tick(&dolly, 2_000);
+        assert_eq!(state(&dolly), State::Paused);
+        let held = dolly.borrow().rig.position;
+        tick(&dolly, 1_000);
+        assert_eq!(dolly.borrow().rig.position, held);
+
+        client.command(Message::ResumeSequence).unwrap();
+        assert_eq!(state(&dolly), State::Resuming);
+        tick(&dolly, 2_000);
+        assert_eq!(state(&dolly), State::Moving);
+
+        client.command(Message::StopSequence).unwrap();
+        assert_eq!(state(&dolly), State::Aborting);
+        tick(&dolly, 2_000);
+        assert_eq!(state(&dolly), State::Ready);
+        let stop = client.request(Message::StopSequence).unwrap();
+        assert_eq!(stop, not_ready);
+        client.command(Message::ClearKeyframes).unwrap();
+    }
+
+    #[test]
+    fn pauses_a_video_take_at_every_stage() {
+        let (mut client, dolly) = connect();
+        upload(&mut client, &TAKE);
+        client.command(Message::SkipHoming).unwrap();
+
+        // away from the first keyframe, so there is a way to go back
+        client.command(Message::StartSequence).unwrap();
+        tick(&dolly, 3_000);
+        client.command(Message::StopSequence).unwrap();
+        tick_until(&dolly, |d| d.machine.state() == State::Ready);
+
+        let video = Message::StartVideo {
+            duration_ms: 4_000,
+            preroll_s: 2,
+        };
+        client.command(video).unwrap();
+        tick(&dolly, 200);
+        assert_eq!(state(&dolly), State::GotoInit);
+        pause_and_resume(&mut client, &dolly);
+        assert_eq!(state(&dolly), State::Resuming);
+        tick_until(&dolly, |d| d.machine.state() == State::Preroll);
+
+        // a countdown stops without a ramp
+        tick(&dolly, 500);
+        pause_and_resume(&mut client, &dolly);
+        assert_eq!(state(&dolly), State::Preroll);
+        tick_until(&dolly, |d| d.machine.state() == State::Moving);
+
+        tick(&dolly, 1_000);
+        pause_and_resume(&mut client, &dolly);
+        tick_until(&dolly, |d| d.machine.state() == State::Ready);
+        assert_eq!(dolly.borrow().rig.position, [mm(100), deg(20), 0]);
+    }
+
+    #[test]
+    fn pauses_a_job_and_shoots_a_cut_exposure_again() {
+        let (mut client, dolly) = connect();
+        client.command(Message::SkipHoming).unwrap();
+
+        let stack = FocusStack {
+            near_um: mm(20),
+            far_um: mm(30),
+            spacing: Spacing::Shots { shots: 3 },
+            backlash_um: 500,
+            settle_ms: 200,
+            exposure_ms: 1_000,
+            return_to_start: false,
+        };
+        client.command(Message::StartFocusStack(stack)).unwrap();
+        tick(&dolly, 100);
+        assert_eq!(state(&dolly), State::FocusStack);
+        pause_and_resume(&mut client, &dolly);
+        tick_until(&dolly, |d| d.rig.shutter);
+
+        // let go while paused, held for a whole exposure again after
+        tick(&dolly, 500);
+        client.command(Message::PauseSequence).unwrap();
+        assert_eq!(state(&dolly), State::Paused);
+        assert!(!dolly.borrow().rig.shutter);
+        tick(&dolly, 3_000);
+        assert_eq!(dolly.borrow().machine.frame(), 0);
+        client.command(Message::ResumeSequence).unwrap();
+        assert!(dolly.borrow().rig.shutter);
+        tick(&dolly, 900);
+        assert!(dolly.borrow().rig.shutter);
+        tick_until(&dolly, |d| d.machine.frame() == 1);
+
+        tick_until(&dolly, |d| !d.machine.is_playing());
+        assert_eq!(dolly.borrow().rig.position[0], mm(30));
+    }
+
+    #[test]
+    fn answers_a_resend_without_starting_twice() {
+        let mut dolly = SimulatedDolly::new();
+        let mut exchange = |seq, message| {
+            let mut frame = [0u8; MAX_FRAME_LEN];
+            let n = Packet::new(seq, message).encode(&mut frame).unwrap();
+            frame[..n].iter().for_each(|&byte| dolly.receive(byte));
+
+            let mut demux = Demux::new();
+            let replies = dolly
+                .output()
+                .into_iter()
+                .filter_map(|byte| match demux.push(byte) {
+                    Some(Event::Packet(p)) => Some(p.message),
+                    _ => None,
+                });
+            replies.last().unwrap()
+        };
+
+        let keyframe = |index| Message::UploadKeyframe {
+            index,
+            keyframe: TAKE[index as usize],
+        };
+        exchange(1, keyframe(0));
+        exchange(2, keyframe(1));
+        exchange(3, Message::SkipHoming);
+        assert_eq!(exchange(4, Message::StartSequence), Message::Ack);
+        assert_eq!(exchange(4, Message::StartSequence), Message::Ack);
+        let again = exchange(5, Message::StartSequence);
+        assert_eq!(again, Message::Nack(ErrorCode::NotReady));
+    }
+
+    #[test]
+    fn streams_telemetry() {
+        let (mut client, dolly) = connect();
+        let stream = Message::StreamTelemetry { period_ms: 20 };
+        client.command(stream).unwrap();
+
+        tick(&dolly, 20);
+        let t = telemetry(&mut client);
+        assert_eq!(t.uptime_ms, 20);
+        // boots without a position to be ready at
+        assert_eq!(t.state, State::SetInitPos);
+        assert_eq!(t.position, [0; AXES]);
+        assert_eq!(t.battery_mv, BATTERY_MV);
+
+        upload(&mut client, &TAKE);
+        client.command(Message::SkipHoming).unwrap();
+        client.command(Message::StartSequence).unwrap();
+        tick(&dolly, 2_000);
+        let t = telemetry(&mut client);
+        assert_eq!(t.state, State::Moving);
+        assert!(t.position[0] > 0 && t.remaining_s < 5);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read, Write},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serialport::SerialPort;
+
+use crate::sim::SimulatedDolly;
+
+pub trait Transport {
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    // Returns whatever arrived within the timeout, possibly nothing
+    fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+pub struct Serial {
+    port: Box<dyn SerialPort>,
+}
+
+impl Serial {
+    pub fn open(path: &str, baud: u32) -> Result<Self> {
+        let port = serialport::new(path, baud)
+            .open()
+            .with_context(|| format!("opening {path}"))?;
+        Ok(Self { port })
+    }
+}
+
+impl Transport for Serial {
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.port.write_all(bytes)?;
+        self.port.flush()
+    }
+
+    fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        self.port.set_timeout(timeout)?;
+        match self.port.read(buf) {
+            Ok(n) => Ok(n),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Talks to an in-process simulation of the firmware instead of a board
+// Talks to a simulated dolly. Its clock follows the wall clock, or only the
+// ticks the caller gives it for a manual one.
+pub struct Loopback {
+    dolly: Rc<RefCell<SimulatedDolly>>,
+    // when the dolly's clock last caught up, and what was left over
+    clock: Option<(Instant, Duration)>,
+    rx: VecDeque<u8>,
+}
+
+impl Loopback {
+    const POLL: Duration = Duration::from_millis(5);
+
+    pub fn new() -> Self {
+        Self {
+            dolly: Rc::new(RefCell::new(SimulatedDolly::new())),
+            clock: Some((Instant::now(), Duration::ZERO)),
+            rx: VecDeque::new(),
+        }
+    }
+
+    // Never waits, the dolly moves on when the caller ticks it
+    #[cfg(test)]
+    pub fn manual(dolly: Rc<RefCell<SimulatedDolly>>) -> Self {
+        Self {
+            dolly,
+            clock: None,
+            rx: VecDeque::new(),
+        }
+    }
+
+    fn catch_up(&mut self) {
+        let Some((since, left)) = &mut self.clock else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed = now - *since + *left;
+        let ms = elapsed.as_millis() as u32;
+        *since = now;
+        *left = elapsed - Duration::from_millis(ms as u64);
+        self.dolly.borrow_mut().tick(ms);
+    }
+}
+
+impl Transport for Loopback {
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.catch_up();
+        let mut dolly = self.dolly.borrow_mut();
+        for byte in bytes {
+            dolly.receive(*byte);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.catch_up();
+            self.rx.extend(self.dolly.borrow_mut().output());
+            if !self.rx.is_empty() || self.clock.is_none() || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Self::POLL);
+        }
+
+        let n = buf.len().min(self.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(self.rx.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,138 @@
+// The dolly counts micrometres and millidegrees, people write millimetres and
+// degrees. Both conversions work on the decimal digits so nothing is lost to
+// floating point.
+
+use anyhow::{bail, Context, Result};
+use dolly_protocol::{
+    plan::{AXES, AXIS_NAMES},
+    Infeasible, Violation,
+};
+
+pub fn parse_milli(text: &str) -> Result<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (whole, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && frac.is_empty() {
+        bail!("expected a number, found {text:?}");
+    }
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(whole) || !is_digits(frac) {
+        bail!("invalid number {text:?}");
+    }
+    if frac.len() > 3 {
+        bail!("{text:?} has more than three decimals");
+    }
+
+    let whole: i64 = match whole {
+        "" => 0,
+        w => w
+            .parse()
+            .with_context(|| format!("invalid number {text:?}"))?,
+    };
+    let frac: i64 = match frac {
+        "" => 0,
+        f => {
+            f.parse::<i64>()
+                .with_context(|| format!("invalid number {text:?}"))?
+                * 10_i64.pow(3 - f.len() as u32)
+        }
+    };
+
+    let value = whole * 1000 + frac;
+    let value = if negative { -value } else { value };
+    i32::try_from(value).with_context(|| format!("{text:?} is out of range"))
+}
+
+// Two numbers around `separator`, like 36x24 or -90:90
+pub fn parse_milli_pair(text: &str, separator: char) -> Result<(i32, i32)> {
+    let Some((a, b)) = text.split_once(separator) else {
+        bail!("expected two numbers separated by {separator:?}, found {text:?}");
+    };
+    Ok((parse_milli(a)?, parse_milli(b)?))
+}
+
+pub fn format_milli(value: i32) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    format!("{sign}{}.{:03}", abs / 1000, abs % 1000)
+}
+
+// in plan::AXIS_NAMES order
+const AXIS_UNITS: [&str; AXES] = ["mm", "deg", "deg"];
+
+pub fn describe_infeasible(e: &Infeasible) -> String {
+    let axis = e.axis as usize;
+    let name = AXIS_NAMES.get(axis).unwrap_or(&"?");
+    let unit = AXIS_UNITS.get(axis).unwrap_or(&"");
+
+    match e.violation {
+        Violation::Travel { position, limit } => format!(
+            "segment {}: {name} at {}{unit} is beyond its limit of {}{unit}",
+            e.segment,
+            format_milli(position),
+            format_milli(limit)
+        ),
+        Violation::Duration {
+            available_ms,
+            needed_ms,
+        } => format!(
+            "segment {}: {name} needs {needed_ms} ms but has {available_ms} ms, {} ms short",
+            e.segment,
+            needed_ms - available_ms
+        ),
+        Violation::Velocity { peak, limit } => format!(
+            "segment {}: tracking turns {name} at {}{unit}/s, beyond its limit of {}{unit}/s",
+            e.segment,
+            format_milli(peak as i32),
+            format_milli(limit as i32)
+        ),
+        Violation::Acceleration { peak, limit } => format!(
+            "segment {}: tracking speeds {name} up at {}{unit}/s², beyond its limit of {}{unit}/s²",
+            e.segment,
+            format_milli(peak as i32),
+            format_milli(limit as i32)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals() {
+        assert_eq!(parse_milli("12").unwrap(), 12_000);
+        assert_eq!(parse_milli("-1.5").unwrap(), -1500);
+        assert_eq!(parse_milli(".25").unwrap(), 250);
+        assert_eq!(parse_milli("3.").unwrap(), 3000);
+        assert_eq!(parse_milli("-0.001").unwrap(), -1);
+        assert_eq!(
+            parse_milli_pair("-90:45.5", ':').unwrap(),
+            (-90_000, 45_500)
+        );
+    }
+
+    #[test]
+    fn rejects_anything_but_digits() {
+        for text in [
+            "", "-", ".", "-.", "1.-5", "--1", "+1", "1.+5", " 1", "1e3", "1.2.3", "1.2345",
+        ] {
+            assert!(parse_milli(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert!(parse_milli("2147484").is_err());
+        assert!(parse_milli("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        for value in [0, 1, -1, 999, -1000, 123_456, i32::MIN, i32::MAX] {
+            assert_eq!(parse_milli(&format_milli(value)).unwrap(), value);
+        }
+    }
+}
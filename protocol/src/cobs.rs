@@ -0,0 +1,150 @@
+// Consistent Overhead Byte Stuffing, removes every 0x00 so it can delimit frames
+
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+pub fn encode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut code_index = 0;
+    let mut out = 1;
+    let mut code: u8 = 1;
+
+    for byte in src {
+        if *byte == 0 {
+            *dst.get_mut(code_index)? = code;
+            code_index = out;
+            out += 1;
+            code = 1;
+            continue;
+        }
+
+        *dst.get_mut(out)? = *byte;
+        out += 1;
+        code += 1;
+
+        if code == 0xFF {
+            *dst.get_mut(code_index)? = code;
+            code_index = out;
+            out += 1;
+            code = 1;
+        }
+    }
+
+    *dst.get_mut(code_index)? = code;
+    Some(out)
+}
+
+pub fn decode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut out = 0;
+
+    while i < src.len() {
+        let code = src[i];
+        if code == 0 {
+            return None;
+        }
+        i += 1;
+
+        for _ in 1..code {
+            *dst.get_mut(out)? = *src.get(i)?;
+            out += 1;
+            i += 1;
+        }
+
+        if code != 0xFF && i < src.len() {
+            *dst.get_mut(out)? = 0;
+            out += 1;
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(src: &[u8]) {
+        let mut encoded = [0u8; 600];
+        let n = encode(src, &mut encoded).unwrap();
+        assert!(n <= max_encoded_len(src.len()));
+        assert!(!encoded[..n].contains(&0));
+
+        let mut decoded = [0u8; 600];
+        let m = decode(&encoded[..n], &mut decoded).unwrap();
+        assert_eq!(&decoded[..m], src);
+    }
+
+    #[test]
+    fn empty() {
+        let mut encoded = [0u8; 4];
+        assert_eq!(encode(&[], &mut encoded), Some(1));
+        assert_eq!(encoded[0], 1);
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn known_encodings() {
+        let mut encoded = [0u8; 8];
+        let n = encode(&[0x11, 0x00, 0x22, 0x33], &mut encoded).unwrap();
+        assert_eq!(&encoded[..n], &[0x02, 0x11, 0x03, 0x22, 0x33]);
+
+        let n = encode(&[0x00, 0x00], &mut encoded).unwrap();
+        assert_eq!(&encoded[..n], &[0x01, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn leading_and_trailing_zeros() {
+        round_trip(&[0]);
+        round_trip(&[0, 0, 0]);
+        round_trip(&[0, 1, 2, 3]);
+        round_trip(&[1, 2, 3, 0]);
+        round_trip(&[0, 0, 1, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn long_zero_free_runs() {
+        let mut src = [0u8; 520];
+        for (i, b) in src.iter_mut().enumerate() {
+            *b = (i % 255) as u8 + 1;
+        }
+
+        for len in [253, 254, 255, 256, 508, 509, 510] {
+            round_trip(&src[..len]);
+        }
+
+        // a full block needs no zero behind it
+        let mut encoded = [0u8; 300];
+        let n = encode(&src[..254], &mut encoded).unwrap();
+        assert_eq!(n, 256);
+        assert_eq!(encoded[0], 0xFF);
+        assert_eq!(encoded[255], 0x01);
+        let n = encode(&src[..255], &mut encoded).unwrap();
+        assert_eq!(n, max_encoded_len(255));
+
+        // and with zeros around the runs
+        let mut mixed = [0u8; 520];
+        mixed[1..255].copy_from_slice(&src[..254]);
+        mixed[256..511].copy_from_slice(&src[..255]);
+        round_trip(&mixed[..512]);
+        round_trip(&mixed[1..511]);
+    }
+
+    #[test]
+    fn small_buffers() {
+        let mut encoded = [0u8; 3];
+        assert_eq!(encode(&[1, 2, 3], &mut encoded), None);
+
+        let mut decoded = [0u8; 2];
+        assert_eq!(decode(&[0x04, 1, 2, 3], &mut decoded), None);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut decoded = [0u8; 8];
+        // a zero inside the frame
+        assert_eq!(decode(&[0x02, 0x11, 0x00], &mut decoded), None);
+        // a code running past the end
+        assert_eq!(decode(&[0x05, 0x11, 0x22], &mut decoded), None);
+    }
+}
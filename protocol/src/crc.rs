@@ -0,0 +1,37 @@
+// CRC-16/CCITT-FALSE, bitwise to keep the firmware free of a 512 byte table
+pub fn crc16(data: &[u8]) -> u16 {
+    const POLY: u16 = 0x1021;
+
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ POLY;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn empty_is_the_initial_value() {
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn detects_a_flipped_bit() {
+        assert_ne!(crc16(b"123456789"), crc16(b"123456788"));
+    }
+}
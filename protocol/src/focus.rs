@@ -0,0 +1,160 @@
+use crate::{ErrorCode, FocusStack, Spacing};
+
+// Slider positions of a focus stack, from the near limit to the far one.
+// Every move between shots goes the same way, and the first shot is
+// approached from behind the near limit, so the play in the drive is taken
+// up before anything is shot and never comes back.
+//
+// Shot i is worked out from the near limit every time, micrometres don't
+// add up into drift over a few hundred shots.
+
+#[derive(Clone, Debug)]
+pub struct Stack {
+    near: i32,
+    far: i32,
+    spacing: Spacing,
+    shots: u16,
+    backlash_um: i32,
+    pub taken: u16,
+    pub settle_ms: u32,
+    pub exposure_ms: u32,
+    pub return_to_start: bool,
+}
+
+impl Stack {
+    pub fn new(f: &FocusStack) -> Result<Self, ErrorCode> {
+        let depth = f.near_um.abs_diff(f.far_um);
+        let shots = match f.spacing {
+            Spacing::Step { step_um: 0 } | Spacing::Shots { shots: 0 } => {
+                return Err(ErrorCode::InvalidValue)
+            }
+            Spacing::Step { step_um } => {
+                u16::try_from(depth.div_ceil(step_um) + 1).map_err(|_| ErrorCode::InvalidValue)?
+            }
+            Spacing::Shots { shots } => shots,
+        };
+
+        Ok(Self {
+            near: f.near_um,
+            far: f.far_um,
+            spacing: f.spacing,
+            shots,
+            backlash_um: f.backlash_um as i32,
+            taken: 0,
+            settle_ms: f.settle_ms as u32,
+            exposure_ms: f.exposure_ms as u32,
+            return_to_start: f.return_to_start,
+        })
+    }
+
+    pub fn shots(&self) -> u16 {
+        self.shots
+    }
+
+    pub fn near(&self) -> i32 {
+        self.near
+    }
+
+    // The closest two shots get, u32::MAX for a single one
+    pub fn spacing_um(&self) -> u32 {
+        match (self.spacing, self.shots) {
+            (_, 1) => u32::MAX,
+            (Spacing::Step { step_um }, _) => step_um,
+            (Spacing::Shots { .. }, n) => self.near.abs_diff(self.far) / (n as u32 - 1),
+        }
+    }
+
+    // Where the slider starts from, the whole stack moves away from here
+    pub fn approach(&self) -> i32 {
+        match self.far < self.near {
+            true => self.near + self.backlash_um,
+            false => self.near - self.backlash_um,
+        }
+    }
+
+    // Slider position of the next shot, None once they are all taken
+    pub fn target(&self) -> Option<i32> {
+        let i = self.taken;
+        if i >= self.shots {
+            return None;
+        }
+
+        let (near, far) = (self.near as i64, self.far as i64);
+        let at = match self.spacing {
+            Spacing::Step { step_um } => {
+                let travel = (i as i64 * step_um as i64).min((far - near).abs());
+                near + travel * (far - near).signum()
+            }
+            Spacing::Shots { shots: 1 } => near,
+            Spacing::Shots { shots } => near + (far - near) * i as i64 / (shots as i64 - 1),
+        };
+        Some(at as i32)
+    }
+
+    pub fn advance(&mut self) {
+        self.taken += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(near_um: i32, far_um: i32, spacing: Spacing) -> FocusStack {
+        FocusStack {
+            near_um,
+            far_um,
+            spacing,
+            backlash_um: 500,
+            settle_ms: 0,
+            exposure_ms: 0,
+            return_to_start: false,
+        }
+    }
+
+    fn targets(stack: &mut Stack) -> [i32; 4] {
+        core::array::from_fn(|_| {
+            let at = stack.target().unwrap_or(i32::MIN);
+            stack.advance();
+            at
+        })
+    }
+
+    #[test]
+    fn steps_end_on_the_far_limit() {
+        let mut s = Stack::new(&stack(1_000, 1_250, Spacing::Step { step_um: 100 })).unwrap();
+        assert_eq!(s.shots(), 4);
+        assert_eq!(s.spacing_um(), 100);
+        assert_eq!(s.approach(), 500);
+        assert_eq!(targets(&mut s), [1_000, 1_100, 1_200, 1_250]);
+        assert_eq!(s.target(), None);
+    }
+
+    #[test]
+    fn shots_spread_evenly_towards_the_rail_start() {
+        let mut s = Stack::new(&stack(1_000, 700, Spacing::Shots { shots: 4 })).unwrap();
+        assert_eq!(s.spacing_um(), 100);
+        // approached from beyond the near limit, moving the same way as the stack
+        assert_eq!(s.approach(), 1_500);
+        assert_eq!(targets(&mut s), [1_000, 900, 800, 700]);
+    }
+
+    #[test]
+    fn one_shot_or_none() {
+        let s = Stack::new(&stack(1_000, 2_000, Spacing::Shots { shots: 1 })).unwrap();
+        assert_eq!(
+            (s.shots(), s.spacing_um(), s.target()),
+            (1, u32::MAX, Some(1_000))
+        );
+
+        for spacing in [Spacing::Shots { shots: 0 }, Spacing::Step { step_um: 0 }] {
+            assert_eq!(
+                Stack::new(&stack(0, 1_000, spacing)).err(),
+                Some(ErrorCode::InvalidValue)
+            );
+        }
+        // more shots than the count holds
+        let tiny = Spacing::Step { step_um: 1 };
+        assert!(Stack::new(&stack(0, 100_000, tiny)).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+/*
+* Binary protocol between the dolly and its companion tools.
+*
+* A frame is `0x00 COBS(seq kind payload crc16) 0x00`. The CRC-16/CCITT-FALSE
+* covers everything before it and is sent big endian, integers in the payload
+* are little endian. Plain text can be interleaved with frames on the same
+* link, a 0x00 switches the receiver to frame mode until the closing one.
+*/
+
+#![no_std]
+
+pub mod cobs;
+pub mod crc;
+pub mod focus;
+pub mod machine;
+pub mod message;
+pub mod panorama;
+pub mod plan;
+pub mod playback;
+pub mod profile;
+pub mod rig;
+pub mod sequence;
+pub mod shell;
+pub mod stopmotion;
+pub mod tracking;
+pub mod units;
+
+pub use message::{
+    Aim, ErrorCode, FocusStack, Keyframe, Lens, Message, Panorama, SettingId, Spacing, State, Step,
+    StopMotion, Telemetry,
+};
+pub use plan::{Infeasible, Limits, Violation};
+pub use playback::{Mode, Pass, Playback};
+
+// kind byte plus the largest payload
+pub const MAX_MESSAGE_LEN: usize = 48;
+const MAX_RAW_LEN: usize = 1 + MAX_MESSAGE_LEN + 2;
+pub const MAX_FRAME_LEN: usize = cobs::max_encoded_len(MAX_RAW_LEN) + 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    BufferTooSmall,
+    Overflow,
+    Cobs,
+    Crc,
+    TooShort,
+    UnknownMessage(u8),
+    Malformed,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::BufferTooSmall => "BufferTooSmall",
+            Error::Overflow => "Overflow",
+            Error::Cobs => "Cobs",
+            Error::Crc => "Crc",
+            Error::TooShort => "TooShort",
+            Error::UnknownMessage(_) => "UnknownMessage",
+            Error::Malformed => "Malformed",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Packet {
+    // responses carry the sequence number of their request
+    pub seq: u8,
+    pub message: Message,
+}
+
+impl Packet {
+    pub fn new(seq: u8, message: Message) -> Self {
+        Self { seq, message }
+    }
+
+    // Writes the whole frame, delimiters included
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let mut raw = [0u8; MAX_RAW_LEN];
+        raw[0] = self.seq;
+        let len = 1 + self.message.encode(&mut raw[1..1 + MAX_MESSAGE_LEN])?;
+        let crc = crc::crc16(&raw[..len]);
+        raw[len..len + 2].copy_from_slice(&crc.to_be_bytes());
+
+        if out.len() < 2 {
+            return Err(Error::BufferTooSmall);
+        }
+        let last = out.len() - 1;
+        let n = cobs::encode(&raw[..len + 2], &mut out[1..last]).ok_or(Error::BufferTooSmall)?;
+        out[0] = 0;
+        out[n + 1] = 0;
+        Ok(n + 2)
+    }
+
+    // Decodes the bytes between two delimiters
+    pub fn decode(frame: &[u8]) -> Result<Self, Error> {
+        let mut raw = [0u8; MAX_RAW_LEN];
+        let len = cobs::decode(frame, &mut raw).ok_or(Error::Cobs)?;
+        if len < 4 {
+            return Err(Error::TooShort);
+        }
+
+        let (data, crc) = raw[..len].split_at(len - 2);
+        if crc::crc16(data) != u16::from_be_bytes([crc[0], crc[1]]) {
+            return Err(Error::Crc);
+        }
+
+        Ok(Self {
+            seq: data[0],
+            message: Message::decode(&data[1..])?,
+        })
+    }
+}
+
+// The last request answered. A client resends a request under the same
+// sequence number when the reply is late, the resend is answered again
+// instead of being carried out twice.
+pub struct Replay {
+    last: Option<(u8, u16, Message)>,
+}
+
+impl Replay {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    // The reply already sent when `request` repeats the last one
+    pub fn get(&self, request: &Packet) -> Option<Message> {
+        match self.last {
+            Some((seq, crc, reply)) if seq == request.seq && crc == Self::crc(request) => {
+                Some(reply)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn record(&mut self, request: &Packet, reply: Message) {
+        self.last = Some((request.seq, Self::crc(request), reply));
+    }
+
+    // Tells a repeat from a new request that happens to reuse the number
+    fn crc(request: &Packet) -> u16 {
+        let mut raw = [0u8; MAX_MESSAGE_LEN];
+        match request.message.encode(&mut raw) {
+            Ok(len) => crc::crc16(&raw[..len]),
+            Err(_) => 0,
+        }
+    }
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Decoder {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    overflow: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+    }
+
+    // Returns a result at every delimiter that closes a non empty frame
+    pub fn push(&mut self, byte: u8) -> Option<Result<Packet, Error>> {
+        if byte != 0 {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+
+        if self.len == 0 && !self.overflow {
+            return None;
+        }
+
+        let result = match self.overflow {
+            true => Err(Error::Overflow),
+            false => Packet::decode(&self.buf[..self.len]),
+        };
+        self.reset();
+        Some(result)
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub enum Event {
+    Text(u8),
+    Packet(Packet),
+    Error(Error),
+}
+
+// Splits a byte stream into plain text and frames
+pub struct Demux {
+    decoder: Decoder,
+    in_frame: bool,
+}
+
+impl Demux {
+    pub const fn new() -> Self {
+        Self {
+            decoder: Decoder::new(),
+            in_frame: false,
+        }
+    }
+
+    // Drops a partially received frame, e.g. after bytes were lost
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+        self.in_frame = false;
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Event> {
+        if !self.in_frame && byte != 0 {
+            return Some(Event::Text(byte));
+        }
+
+        // an empty frame is the opening delimiter, stay in frame mode
+        self.in_frame = true;
+        let result = self.decoder.push(byte)?;
+        self.in_frame = false;
+
+        match result {
+            Ok(packet) => Some(Event::Packet(packet)),
+            Err(e) => Some(Event::Error(e)),
+        }
+    }
+}
+
+impl Default for Demux {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TELEMETRY: Message = Message::Telemetry(Telemetry {
+        uptime_ms: 0x0100_0000,
+        state: State::Moving,
+        position: [0, -1, 255],
+        velocity: [0; 3],
+        frame: 0,
+        remaining_s: 0,
+        battery_mv: 0,
+        loop_hz: 0,
+        overruns: 0,
+        max_lateness_ms: 0,
+    });
+
+    fn frame(packet: Packet) -> ([u8; MAX_FRAME_LEN], usize) {
+        let mut out = [0u8; MAX_FRAME_LEN];
+        let len = packet.encode(&mut out).unwrap();
+        (out, len)
+    }
+
+    fn feed(decoder: &mut Decoder, bytes: &[u8]) -> Option<Result<Packet, Error>> {
+        let mut result = None;
+        for &byte in bytes {
+            if let Some(r) = decoder.push(byte) {
+                assert!(result.is_none(), "more than one result");
+                result = Some(r);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        for packet in [
+            Packet::new(0, Message::Ping),
+            Packet::new(255, TELEMETRY),
+            Packet::new(7, Message::Nack(ErrorCode::NotReady)),
+        ] {
+            let (out, len) = frame(packet);
+            assert_eq!(out[0], 0);
+            assert_eq!(out[len - 1], 0);
+            assert!(!out[1..len - 1].contains(&0));
+            assert_eq!(Packet::decode(&out[1..len - 1]), Ok(packet));
+
+            let mut decoder = Decoder::new();
+            assert_eq!(feed(&mut decoder, &out[..len]), Some(Ok(packet)));
+        }
+    }
+
+    #[test]
+    fn packet_buffer_too_small() {
+        let mut out = [0u8; 8];
+        assert_eq!(
+            Packet::new(1, TELEMETRY).encode(&mut out),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(
+            Packet::new(1, Message::Ping).encode(&mut out[..1]),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn bad_crc() {
+        let (mut out, len) = frame(Packet::new(3, Message::Ping));
+        // seq is the first byte after the COBS code
+        out[2] ^= 0x01;
+        let mut decoder = Decoder::new();
+        assert_eq!(feed(&mut decoder, &out[..len]), Some(Err(Error::Crc)));
+
+        // and the next frame still gets through
+        let (out, len) = frame(Packet::new(4, Message::Ack));
+        assert_eq!(
+            feed(&mut decoder, &out[..len]),
+            Some(Ok(Packet::new(4, Message::Ack)))
+        );
+    }
+
+    #[test]
+    fn short_and_broken_frames() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            feed(&mut decoder, &[0, 0x02, 0x01, 0]),
+            Some(Err(Error::TooShort))
+        );
+        assert_eq!(feed(&mut decoder, &[0x05, 0x01, 0]), Some(Err(Error::Cobs)));
+        // delimiters alone close nothing
+        assert_eq!(feed(&mut decoder, &[0, 0, 0]), None);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut decoder = Decoder::new();
+        let long = [0x55u8; MAX_FRAME_LEN + 10];
+        assert_eq!(feed(&mut decoder, &long), None);
+        assert_eq!(decoder.push(0), Some(Err(Error::Overflow)));
+
+        // reset by the delimiter
+        let (out, len) = frame(Packet::new(9, Message::Ping));
+        assert_eq!(
+            feed(&mut decoder, &out[..len]),
+            Some(Ok(Packet::new(9, Message::Ping)))
+        );
+    }
+
+    #[test]
+    fn frame_of_exactly_the_buffer_size() {
+        let mut decoder = Decoder::new();
+        let mut frame = [0x55u8; MAX_FRAME_LEN];
+        frame[0] = 0xFF;
+        assert_eq!(feed(&mut decoder, &frame), None);
+        assert_ne!(decoder.push(0), Some(Err(Error::Overflow)));
+    }
+
+    #[test]
+    fn demux_interleaves_text_and_frames() {
+        let mut stream = [0u8; 64 + 2 * MAX_FRAME_LEN];
+        let mut len = 0;
+        let mut put = |bytes: &[u8]| {
+            stream[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+
+        put(b"hi\n");
+        let (out, n) = frame(Packet::new(1, TELEMETRY));
+        put(&out[..n]);
+        put(b"ok");
+        // a broken frame between the text
+        put(&[0, 0x02, 0x01, 0]);
+        put(b"!");
+        let (out, n) = frame(Packet::new(2, Message::Ack));
+        put(&out[..n]);
+
+        let mut demux = Demux::new();
+        let mut text = [0u8; 16];
+        let mut text_len = 0;
+        let mut packets = 0;
+        let mut errors = 0;
+        for &byte in &stream[..len] {
+            match demux.push(byte) {
+                Some(Event::Text(b)) => {
+                    text[text_len] = b;
+                    text_len += 1;
+                }
+                Some(Event::Packet(p)) => {
+                    packets += 1;
+                    let want = match packets {
+                        1 => Packet::new(1, TELEMETRY),
+                        _ => Packet::new(2, Message::Ack),
+                    };
+                    assert_eq!(p, want);
+                }
+                Some(Event::Error(e)) => {
+                    errors += 1;
+                    assert_eq!(e, Error::TooShort);
+                }
+                None => {}
+            }
+        }
+
+        assert_eq!(&text[..text_len], b"hi\nok!");
+        assert_eq!((packets, errors), (2, 1));
+    }
+
+    #[test]
+    fn demux_reset_drops_a_partial_frame() {
+        let mut demux = Demux::new();
+        let (out, n) = frame(Packet::new(5, Message::Ping));
+        for &byte in &out[..n / 2] {
+            assert!(demux.push(byte).is_none());
+        }
+
+        demux.reset();
+        assert!(matches!(demux.push(b'x'), Some(Event::Text(b'x'))));
+    }
+
+    #[test]
+    fn replays_only_a_repeated_request() {
+        let mut replay = Replay::new();
+        let step = Packet::new(9, Message::StepFrame(Step::Shoot));
+        assert_eq!(replay.get(&step), None);
+
+        replay.record(&step, Message::Ack);
+        assert_eq!(replay.get(&step), Some(Message::Ack));
+        // the same number reused for something else, or the next request
+        assert_eq!(replay.get(&Packet::new(9, Message::StartSequence)), None);
+        assert_eq!(replay.get(&Packet::new(10, step.message)), None);
+
+        replay.record(&Packet::new(10, Message::Ping), Message::Ack);
+        assert_eq!(replay.get(&step), None);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{focus::Stack, plan, ErrorCode, FocusStack, Message};
+
+use super::{goto, Hardware, Note};
+
+#[derive(Clone, Copy)]
+enum Phase {
+    // to the overshoot behind the near limit
+    TakingUp,
+    Moving,
+    Settling,
+    Exposing,
+    Returning,
+}
+
+// The shots of a stack come from crate::focus::Stack, this is where the
+// dolly is between them
+pub struct Run {
+    stack: Stack,
+    phase: Phase,
+    since: u32,
+}
+
+impl Run {
+    // Off to take up the play, or the refusal to send back
+    pub fn start<H: Hardware>(f: &FocusStack, hw: &mut H, now: u32) -> Result<Self, Message> {
+        let stack = Stack::new(f).map_err(Message::Nack)?;
+        // closer shots would land on the same motor step
+        let step_um = hw.slider_step_um();
+        if stack.spacing_um() < step_um.unsigned_abs() {
+            hw.note(Note::ShotsTooClose { step_um });
+            return Err(Message::Nack(ErrorCode::InvalidValue));
+        }
+
+        let [_, pan, tilt] = hw.positions();
+        let points = [stack.approach(), f.near_um, f.far_um].map(|s| [s, pan, tilt]);
+        if let Err(e) = plan::check_travel(&hw.limits(), points) {
+            hw.note(Note::Infeasible(e));
+            return Err(Message::Infeasible(e));
+        }
+        hw.note(Note::FocusStackStarted {
+            shots: stack.shots(),
+        });
+
+        move_slider(hw, stack.approach(), now);
+        Ok(Self {
+            stack,
+            phase: Phase::TakingUp,
+            since: now,
+        })
+    }
+
+    // Settles for as long as it still had to after the pause, an exposure
+    // it cut short starts over
+    pub fn resume<H: Hardware>(&mut self, hw: &mut H, now: u32, paused_ms: u32) {
+        match self.phase {
+            Phase::Exposing => {
+                hw.shutter(true);
+                self.since = now;
+            }
+            _ => self.since = self.since.wrapping_add(paused_ms),
+        }
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.stack.taken as u32
+    }
+
+    pub fn update<H: Hardware>(&mut self, hw: &mut H, now: u32, arrived: bool) -> bool {
+        let stack = &mut self.stack;
+        let elapsed = now.wrapping_sub(self.since);
+        let next = match self.phase {
+            Phase::TakingUp if arrived => {
+                self.phase = Phase::Moving;
+                stack.target()
+            }
+            Phase::Moving if arrived => {
+                self.phase = Phase::Settling;
+                self.since = now;
+                None
+            }
+            Phase::Settling if elapsed >= stack.settle_ms => {
+                hw.shutter(true);
+                self.phase = Phase::Exposing;
+                self.since = now;
+                None
+            }
+            Phase::Exposing if elapsed >= stack.exposure_ms => {
+                hw.shutter(false);
+                stack.advance();
+                hw.note(Note::Shot {
+                    taken: stack.taken,
+                    of: stack.shots(),
+                });
+                match stack.target() {
+                    Some(slider) => {
+                        self.phase = Phase::Moving;
+                        Some(slider)
+                    }
+                    None if stack.return_to_start => {
+                        self.phase = Phase::Returning;
+                        Some(stack.near())
+                    }
+                    None => {
+                        hw.note(Note::FocusStackFinished);
+                        return true;
+                    }
+                }
+            }
+            Phase::Returning if arrived => {
+                hw.note(Note::FocusStackFinished);
+                return true;
+            }
+            _ => None,
+        };
+
+        if let Some(slider) = next {
+            move_slider(hw, slider, now);
+        }
+        false
+    }
+}
+
+// Pan and tilt stay where they are
+fn move_slider<H: Hardware>(hw: &mut H, slider: i32, now: u32) {
+    let [_, pan, tilt] = hw.positions();
+    goto(hw, &[slider, pan, tilt], now, None);
+}
@@ -0,0 +1,67 @@
+#[cfg(feature = "focus-stack")]
+use super::focus;
+#[cfg(feature = "panorama")]
+use super::panorama;
+#[cfg(feature = "stop-motion")]
+use super::stopmotion;
+use super::Hardware;
+use crate::State;
+
+// A panorama, an animation or a focus stack. Each drives the axes and the
+// shutter by itself once started, the machine only passes the time on.
+pub enum Job {
+    #[cfg(feature = "panorama")]
+    Panorama(panorama::Run),
+    #[cfg(feature = "stop-motion")]
+    StopMotion(stopmotion::Run),
+    #[cfg(feature = "focus-stack")]
+    FocusStack(focus::Run),
+}
+
+impl Job {
+    pub fn id(&self) -> State {
+        match self {
+            #[cfg(feature = "panorama")]
+            Job::Panorama(_) => State::Panorama,
+            #[cfg(feature = "stop-motion")]
+            Job::StopMotion(_) => State::StopMotion,
+            #[cfg(feature = "focus-stack")]
+            Job::FocusStack(_) => State::FocusStack,
+        }
+    }
+
+    // True once it's finished, `arrived` when its last move just did
+    pub fn update<H: Hardware>(&mut self, hw: &mut H, now: u32, arrived: bool) -> bool {
+        match self {
+            #[cfg(feature = "panorama")]
+            Job::Panorama(run) => run.update(hw, now, arrived),
+            #[cfg(feature = "stop-motion")]
+            Job::StopMotion(run) => run.update(hw, now, arrived),
+            #[cfg(feature = "focus-stack")]
+            Job::FocusStack(run) => run.update(hw, now, arrived),
+        }
+    }
+
+    // Its clock shifted by a pause of `paused_ms`, the shutter let go by it
+    pub fn resume<H: Hardware>(&mut self, hw: &mut H, now: u32, paused_ms: u32) {
+        match self {
+            #[cfg(feature = "panorama")]
+            Job::Panorama(run) => run.resume(hw, now, paused_ms),
+            #[cfg(feature = "stop-motion")]
+            Job::StopMotion(run) => run.resume(hw, now, paused_ms),
+            #[cfg(feature = "focus-stack")]
+            Job::FocusStack(run) => run.resume(hw, now, paused_ms),
+        }
+    }
+
+    pub fn frame(&self) -> u32 {
+        match self {
+            #[cfg(feature = "panorama")]
+            Job::Panorama(run) => run.frame(),
+            #[cfg(feature = "stop-motion")]
+            Job::StopMotion(run) => run.frame(),
+            #[cfg(feature = "focus-stack")]
+            Job::FocusStack(run) => run.frame(),
+        }
+    }
+}
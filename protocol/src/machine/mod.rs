@@ -0,0 +1,1137 @@
+/*
+* The run state machine of the dolly: sequences, video takes, jobs, and how
+* a pause, resume or abort moves between them. The firmware drives it with
+* its executor and steppers, the simulator with a model of them, both
+* through `Hardware`, so the two can't tell a different story.
+*/
+
+use core::ops::Range;
+
+#[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+use crate::plan;
+#[cfg(feature = "focus-stack")]
+use crate::FocusStack;
+#[cfg(feature = "panorama")]
+use crate::Panorama;
+use crate::{plan::AXES, shell::Command, tracking::Target, ErrorCode, Limits, Message, State};
+#[cfg(feature = "timelapse")]
+use crate::{playback, sequence::Sequence, Aim, Infeasible, Keyframe, Pass, Playback};
+#[cfg(feature = "stop-motion")]
+use crate::{stopmotion::RECORD_LEN, Step, StopMotion};
+
+#[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+use self::job::Job;
+pub use self::note::{Note, Sink};
+
+#[cfg(feature = "focus-stack")]
+mod focus;
+#[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+mod job;
+mod note;
+#[cfg(feature = "panorama")]
+mod panorama;
+#[cfg(feature = "stop-motion")]
+mod stopmotion;
+
+// What the machine needs of a rig. Positions are in micrometres and
+// millidegrees on the sequenced axes, times on the caller's millisecond clock.
+pub trait Hardware {
+    type HomingError;
+
+    fn positions(&self) -> [i32; AXES];
+    fn limits(&self) -> [Limits; AXES];
+    // one motor step of the slider
+    fn slider_step_um(&self) -> i32;
+
+    // A timed move from wherever the axes are, with the drivers on.
+    // `started` may lie in the past to keep a longer schedule on time.
+    fn start(&mut self, to: &[i32; AXES], duration_ms: u32, started: u32, track: Option<Target>);
+    // Returns true once, when the running move has arrived
+    fn update(&mut self, now: u32) -> bool;
+    fn pause(&mut self, now: u32);
+    fn resume(&mut self, now: u32);
+    // Brakes like a pause, then drops the move
+    fn abort(&mut self, now: u32);
+    // Stops a jog where it is, without a ramp
+    fn halt(&mut self);
+    fn is_running(&self) -> bool;
+    fn is_jogging(&self) -> bool;
+    // While a pause, resume or abort is still changing speed
+    fn is_easing(&self, now: u32) -> bool;
+    fn remaining_ms(&self, now: u32) -> u32;
+
+    fn shutter(&mut self, pressed: bool);
+    fn leds(&mut self, in_on: bool, out_on: bool);
+    // where an animation keeps its frame across power cycles
+    #[cfg(feature = "stop-motion")]
+    fn load(&self) -> [u8; RECORD_LEN];
+    #[cfg(feature = "stop-motion")]
+    fn store(&mut self, record: &[u8; RECORD_LEN]);
+
+    // Finds the end of travel of every axis that can, the positions count
+    // from there afterwards
+    fn home(&mut self) -> Result<(), Self::HomingError>;
+    fn note(&mut self, note: Note);
+}
+
+// The fastest move there from wherever the axes are
+#[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+fn goto<H: Hardware>(hw: &mut H, to: &[i32; AXES], now: u32, track: Option<Target>) {
+    let duration_ms = plan::fastest_ms(&hw.limits(), &hw.positions(), to);
+    hw.start(to, duration_ms, now, track);
+}
+
+// The first and last keyframe as they play, aimed at the subject when tracking
+type Ends = Range<[i32; AXES]>;
+
+// What the move to the first keyframe leads into
+#[cfg(feature = "timelapse")]
+#[derive(Clone, Copy)]
+enum Take {
+    Sequence,
+    Video { duration_ms: u32, preroll_ms: u32 },
+}
+
+// A run as it plays, and as a pause holds on to it
+#[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+enum Playing {
+    #[cfg(feature = "timelapse")]
+    GotoInit { range: Ends, take: Take },
+    // `started` is when the pass began, segment i is the pass's i-th move
+    #[cfg(feature = "timelapse")]
+    Moving {
+        range: Ends,
+        pass: Pass,
+        cycle: u16,
+        segment: usize,
+        started: u32,
+    },
+    #[cfg(feature = "timelapse")]
+    Dwelling {
+        range: Ends,
+        since: u32,
+        dwell_ms: u32,
+        next: Pass,
+        cycle: u16,
+    },
+    #[cfg(feature = "timelapse")]
+    Preroll {
+        range: Ends,
+        since: u32,
+        preroll_ms: u32,
+        duration_ms: u32,
+    },
+    // the single timed move of a video take, from the start of the range to its end
+    #[cfg(feature = "timelapse")]
+    Filming { range: Ends },
+    // `resume` is the range to be ready with afterwards, if there was one
+    #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+    Job { job: Job, resume: Option<Ends> },
+}
+
+#[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+impl Playing {
+    fn id(&self) -> State {
+        match self {
+            #[cfg(feature = "timelapse")]
+            Playing::GotoInit { .. } => State::GotoInit,
+            #[cfg(feature = "timelapse")]
+            Playing::Moving { .. } | Playing::Filming { .. } => State::Moving,
+            #[cfg(feature = "timelapse")]
+            Playing::Dwelling { .. } => State::Dwelling,
+            #[cfg(feature = "timelapse")]
+            Playing::Preroll { .. } => State::Preroll,
+            #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+            Playing::Job { job, .. } => job.id(),
+        }
+    }
+
+    // The range to be ready with once it's stopped
+    fn take_ends(&mut self) -> Option<Ends> {
+        match self {
+            #[cfg(feature = "timelapse")]
+            Playing::GotoInit { range, .. }
+            | Playing::Moving { range, .. }
+            | Playing::Dwelling { range, .. }
+            | Playing::Preroll { range, .. }
+            | Playing::Filming { range } => Some(range.clone()),
+            #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+            Playing::Job { resume, .. } => resume.take(),
+        }
+    }
+
+    // Everything after a pause runs late by as long as it lasted. The
+    // moves keep their own time, the rig resumes them.
+    #[cfg(feature = "timelapse")]
+    fn shift(&mut self, paused_ms: u32) {
+        match self {
+            #[cfg(feature = "timelapse")]
+            Playing::Moving { started, .. } => *started = started.wrapping_add(paused_ms),
+            #[cfg(feature = "timelapse")]
+            Playing::Dwelling { since, .. } | Playing::Preroll { since, .. } => {
+                *since = since.wrapping_add(paused_ms)
+            }
+            _ => {}
+        }
+    }
+}
+
+enum DollyState {
+    SetInitPos,
+    // the range a job hands back once it's done
+    #[cfg_attr(
+        not(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack")),
+        allow(dead_code)
+    )]
+    Ready(Ends),
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    Playing(Playing),
+    // `since` is when the pause was asked for, the schedule shifts from there
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    Pausing {
+        held: Playing,
+        since: u32,
+    },
+    // `arrived` when the held move got there while braking
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    Paused {
+        held: Playing,
+        since: u32,
+        arrived: bool,
+    },
+    // back up to speed, its clocks already shifted by the pause
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    Resuming(Playing),
+    // braking to a stop before being ready again
+    Aborting {
+        resume: Option<Ends>,
+    },
+}
+
+impl DollyState {
+    fn id(&self) -> State {
+        match self {
+            DollyState::SetInitPos => State::SetInitPos,
+            DollyState::Ready(_) => State::Ready,
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            DollyState::Playing(playing) => playing.id(),
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            DollyState::Pausing { .. } => State::Pausing,
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            DollyState::Paused { .. } => State::Paused,
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            DollyState::Resuming(_) => State::Resuming,
+            DollyState::Aborting { .. } => State::Aborting,
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        !matches!(self, DollyState::SetInitPos | DollyState::Ready(_))
+    }
+
+    // from the pause until back on the schedule
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    fn is_paused(&self) -> bool {
+        matches!(
+            self,
+            DollyState::Pausing { .. } | DollyState::Paused { .. } | DollyState::Resuming(_)
+        )
+    }
+
+    // The run, whether it plays or a pause holds it
+    #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+    fn playing(&self) -> Option<&Playing> {
+        match self {
+            DollyState::Playing(playing)
+            | DollyState::Pausing { held: playing, .. }
+            | DollyState::Paused { held: playing, .. }
+            | DollyState::Resuming(playing) => Some(playing),
+            _ => None,
+        }
+    }
+
+    // Where a finished or stopped run leaves the dolly
+    fn idle(range: Option<Ends>) -> Self {
+        match range {
+            Some(range) => DollyState::Ready(range),
+            None => DollyState::SetInitPos,
+        }
+    }
+}
+
+pub struct Machine {
+    #[cfg(feature = "timelapse")]
+    sequence: Sequence,
+    #[cfg(feature = "timelapse")]
+    pub playback: Playback,
+    // pan and tilt follow it through sequences, video moves and animations
+    #[cfg(feature = "timelapse")]
+    tracking: Option<Target>,
+    // the first of two sightings of the subject
+    #[cfg(feature = "timelapse")]
+    sighting: Option<[i32; AXES]>,
+    state: DollyState,
+    // set after a watchdog reset, cleared by `acknowledge`
+    motion_blocked: bool,
+    homing_pending: bool,
+    // set once homing succeeded or was skipped
+    homed: bool,
+}
+
+impl Machine {
+    pub fn new(motion_blocked: bool) -> Self {
+        Self {
+            #[cfg(feature = "timelapse")]
+            sequence: Sequence::new(),
+            #[cfg(feature = "timelapse")]
+            playback: Playback::new(),
+            #[cfg(feature = "timelapse")]
+            tracking: None,
+            #[cfg(feature = "timelapse")]
+            sighting: None,
+            state: DollyState::SetInitPos,
+            motion_blocked,
+            homing_pending: false,
+            homed: false,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state.id()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state.is_playing()
+    }
+
+    // a paused animation isn't, the button resumes it rather than stepping
+    #[cfg(feature = "stop-motion")]
+    pub fn is_animating(&self) -> bool {
+        matches!(
+            self.state,
+            DollyState::Playing(Playing::Job {
+                job: Job::StopMotion(_),
+                ..
+            })
+        )
+    }
+
+    #[cfg(not(feature = "stop-motion"))]
+    pub fn is_animating(&self) -> bool {
+        false
+    }
+
+    // The sticks and buttons may move the axes by hand
+    pub fn can_jog(&self) -> bool {
+        !self.motion_blocked && self.homed && !self.is_playing()
+    }
+
+    // Nothing else may be moving the axes, and their positions must be known
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    fn can_start<H: Hardware>(&self, hw: &H) -> bool {
+        self.can_jog() && !hw.is_jogging()
+    }
+
+    // The messages that start, stop or set up a run. None for the ones that
+    // aren't the machine's to answer.
+    pub fn handle<H: Hardware>(
+        &mut self,
+        hw: &mut H,
+        message: &Message,
+        now: u32,
+    ) -> Option<Message> {
+        let reply = match *message {
+            Message::Home => self.retry_homing(hw),
+            Message::SkipHoming => {
+                self.skip_homing(hw);
+                Message::Ack
+            }
+            // the running sequence is read while it plays
+            #[cfg(feature = "timelapse")]
+            Message::ClearKeyframes | Message::UploadKeyframe { .. } if self.is_playing() => {
+                Message::Nack(ErrorCode::NotReady)
+            }
+            #[cfg(feature = "timelapse")]
+            Message::ClearKeyframes => {
+                self.sequence.clear();
+                Message::Ack
+            }
+            #[cfg(feature = "timelapse")]
+            Message::UploadKeyframe { index, keyframe } => {
+                match self.sequence.set(index as usize, keyframe) {
+                    Ok(()) => Message::Ack,
+                    Err(e) => Message::Nack(e),
+                }
+            }
+            #[cfg(feature = "timelapse")]
+            Message::ReadKeyframe { index } => match self.sequence.get(index as usize) {
+                Some(keyframe) => Message::Keyframe { index, keyframe },
+                None => Message::Nack(ErrorCode::OutOfRange),
+            },
+            #[cfg(feature = "timelapse")]
+            Message::StartSequence => self.start_sequence(hw, now),
+            #[cfg(feature = "timelapse")]
+            Message::StartVideo {
+                duration_ms,
+                preroll_s,
+            } => self.start_video(hw, duration_ms, preroll_s, now),
+            #[cfg(feature = "panorama")]
+            Message::StartPanorama(p) => self.start_panorama(hw, &p, now),
+            #[cfg(feature = "timelapse")]
+            Message::AimTarget { aim, follow_tilt } => self.aim_target(hw, aim, follow_tilt),
+            #[cfg(feature = "stop-motion")]
+            Message::StartAnimation(setup) => self.start_animation(hw, &setup, now),
+            #[cfg(feature = "stop-motion")]
+            Message::StepFrame(step) => self.step_frame(hw, step, now),
+            #[cfg(feature = "focus-stack")]
+            Message::StartFocusStack(f) => self.start_focus_stack(hw, &f, now),
+            Message::StopSequence => self.abort(hw, now),
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            Message::PauseSequence => self.pause(hw, now),
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            Message::ResumeSequence => self.resume(hw, now),
+            #[cfg(not(feature = "timelapse"))]
+            Message::ClearKeyframes
+            | Message::UploadKeyframe { .. }
+            | Message::ReadKeyframe { .. }
+            | Message::StartSequence
+            | Message::StartVideo { .. }
+            | Message::AimTarget { .. } => Message::Nack(ErrorCode::Unsupported),
+            #[cfg(not(any(feature = "timelapse", feature = "panorama", feature = "focus-stack")))]
+            Message::PauseSequence | Message::ResumeSequence => {
+                Message::Nack(ErrorCode::Unsupported)
+            }
+            #[cfg(not(feature = "panorama"))]
+            Message::StartPanorama(_) => Message::Nack(ErrorCode::Unsupported),
+            #[cfg(not(feature = "stop-motion"))]
+            Message::StartAnimation(_) | Message::StepFrame(_) => {
+                Message::Nack(ErrorCode::Unsupported)
+            }
+            #[cfg(not(feature = "focus-stack"))]
+            Message::StartFocusStack(_) => Message::Nack(ErrorCode::Unsupported),
+            _ => return None,
+        };
+        Some(reply)
+    }
+
+    // The shell's run and playback commands, false for the ones it doesn't
+    // know, left out of the build or not the machine's
+    pub fn command<H: Hardware>(&mut self, hw: &mut H, cmd: &Command, now: u32) -> bool {
+        match *cmd {
+            Command::Home => {
+                self.retry_homing(hw);
+            }
+            Command::SkipHoming => self.skip_homing(hw),
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            Command::Pause => {
+                if self.pause(hw, now) != Message::Ack {
+                    hw.note(Note::NothingToPause);
+                }
+            }
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            Command::Resume => {
+                if self.resume(hw, now) != Message::Ack {
+                    hw.note(Note::NothingPaused);
+                }
+            }
+            Command::Abort => {
+                if self.abort(hw, now) != Message::Ack {
+                    hw.note(Note::NothingToAbort);
+                }
+            }
+            #[cfg(feature = "timelapse")]
+            Command::ShowPlayback => self.show_playback(hw),
+            #[cfg(feature = "timelapse")]
+            Command::SetPlayback { mode, repeat } => {
+                self.playback.mode = mode;
+                if let Some(repeat) = repeat {
+                    self.playback.repeat = repeat;
+                }
+                self.show_playback(hw);
+            }
+            #[cfg(feature = "timelapse")]
+            Command::SetDwell { start_ms, end_ms } => {
+                self.playback.dwell_start_ms = start_ms;
+                self.playback.dwell_end_ms = end_ms;
+                self.show_playback(hw);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn start_sequence<H: Hardware>(&mut self, hw: &mut H, now: u32) -> Message {
+        if !self.can_start(hw) {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        let keyframes = self.sequence.keyframes();
+        let (Some(first), Some(last)) = (keyframes.first(), keyframes.last()) else {
+            return Message::Nack(ErrorCode::NotReady);
+        };
+        if let Err(e) = self.check_take(&hw.limits(), keyframes) {
+            hw.note(Note::Infeasible(e));
+            return Message::Infeasible(e);
+        }
+
+        let range = self.ends(first, last);
+        self.goto_init(hw, range, Take::Sequence, now);
+        hw.note(Note::SequenceStarted);
+        Message::Ack
+    }
+
+    // The ends of the sequence, whatever lies between them
+    #[cfg(feature = "timelapse")]
+    fn start_video<H: Hardware>(
+        &mut self,
+        hw: &mut H,
+        duration_ms: u32,
+        preroll_s: u8,
+        now: u32,
+    ) -> Message {
+        if !self.can_start(hw) {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        let keyframes = self.sequence.keyframes();
+        let (Some(first), Some(last)) = (keyframes.first(), keyframes.last()) else {
+            return Message::Nack(ErrorCode::NotReady);
+        };
+        let take = [
+            Keyframe {
+                time_ms: 0,
+                ..*first
+            },
+            Keyframe {
+                time_ms: duration_ms,
+                ..*last
+            },
+        ];
+        if let Err(e) = self.check_take(&hw.limits(), &take) {
+            hw.note(Note::Infeasible(e));
+            return Message::Infeasible(e);
+        }
+
+        let range = self.ends(first, last);
+        let preroll_ms = preroll_s as u32 * 1000;
+        let take = Take::Video {
+            duration_ms,
+            preroll_ms,
+        };
+        self.goto_init(hw, range, take, now);
+        hw.note(Note::VideoStarted { duration_ms });
+        Message::Ack
+    }
+
+    #[cfg(feature = "panorama")]
+    fn start_panorama<H: Hardware>(&mut self, hw: &mut H, p: &Panorama, now: u32) -> Message {
+        if !self.can_start(hw) {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        match panorama::Run::start(p, hw, now) {
+            Ok(run) => self.start_job(Job::Panorama(run)),
+            Err(refused) => refused,
+        }
+    }
+
+    #[cfg(feature = "stop-motion")]
+    fn start_animation<H: Hardware>(
+        &mut self,
+        hw: &mut H,
+        setup: &StopMotion,
+        now: u32,
+    ) -> Message {
+        if !self.can_start(hw) {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        let keyframes = self.sequence.keyframes();
+        let (Some(first), Some(last)) = (keyframes.first(), keyframes.last()) else {
+            return Message::Nack(ErrorCode::NotReady);
+        };
+        match stopmotion::Run::start((first, last), setup, self.tracking, hw, now) {
+            Ok(run) => self.start_job(Job::StopMotion(run)),
+            Err(refused) => refused,
+        }
+    }
+
+    #[cfg(feature = "stop-motion")]
+    pub fn step_frame<H: Hardware>(&mut self, hw: &mut H, step: Step, now: u32) -> Message {
+        match &mut self.state {
+            DollyState::Playing(Playing::Job {
+                job: Job::StopMotion(run),
+                ..
+            }) => run.step(step, hw, now),
+            _ => Message::Nack(ErrorCode::NotReady),
+        }
+    }
+
+    #[cfg(feature = "focus-stack")]
+    fn start_focus_stack<H: Hardware>(&mut self, hw: &mut H, f: &FocusStack, now: u32) -> Message {
+        if !self.can_start(hw) {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        match focus::Run::start(f, hw, now) {
+            Ok(run) => self.start_job(Job::FocusStack(run)),
+            Err(refused) => refused,
+        }
+    }
+
+    // Ready again with the same range once the job is done
+    #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+    fn start_job(&mut self, job: Job) -> Message {
+        let resume = match &self.state {
+            DollyState::Ready(range) => Some(range.clone()),
+            _ => None,
+        };
+        self.state = DollyState::Playing(Playing::Job { job, resume });
+        Message::Ack
+    }
+
+    // The keyframe as it plays, aimed at the subject when tracking
+    #[cfg(feature = "timelapse")]
+    fn track(&self, k: &Keyframe) -> Keyframe {
+        match &self.tracking {
+            Some(target) => target.apply(*k),
+            None => *k,
+        }
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn ends(&self, first: &Keyframe, last: &Keyframe) -> Ends {
+        plan::positions(&self.track(first))..plan::positions(&self.track(last))
+    }
+
+    // The keyframes as tracked, and the pan and tilt in between them, which
+    // turn fastest when the slider passes the subject
+    #[cfg(feature = "timelapse")]
+    fn check_take(
+        &self,
+        limits: &[Limits; AXES],
+        keyframes: &[Keyframe],
+    ) -> Result<(), Infeasible> {
+        plan::check_each(limits, keyframes.iter().map(|k| self.track(k)))?;
+        match &self.tracking {
+            Some(target) => plan::check_tracked(limits, target, keyframes),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn aim_target<H: Hardware>(&mut self, hw: &mut H, aim: Aim, follow_tilt: bool) -> Message {
+        if self.is_playing() {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        let here = hw.positions();
+        let target = match aim {
+            Aim::Off => {
+                self.tracking = None;
+                self.sighting = None;
+                hw.note(Note::TrackingOff);
+                return Message::Ack;
+            }
+            Aim::Distance { distance_um } => Target::at_distance(here, distance_um, follow_tilt),
+            Aim::Sighting => match self.sighting.take() {
+                Some(first) => Target::triangulate(first, here, follow_tilt),
+                None => {
+                    self.sighting = Some(here);
+                    hw.note(Note::SightingTaken);
+                    return Message::Ack;
+                }
+            },
+        };
+
+        let Some(target) = target else {
+            hw.note(Note::NoSubject);
+            return Message::Nack(ErrorCode::InvalidValue);
+        };
+        hw.note(Note::Tracking {
+            from_rail: target.from_rail(),
+        });
+        self.tracking = Some(target);
+        Message::Ack
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn goto_init<H: Hardware>(&mut self, hw: &mut H, range: Ends, take: Take, now: u32) {
+        // already aimed at the subject from the start of the range
+        goto(hw, &range.start, now, None);
+        self.state = DollyState::Playing(Playing::GotoInit { range, take });
+    }
+
+    // The in LED blinks every second, the out LED joins it for the last one
+    #[cfg(feature = "timelapse")]
+    fn countdown<H: Hardware>(hw: &mut H, elapsed_ms: u32, left_ms: u32) {
+        hw.leds(elapsed_ms % 1000 < 200, left_ms <= 1000);
+    }
+
+    // Starts the take on the clock, the move lasts exactly `duration_ms`
+    #[cfg(feature = "timelapse")]
+    fn roll<H: Hardware>(&mut self, hw: &mut H, range: Ends, duration_ms: u32, now: u32) {
+        hw.leds(false, false);
+        hw.start(&range.end, duration_ms, now, self.tracking);
+        self.state = DollyState::Playing(Playing::Filming { range });
+        hw.note(Note::Rolling);
+    }
+
+    // Segments start on the pass clock, not when the previous one arrived,
+    // so a late update never adds up over a long sequence
+    #[cfg(feature = "timelapse")]
+    #[allow(clippy::too_many_arguments)]
+    fn play_segment<H: Hardware>(
+        &mut self,
+        hw: &mut H,
+        range: Ends,
+        pass: Pass,
+        cycle: u16,
+        segment: usize,
+        started: u32,
+        now: u32,
+    ) {
+        let keyframes = self.sequence.keyframes();
+        let next = match pass {
+            Pass::Rewind if segment == 1 => {
+                let to = range.start;
+                Some((to, plan::fastest_ms(&hw.limits(), &hw.positions(), &to), 0))
+            }
+            _ => playback::segment(keyframes, pass, segment).map(|s| {
+                let to = plan::positions(&self.track(&s.to));
+                (to, s.duration_ms, s.offset_ms)
+            }),
+        };
+
+        let Some((to, duration_ms, offset)) = next else {
+            self.end_pass(hw, range, pass, cycle, now);
+            return;
+        };
+        hw.start(
+            &to,
+            duration_ms,
+            started.wrapping_add(offset),
+            self.tracking,
+        );
+        self.state = DollyState::Playing(Playing::Moving {
+            range,
+            pass,
+            cycle,
+            segment,
+            started,
+        });
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn end_pass<H: Hardware>(&mut self, hw: &mut H, range: Ends, pass: Pass, cycle: u16, now: u32) {
+        let Some((dwell_ms, next, cycle)) = self.playback.after(pass, cycle) else {
+            hw.note(Note::SequenceFinished);
+            self.state = DollyState::Ready(range);
+            return;
+        };
+
+        if next == Pass::Forward {
+            hw.note(Note::Cycle(cycle.wrapping_add(1)));
+        }
+        // even without a dwell, so a one keyframe sequence can't spin here
+        self.state = DollyState::Playing(Playing::Dwelling {
+            range,
+            since: now,
+            dwell_ms,
+            next,
+            cycle,
+        });
+    }
+
+    // Every motion update, the rig's and the run's
+    pub fn update<H: Hardware>(&mut self, hw: &mut H, now: u32) {
+        // waiting on the clock rather than on the axes
+        #[cfg(feature = "timelapse")]
+        match &self.state {
+            DollyState::Playing(Playing::Dwelling {
+                range,
+                since,
+                dwell_ms,
+                next,
+                cycle,
+            }) => {
+                if now.wrapping_sub(*since) >= *dwell_ms {
+                    self.play_segment(hw, range.clone(), *next, *cycle, 1, now, now);
+                }
+                return;
+            }
+            DollyState::Playing(Playing::Preroll {
+                range,
+                since,
+                preroll_ms,
+                duration_ms,
+            }) => {
+                let elapsed = now.wrapping_sub(*since);
+                match preroll_ms.checked_sub(elapsed).filter(|&left| left > 0) {
+                    Some(left) => Self::countdown(hw, elapsed, left),
+                    None => self.roll(hw, range.clone(), *duration_ms, now),
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // there is nothing to arrive at without a run to play
+        #[cfg_attr(
+            not(any(feature = "timelapse", feature = "panorama", feature = "focus-stack")),
+            allow(unused_variables)
+        )]
+        let arrived = hw.update(now);
+        match &mut self.state {
+            DollyState::Aborting { resume } if !hw.is_running() => {
+                hw.note(Note::Aborted);
+                self.state = DollyState::idle(resume.take());
+            }
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            DollyState::Playing(_) => self.advance(hw, now, arrived),
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            state if state.is_paused() => self.update_pause(hw, now, arrived),
+            _ => {}
+        }
+    }
+
+    // Moves the run on, `arrived` when its move just did
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    fn advance<H: Hardware>(&mut self, hw: &mut H, now: u32, arrived: bool) {
+        match &mut self.state {
+            #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+            DollyState::Playing(Playing::Job { job, resume }) => {
+                let finished = job.update(hw, now, arrived);
+                if finished {
+                    self.state = DollyState::idle(resume.take());
+                }
+            }
+            #[cfg(feature = "timelapse")]
+            _ if arrived => self.arrive(hw, now),
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn arrive<H: Hardware>(&mut self, hw: &mut H, now: u32) {
+        let DollyState::Playing(playing) = &self.state else {
+            return;
+        };
+        match playing {
+            Playing::GotoInit {
+                range,
+                take: Take::Sequence,
+            } => self.play_segment(hw, range.clone(), Pass::Forward, 0, 1, now, now),
+            Playing::GotoInit {
+                range,
+                take:
+                    Take::Video {
+                        duration_ms,
+                        preroll_ms,
+                    },
+            } => {
+                self.state = DollyState::Playing(Playing::Preroll {
+                    range: range.clone(),
+                    since: now,
+                    preroll_ms: *preroll_ms,
+                    duration_ms: *duration_ms,
+                })
+            }
+            Playing::Filming { range } => {
+                hw.note(Note::VideoFinished);
+                self.state = DollyState::Ready(range.clone());
+            }
+            Playing::Moving {
+                range,
+                pass,
+                cycle,
+                segment,
+                started,
+            } => {
+                let (pass, cycle, segment, started) = (*pass, *cycle, *segment, *started);
+                self.play_segment(hw, range.clone(), pass, cycle, segment + 1, started, now)
+            }
+            _ => {}
+        }
+    }
+
+    // Brakes whatever is playing to a stop on its path, then leaves the
+    // dolly ready with the range it had
+    pub fn abort<H: Hardware>(&mut self, hw: &mut H, now: u32) -> Message {
+        // the range to be ready with afterwards, if there was a run to stop
+        let stopped = match &mut self.state {
+            #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+            DollyState::Playing(playing)
+            | DollyState::Pausing { held: playing, .. }
+            | DollyState::Paused { held: playing, .. }
+            | DollyState::Resuming(playing) => Some(playing.take_ends()),
+            DollyState::Aborting { .. } => return Message::Ack,
+            _ => None,
+        };
+        let Some(range) = stopped else {
+            // a jog has no run to end, it only stands still
+            if !hw.is_jogging() {
+                return Message::Nack(ErrorCode::NotReady);
+            }
+            hw.halt();
+            hw.note(Note::JogStopped);
+            return Message::Ack;
+        };
+
+        hw.abort(now);
+        hw.shutter(false);
+        hw.leds(false, false);
+        self.state = DollyState::Aborting { resume: range };
+        hw.note(Note::Aborting);
+        Message::Ack
+    }
+
+    // A move brakes on its path, a dwell, countdown or settle just stops
+    // counting down. The shutter lets go, an exposure cut short is shot
+    // again on resume.
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    fn pause<H: Hardware>(&mut self, hw: &mut H, now: u32) -> Message {
+        let state = core::mem::replace(&mut self.state, DollyState::SetInitPos);
+        let (DollyState::Playing(held) | DollyState::Resuming(held)) = state else {
+            self.state = state;
+            return Message::Nack(ErrorCode::NotReady);
+        };
+
+        hw.shutter(false);
+        hw.leds(false, false);
+        match hw.is_running() {
+            true => {
+                hw.pause(now);
+                self.state = DollyState::Pausing { held, since: now };
+                hw.note(Note::Pausing);
+            }
+            false => {
+                self.state = DollyState::Paused {
+                    held,
+                    since: now,
+                    arrived: false,
+                };
+                hw.note(Note::Paused);
+            }
+        }
+        Message::Ack
+    }
+
+    // Picks up where the pause left off, everything after it runs late by
+    // as long as it lasted
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    fn resume<H: Hardware>(&mut self, hw: &mut H, now: u32) -> Message {
+        let state = core::mem::replace(&mut self.state, DollyState::SetInitPos);
+        let (mut playing, since, arrived) = match state {
+            DollyState::Pausing { held, since } => (held, since, false),
+            DollyState::Paused {
+                held,
+                since,
+                arrived,
+            } => (held, since, arrived),
+            state => {
+                self.state = state;
+                return Message::Nack(ErrorCode::NotReady);
+            }
+        };
+
+        let paused_ms = now.wrapping_sub(since);
+        match &mut playing {
+            #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+            Playing::Job { job, .. } => job.resume(hw, now, paused_ms),
+            #[cfg(feature = "timelapse")]
+            timed => timed.shift(paused_ms),
+        }
+        hw.note(Note::Resumed {
+            after_ms: paused_ms,
+        });
+
+        match hw.is_running() {
+            true => {
+                hw.resume(now);
+                self.state = DollyState::Resuming(playing);
+            }
+            // held between two moves, or where the last one ended
+            false => {
+                self.state = DollyState::Playing(playing);
+                self.advance(hw, now, arrived);
+            }
+        }
+        Message::Ack
+    }
+
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    pub fn toggle_pause<H: Hardware>(&mut self, hw: &mut H, now: u32) -> Message {
+        match self.state {
+            DollyState::Pausing { .. } | DollyState::Paused { .. } => self.resume(hw, now),
+            _ => self.pause(hw, now),
+        }
+    }
+
+    // Moves on once the rig has braked or is back up to speed
+    #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+    fn update_pause<H: Hardware>(&mut self, hw: &mut H, now: u32, arrived: bool) {
+        let easing = hw.is_easing(now);
+        let state = core::mem::replace(&mut self.state, DollyState::SetInitPos);
+        self.state = match state {
+            DollyState::Pausing { held, since } if arrived || !easing => {
+                hw.note(Note::Paused);
+                DollyState::Paused {
+                    held,
+                    since,
+                    arrived,
+                }
+            }
+            DollyState::Resuming(playing) if arrived || !easing => {
+                self.state = DollyState::Playing(playing);
+                self.advance(hw, now, arrived);
+                return;
+            }
+            state => state,
+        };
+    }
+
+    // of the running pass, dwell or take, repeats aren't counted. Paused
+    // it stays what was left when the pause was asked for.
+    #[cfg(feature = "timelapse")]
+    pub fn remaining_ms<H: Hardware>(&self, hw: &H, now: u32) -> u32 {
+        match &self.state {
+            DollyState::Playing(playing) | DollyState::Resuming(playing) => {
+                self.left_ms(hw, playing, now)
+            }
+            DollyState::Pausing { held, since } | DollyState::Paused { held, since, .. } => {
+                self.left_ms(hw, held, *since)
+            }
+            _ => 0,
+        }
+    }
+
+    #[cfg(not(feature = "timelapse"))]
+    pub fn remaining_ms<H: Hardware>(&self, _hw: &H, _now: u32) -> u32 {
+        0
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn left_ms<H: Hardware>(&self, hw: &H, playing: &Playing, now: u32) -> u32 {
+        match playing {
+            Playing::Moving { pass, started, .. } => self.pass_left_ms(hw, *pass, *started, now),
+            Playing::Dwelling {
+                since, dwell_ms, ..
+            } => dwell_ms.saturating_sub(now.wrapping_sub(*since)),
+            // until the end of the take
+            Playing::Preroll {
+                since,
+                preroll_ms,
+                duration_ms,
+                ..
+            } => preroll_ms.saturating_sub(now.wrapping_sub(*since)) + duration_ms,
+            Playing::Filming { .. } => hw.remaining_ms(now),
+            _ => 0,
+        }
+    }
+
+    #[cfg(feature = "timelapse")]
+    fn pass_left_ms<H: Hardware>(&self, hw: &H, pass: Pass, started: u32, now: u32) -> u32 {
+        if pass == Pass::Rewind {
+            return hw.remaining_ms(now);
+        }
+
+        let keyframes = self.sequence.keyframes();
+        let (Some(first), Some(last)) = (keyframes.first(), keyframes.last()) else {
+            return 0;
+        };
+        let elapsed = now.wrapping_sub(started);
+        (last.time_ms - first.time_ms).saturating_sub(elapsed)
+    }
+
+    // frames a panorama or focus stack has taken, or the frame an animation is at
+    #[cfg(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack"))]
+    pub fn frame(&self) -> u32 {
+        match self.state.playing() {
+            Some(Playing::Job { job, .. }) => job.frame(),
+            _ => 0,
+        }
+    }
+
+    #[cfg(not(any(feature = "panorama", feature = "stop-motion", feature = "focus-stack")))]
+    pub fn frame(&self) -> u32 {
+        0
+    }
+
+    // Homing put off by a watchdog reset runs once it is acknowledged
+    pub fn home<H: Hardware>(&mut self, hw: &mut H) -> Result<(), H::HomingError> {
+        if self.motion_blocked {
+            self.homing_pending = true;
+            hw.note(Note::HomingPostponed);
+            return Ok(());
+        }
+
+        let homed = hw.home();
+        // a failed attempt leaves the positions unknown
+        self.homed = homed.is_ok();
+        homed
+    }
+
+    fn rehome<H: Hardware>(&mut self, hw: &mut H) {
+        if self.home(hw).is_err() {
+            hw.note(Note::BlockedUntilHomed);
+        }
+    }
+
+    // True when there was a watchdog reset to acknowledge
+    pub fn acknowledge<H: Hardware>(&mut self, hw: &mut H) -> bool {
+        if !self.motion_blocked {
+            hw.note(Note::NothingToAcknowledge);
+            return false;
+        }
+
+        self.motion_blocked = false;
+        hw.note(Note::ResetAcknowledged);
+        if self.homing_pending {
+            self.homing_pending = false;
+            self.rehome(hw);
+        }
+        true
+    }
+
+    pub fn retry_homing<H: Hardware>(&mut self, hw: &mut H) -> Message {
+        if self.motion_blocked {
+            hw.note(Note::BlockedUntilAcknowledged);
+            return Message::Nack(ErrorCode::NotReady);
+        }
+        if self.is_playing() || hw.is_running() {
+            hw.note(Note::CantHomeWhileMoving);
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        self.rehome(hw);
+        match self.homed {
+            true => Message::Ack,
+            false => Message::Nack(ErrorCode::NotReady),
+        }
+    }
+
+    // Trusts the positions as they are, e.g. on a rig without stall detection
+    pub fn skip_homing<H: Hardware>(&mut self, hw: &mut H) {
+        if self.homed {
+            hw.note(Note::AlreadyHomed);
+            return;
+        }
+        self.homed = true;
+        hw.note(Note::HomingSkipped);
+    }
+
+    #[cfg(feature = "timelapse")]
+    pub fn show_playback<H: Hardware>(&self, hw: &mut H) {
+        let p = &self.playback;
+        hw.note(Note::Playback {
+            mode: p.mode,
+            repeat: p.repeat,
+        });
+        hw.note(Note::Dwell {
+            start_ms: p.dwell_start_ms,
+            end_ms: p.dwell_end_ms,
+        });
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    plan::{Infeasible, Violation, AXIS_NAMES, AXIS_UNITS},
+    shell::Level,
+    units::Unit,
+    Mode,
+};
+
+// What the machine has to say about a run. The dolly writes it to its
+// console, the simulator to its log, in the same words.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Note {
+    SequenceStarted,
+    VideoStarted { duration_ms: u32 },
+    Rolling,
+    VideoFinished,
+    SequenceFinished,
+    Cycle(u16),
+    Pausing,
+    Paused,
+    Resumed { after_ms: u32 },
+    Aborting,
+    Aborted,
+    JogStopped,
+    NothingToPause,
+    NothingPaused,
+    NothingToAbort,
+    Playback { mode: Mode, repeat: u16 },
+    Dwell { start_ms: u32, end_ms: u32 },
+
+    TrackingOff,
+    SightingTaken,
+    Tracking { from_rail: i32 },
+    NoSubject,
+    Infeasible(Infeasible),
+
+    PanoramaStarted { frames: u32 },
+    PanoramaFinished,
+    Frame { at: u32, of: u32 },
+    ShotsTooClose { step_um: i32 },
+    FocusStackStarted { shots: u16 },
+    Shot { taken: u16, of: u16 },
+    FocusStackFinished,
+    ResumingAt { frame: u16 },
+    StopMotionAt { frame: u16, of: u16 },
+    NoFramePast { frame: u16 },
+    FrameShot { frame: u16 },
+
+    // homing put off until a watchdog reset is acknowledged
+    HomingPostponed,
+    BlockedUntilAcknowledged,
+    BlockedUntilHomed,
+    CantHomeWhileMoving,
+    AlreadyHomed,
+    HomingSkipped,
+    NothingToAcknowledge,
+    ResetAcknowledged,
+}
+
+// Where a note is written out, piece by piece so the dolly needs no
+// formatting machinery beyond its own
+pub trait Sink {
+    fn text(&mut self, text: &str);
+    fn number(&mut self, n: u32);
+    // thousandths printed as the whole unit with three decimals
+    fn quantity(&mut self, value: i32, unit: Unit);
+}
+
+impl Note {
+    pub fn level(&self) -> Level {
+        match self {
+            Note::Aborting
+            | Note::JogStopped
+            | Note::NothingToPause
+            | Note::NothingPaused
+            | Note::NothingToAbort
+            | Note::NoSubject
+            | Note::Infeasible(_)
+            | Note::ShotsTooClose { .. }
+            | Note::NoFramePast { .. }
+            | Note::HomingPostponed
+            | Note::BlockedUntilAcknowledged
+            | Note::BlockedUntilHomed
+            | Note::CantHomeWhileMoving
+            | Note::HomingSkipped => Level::Warn,
+            _ => Level::Info,
+        }
+    }
+
+    pub fn write(&self, out: &mut impl Sink) {
+        match *self {
+            Note::SequenceStarted => out.text("Sequence started"),
+            Note::VideoStarted { duration_ms } => {
+                out.text("Video move of ");
+                out.number(duration_ms);
+                out.text("ms started");
+            }
+            Note::Rolling => out.text("Rolling"),
+            Note::VideoFinished => out.text("Video move finished"),
+            Note::SequenceFinished => out.text("Sequence finished"),
+            Note::Cycle(cycle) => {
+                out.text("Cycle ");
+                out.number(cycle as u32);
+            }
+            Note::Pausing => out.text("Pausing"),
+            Note::Paused => out.text("Paused"),
+            Note::Resumed { after_ms } => {
+                out.text("Resumed after ");
+                out.number(after_ms);
+                out.text("ms");
+            }
+            Note::Aborting => out.text("Aborting"),
+            Note::Aborted => out.text("Aborted"),
+            Note::JogStopped => out.text("Jog stopped"),
+            Note::NothingToPause => out.text("Nothing to pause"),
+            Note::NothingPaused => out.text("Nothing paused"),
+            Note::NothingToAbort => out.text("Nothing to abort"),
+            Note::Playback { mode, repeat } => {
+                out.text("Playback: ");
+                out.text(mode.as_str());
+                match repeat {
+                    0 => out.text(" forever"),
+                    n => {
+                        out.text(" x");
+                        out.number(n as u32);
+                    }
+                }
+            }
+            Note::Dwell { start_ms, end_ms } => {
+                out.text("Dwell: ");
+                out.number(start_ms);
+                out.text("ms ");
+                out.number(end_ms);
+                out.text("ms");
+            }
+
+            Note::TrackingOff => out.text("Tracking off"),
+            Note::SightingTaken => {
+                out.text("Sighting taken, take another from elsewhere on the rail")
+            }
+            Note::Tracking { from_rail } => {
+                out.text("Tracking a subject ");
+                out.quantity(from_rail, Unit::Micrometre);
+                out.text(" from the rail");
+            }
+            Note::NoSubject => out.text("Can't place the subject in front of the rail"),
+            Note::Infeasible(e) => write_infeasible(&e, out),
+
+            Note::PanoramaStarted { frames } => {
+                out.text("Panorama of ");
+                out.number(frames);
+                out.text(" frames started");
+            }
+            Note::PanoramaFinished => out.text("Panorama finished"),
+            Note::Frame { at, of } => {
+                out.text("Frame ");
+                out.number(at);
+                out.text("/");
+                out.number(of);
+            }
+            Note::ShotsTooClose { step_um } => {
+                out.text("Shots closer than the slider's ");
+                out.quantity(step_um, Unit::Micrometre);
+                out.text(" step");
+            }
+            Note::FocusStackStarted { shots } => {
+                out.text("Focus stack of ");
+                out.number(shots as u32);
+                out.text(" shots started");
+            }
+            Note::Shot { taken, of } => {
+                out.text("Shot ");
+                out.number(taken as u32);
+                out.text("/");
+                out.number(of as u32);
+            }
+            Note::FocusStackFinished => out.text("Focus stack finished"),
+            Note::ResumingAt { frame } => {
+                out.text("Resuming at frame ");
+                out.number(frame as u32);
+            }
+            Note::StopMotionAt { frame, of } => {
+                out.text("Stop motion at frame ");
+                out.number(frame as u32);
+                out.text("/");
+                out.number(of as u32);
+            }
+            Note::NoFramePast { frame } => {
+                out.text("No frame past ");
+                out.number(frame as u32);
+            }
+            Note::FrameShot { frame } => {
+                out.text("Frame ");
+                out.number(frame as u32);
+                out.text(" shot");
+            }
+
+            Note::HomingPostponed => out.text("Homing skipped"),
+            Note::BlockedUntilAcknowledged => out.text("Motion blocked until `ack`"),
+            Note::BlockedUntilHomed => {
+                out.text("Motion blocked until `home` succeeds or `home skip`")
+            }
+            Note::CantHomeWhileMoving => out.text("Can't home while moving"),
+            Note::AlreadyHomed => out.text("Already homed"),
+            Note::HomingSkipped => {
+                out.text("Homing skipped, soft limits count from where the axes stand")
+            }
+            Note::NothingToAcknowledge => out.text("Nothing to acknowledge"),
+            Note::ResetAcknowledged => out.text("Watchdog reset acknowledged"),
+        }
+    }
+}
+
+fn write_infeasible(e: &Infeasible, out: &mut impl Sink) {
+    let axis = e.axis as usize;
+    let unit = AXIS_UNITS[axis];
+    out.text("Segment ");
+    out.number(e.segment as u32);
+    out.text(": ");
+    out.text(AXIS_NAMES[axis]);
+
+    match e.violation {
+        Violation::Travel { position, limit } => {
+            out.text(" at ");
+            out.quantity(position, unit);
+            out.text(" is beyond ");
+            out.quantity(limit, unit);
+        }
+        Violation::Duration {
+            available_ms,
+            needed_ms,
+        } => {
+            out.text(" needs ");
+            out.number(needed_ms);
+            out.text("ms, has ");
+            out.number(available_ms);
+            out.text("ms");
+        }
+        Violation::Velocity { peak, limit } => {
+            out.text(" tracks at ");
+            out.quantity(peak as i32, unit);
+            out.text("/s, beyond ");
+            out.quantity(limit as i32, unit);
+            out.text("/s");
+        }
+        Violation::Acceleration { peak, limit } => {
+            out.text(" tracks at ");
+            out.quantity(peak as i32, unit);
+            out.text("/s², beyond ");
+            out.quantity(limit as i32, unit);
+            out.text("/s²");
+        }
+    }
+}
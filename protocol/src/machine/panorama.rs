@@ -0,0 +1,103 @@
+use crate::{
+    panorama::{self, Shoot},
+    Message, Panorama,
+};
+
+use super::{goto, Hardware, Note};
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Moving,
+    // letting the rig stop shaking
+    Settling,
+    Exposing,
+}
+
+// crate::panorama::Shoot says where the frames are, this is where the dolly
+// is with each of them
+pub struct Run {
+    shoot: Shoot,
+    phase: Phase,
+    since: u32,
+}
+
+impl Run {
+    // Off to the first frame, or the refusal to send back
+    pub fn start<H: Hardware>(p: &Panorama, hw: &mut H, now: u32) -> Result<Self, Message> {
+        if let Err(e) = panorama::check(&hw.limits(), p) {
+            hw.note(Note::Infeasible(e));
+            return Err(Message::Infeasible(e));
+        }
+
+        let shoot = Shoot::new(p).map_err(Message::Nack)?;
+        hw.note(Note::PanoramaStarted {
+            frames: shoot.frames(),
+        });
+
+        let mut run = Self {
+            shoot,
+            phase: Phase::Moving,
+            since: now,
+        };
+        run.next_frame(hw, now);
+        Ok(run)
+    }
+
+    // Settles for as long as it still had to after the pause, an exposure
+    // it cut short starts over
+    pub fn resume<H: Hardware>(&mut self, hw: &mut H, now: u32, paused_ms: u32) {
+        match self.phase {
+            Phase::Exposing => {
+                hw.shutter(true);
+                self.since = now;
+            }
+            _ => self.since = self.since.wrapping_add(paused_ms),
+        }
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.shoot.taken
+    }
+
+    // Moves to the next frame with the slider where it is, true when there
+    // is none left
+    fn next_frame<H: Hardware>(&mut self, hw: &mut H, now: u32) -> bool {
+        let Some((pan, tilt)) = self.shoot.target() else {
+            hw.note(Note::PanoramaFinished);
+            return true;
+        };
+
+        let to = [hw.positions()[0], pan, tilt];
+        goto(hw, &to, now, None);
+        self.phase = Phase::Moving;
+        self.since = now;
+        false
+    }
+
+    // Settles once the move has arrived, then holds the shutter
+    pub fn update<H: Hardware>(&mut self, hw: &mut H, now: u32, arrived: bool) -> bool {
+        let elapsed = now.wrapping_sub(self.since);
+        match self.phase {
+            Phase::Moving if arrived => {
+                self.phase = Phase::Settling;
+                self.since = now;
+            }
+            Phase::Settling if elapsed >= self.shoot.settle_ms => {
+                hw.shutter(true);
+                self.phase = Phase::Exposing;
+                self.since = now;
+            }
+            Phase::Exposing if elapsed >= self.shoot.exposure_ms => {
+                hw.shutter(false);
+                self.shoot.advance();
+                hw.note(Note::Frame {
+                    at: self.shoot.taken,
+                    of: self.shoot.frames(),
+                });
+                return self.next_frame(hw, now);
+            }
+            _ => {}
+        }
+        false
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    plan, stopmotion::Animation, tracking::Target, ErrorCode, Keyframe, Message, Step, StopMotion,
+};
+
+use super::{goto, Hardware, Note};
+
+#[derive(Clone, Copy)]
+enum Stage {
+    // waiting for the next step
+    Holding,
+    Moving { shoot: bool },
+    Settling,
+    Exposing,
+}
+
+// Where an animation is between steps, its frames aimed at `tracking`
+pub struct Run {
+    animation: Animation,
+    stage: Stage,
+    since: u32,
+    tracking: Option<Target>,
+}
+
+impl Run {
+    // Off to the frame it is at, or the refusal to send back
+    pub fn start<H: Hardware>(
+        (first, last): (&Keyframe, &Keyframe),
+        setup: &StopMotion,
+        tracking: Option<Target>,
+        hw: &mut H,
+        now: u32,
+    ) -> Result<Self, Message> {
+        let mut animation = Animation::new(first, last, setup).map_err(Message::Nack)?;
+        // the frames lie between the ends, a tracking head never turns past 90°
+        let ends = [first, last].map(|k| plan::positions(&aim(tracking, k)));
+        if let Err(e) = plan::check_travel(&hw.limits(), ends) {
+            hw.note(Note::Infeasible(e));
+            return Err(Message::Infeasible(e));
+        }
+
+        if setup.resume && animation.restore(&hw.load()) {
+            hw.note(Note::ResumingAt {
+                frame: animation.frame(),
+            });
+        }
+        hw.store(&animation.record());
+        hw.note(Note::StopMotionAt {
+            frame: animation.frame(),
+            of: animation.steps(),
+        });
+
+        let mut run = Self {
+            animation,
+            stage: Stage::Holding,
+            since: now,
+            tracking,
+        };
+        let shoot = run.animation.shoots();
+        run.goto_frame(hw, shoot, now);
+        Ok(run)
+    }
+
+    // Settles for as long as it still had to after the pause, an exposure
+    // it cut short starts over
+    pub fn resume<H: Hardware>(&mut self, hw: &mut H, now: u32, paused_ms: u32) {
+        match self.stage {
+            Stage::Exposing => {
+                hw.shutter(true);
+                self.since = now;
+            }
+            _ => self.since = self.since.wrapping_add(paused_ms),
+        }
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.animation.frame() as u32
+    }
+
+    // Only between steps, a step while the last one moves or shoots is refused
+    pub fn step<H: Hardware>(&mut self, step: Step, hw: &mut H, now: u32) -> Message {
+        if !matches!(self.stage, Stage::Holding) {
+            return Message::Nack(ErrorCode::NotReady);
+        }
+
+        let animation = &mut self.animation;
+        let Some(shoot) = animation.take(step) else {
+            hw.note(Note::NoFramePast {
+                frame: animation.frame(),
+            });
+            return Message::Nack(ErrorCode::OutOfRange);
+        };
+        hw.store(&animation.record());
+        hw.note(Note::Frame {
+            at: animation.frame() as u32,
+            of: animation.steps() as u32,
+        });
+
+        self.goto_frame(hw, shoot, now);
+        Message::Ack
+    }
+
+    // Moves to the current frame of the animation, `shoot` fires the shutter
+    // once it has settled there
+    fn goto_frame<H: Hardware>(&mut self, hw: &mut H, shoot: bool, now: u32) {
+        self.stage = Stage::Moving { shoot };
+        self.since = now;
+
+        let k = self.animation.position();
+        let to = plan::positions(&aim(self.tracking, &k));
+        goto(hw, &to, now, self.tracking);
+    }
+
+    // An animation only ends when aborted
+    pub fn update<H: Hardware>(&mut self, hw: &mut H, now: u32, arrived: bool) -> bool {
+        let elapsed = now.wrapping_sub(self.since);
+        match self.stage {
+            Stage::Moving { shoot: false } if arrived => self.stage = Stage::Holding,
+            Stage::Moving { shoot: true } if arrived => {
+                self.stage = Stage::Settling;
+                self.since = now;
+            }
+            Stage::Settling if elapsed >= self.animation.settle_ms => {
+                hw.shutter(true);
+                self.stage = Stage::Exposing;
+                self.since = now;
+            }
+            Stage::Exposing if elapsed >= self.animation.exposure_ms => {
+                hw.shutter(false);
+                self.stage = Stage::Holding;
+                hw.note(Note::FrameShot {
+                    frame: self.animation.frame(),
+                });
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+// The keyframe as it plays, aimed at the subject when tracking
+fn aim(tracking: Option<Target>, k: &Keyframe) -> Keyframe {
+    match tracking {
+        Some(target) => target.apply(*k),
+        None => *k,
+    }
+}
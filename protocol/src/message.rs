@@ -0,0 +1,987 @@
+use crate::{
+    plan::{Infeasible, Violation},
+    Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Keyframe {
+    pub time_ms: u32,
+    // micrometres, pan and tilt in millidegrees
+    pub slider: i32,
+    pub pan: i32,
+    pub tilt: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    SetInitPos = 0,
+    SetEndPos = 1,
+    GotoInit = 2,
+    Ready = 3,
+    Moving = 4,
+    // resting at either end between playback passes
+    Dwelling = 5,
+    // counting down before a video move
+    Preroll = 6,
+    // visiting the frames of a panorama
+    Panorama = 7,
+    // at a frame of a stop-motion animation, waiting for the next step
+    StopMotion = 8,
+    // stepping the slider through a focus stack
+    FocusStack = 9,
+    // braking on the planned path after PauseSequence
+    Pausing = 10,
+    // holding part way through a sequence
+    Paused = 11,
+    // getting back up to speed on the shifted schedule
+    Resuming = 12,
+    // braking before being ready again
+    Aborting = 13,
+}
+
+impl State {
+    pub fn from_u8(state: u8) -> Option<Self> {
+        match state {
+            0 => Some(State::SetInitPos),
+            1 => Some(State::SetEndPos),
+            2 => Some(State::GotoInit),
+            3 => Some(State::Ready),
+            4 => Some(State::Moving),
+            5 => Some(State::Dwelling),
+            6 => Some(State::Preroll),
+            7 => Some(State::Panorama),
+            8 => Some(State::StopMotion),
+            9 => Some(State::FocusStack),
+            10 => Some(State::Pausing),
+            11 => Some(State::Paused),
+            12 => Some(State::Resuming),
+            13 => Some(State::Aborting),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::SetInitPos => "SetInitPos",
+            State::SetEndPos => "SetEndPos",
+            State::GotoInit => "GotoInit",
+            State::Ready => "Ready",
+            State::Moving => "Moving",
+            State::Dwelling => "Dwelling",
+            State::Preroll => "Preroll",
+            State::Panorama => "Panorama",
+            State::StopMotion => "StopMotion",
+            State::FocusStack => "FocusStack",
+            State::Pausing => "Pausing",
+            State::Paused => "Paused",
+            State::Resuming => "Resuming",
+            State::Aborting => "Aborting",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Telemetry {
+    pub uptime_ms: u32,
+    pub state: State,
+    // slider in micrometres, pan and tilt in millidegrees
+    pub position: [i32; 3],
+    // the same units per second
+    pub velocity: [i32; 3],
+    pub frame: u32,
+    pub remaining_s: u32,
+    pub battery_mv: u16,
+    pub loop_hz: u16,
+    pub overruns: u16,
+    pub max_lateness_ms: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lens {
+    // field of view of one frame in millidegrees
+    Fov {
+        horizontal: u32,
+        vertical: u32,
+    },
+    // focal length and sensor size in micrometres, the dolly works out the view
+    Optics {
+        focal_um: u32,
+        sensor_width_um: u32,
+        sensor_height_um: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Panorama {
+    pub lens: Lens,
+    // of neighbouring frames, in percent of a frame
+    pub overlap_pct: u8,
+    // the area to cover in millidegrees, a pan span of 360° goes all the way round
+    pub pan_from: i32,
+    pub pan_to: i32,
+    pub tilt_from: i32,
+    pub tilt_to: i32,
+    // rest after each move before the shutter, then how long to hold it
+    pub settle_ms: u16,
+    pub exposure_ms: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aim {
+    // remember where the camera looks now, a second sighting from elsewhere
+    // on the rail locates the subject
+    Sighting,
+    // the subject is `distance_um` along the current line of sight
+    Distance { distance_um: u32 },
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StopMotion {
+    // increments from the first keyframe to the last, frame 0 is the first
+    pub steps: u16,
+    // rest after each shooting step before the shutter, then how long to
+    // hold it, 0 leaves the camera alone
+    pub settle_ms: u16,
+    pub exposure_ms: u16,
+    // carry on at the frame the dolly remembers for the same move and steps
+    pub resume: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Back = 0,
+    Forward = 1,
+    // forward, then the shutter
+    Shoot = 2,
+}
+
+impl Step {
+    pub fn from_u8(step: u8) -> Option<Self> {
+        match step {
+            0 => Some(Step::Back),
+            1 => Some(Step::Forward),
+            2 => Some(Step::Shoot),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spacing {
+    // micrometres between shots, the last one may be closer to the far limit
+    Step { step_um: u32 },
+    // spread evenly from the near limit to the far one
+    Shots { shots: u16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FocusStack {
+    // slider positions in micrometres, shots go from near to far
+    pub near_um: i32,
+    pub far_um: i32,
+    pub spacing: Spacing,
+    // overshoot behind the near limit before the first shot, so every shot
+    // is approached from the same side
+    pub backlash_um: u16,
+    pub settle_ms: u16,
+    pub exposure_ms: u16,
+    // back to the near limit after the last shot
+    pub return_to_start: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingId {
+    LogLevel = 0,
+    TxFullPolicy = 1,
+    // 0 disables the stream
+    TelemetryPeriod = 2,
+    // 0 text lines, 1 binary frames
+    TelemetryFormat = 3,
+    // see playback::Mode
+    PlaybackMode = 4,
+    // 0 repeats until stopped
+    PlaybackRepeat = 5,
+    DwellStart = 6,
+    DwellEnd = 7,
+}
+
+impl SettingId {
+    pub fn from_u8(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(SettingId::LogLevel),
+            1 => Some(SettingId::TxFullPolicy),
+            2 => Some(SettingId::TelemetryPeriod),
+            3 => Some(SettingId::TelemetryFormat),
+            4 => Some(SettingId::PlaybackMode),
+            5 => Some(SettingId::PlaybackRepeat),
+            6 => Some(SettingId::DwellStart),
+            7 => Some(SettingId::DwellEnd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    Malformed = 0,
+    UnexpectedMessage = 1,
+    OutOfRange = 2,
+    InvalidValue = 3,
+    NotReady = 4,
+    Unsupported = 5,
+}
+
+impl ErrorCode {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(ErrorCode::Malformed),
+            1 => Some(ErrorCode::UnexpectedMessage),
+            2 => Some(ErrorCode::OutOfRange),
+            3 => Some(ErrorCode::InvalidValue),
+            4 => Some(ErrorCode::NotReady),
+            5 => Some(ErrorCode::Unsupported),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    Ping,
+    Ack,
+    Nack(ErrorCode),
+    // lifts the motion block left by a watchdog reset
+    AcknowledgeReset,
+    // homes the axes again after a failed attempt, Nack when it fails again
+    Home,
+    // trusts the positions as they stand, for a rig without stall detection
+    SkipHoming,
+
+    ClearKeyframes,
+    UploadKeyframe { index: u8, keyframe: Keyframe },
+    ReadKeyframe { index: u8 },
+    Keyframe { index: u8, keyframe: Keyframe },
+
+    ReadSetting { id: SettingId },
+    WriteSetting { id: SettingId, value: i32 },
+    Setting { id: SettingId, value: i32 },
+
+    StartSequence,
+    // brakes whatever is playing to a stop, then ready again
+    StopSequence,
+    // a sequence pass or dwell, its schedule shifts by the time it is held
+    PauseSequence,
+    ResumeSequence,
+    // reply to StartSequence and StartVideo when a segment exceeds the axis limits
+    Infeasible(Infeasible),
+    // one move from the first keyframe to the last taking exactly `duration_ms`,
+    // after counting down `preroll_s` seconds at the start
+    StartVideo { duration_ms: u32, preroll_s: u8 },
+    // frames over a pan and tilt grid, stopped with StopSequence
+    StartPanorama(Panorama),
+    // keeps pan, and tilt with `follow_tilt`, on a subject while the slider moves
+    AimTarget { aim: Aim, follow_tilt: bool },
+    // one increment of the move from the first keyframe to the last per
+    // step, stopped with StopSequence
+    StartAnimation(StopMotion),
+    StepFrame(Step),
+    // slider only, pan and tilt stay where they are, stopped with StopSequence
+    StartFocusStack(FocusStack),
+
+    // 0 stops the stream
+    StreamTelemetry { period_ms: u16 },
+    Telemetry(Telemetry),
+}
+
+mod kind {
+    pub const PING: u8 = 0x01;
+    pub const ACK: u8 = 0x02;
+    pub const NACK: u8 = 0x03;
+    pub const ACKNOWLEDGE_RESET: u8 = 0x04;
+    pub const HOME: u8 = 0x05;
+    pub const SKIP_HOMING: u8 = 0x06;
+    pub const CLEAR_KEYFRAMES: u8 = 0x10;
+    pub const UPLOAD_KEYFRAME: u8 = 0x11;
+    pub const READ_KEYFRAME: u8 = 0x12;
+    pub const KEYFRAME: u8 = 0x13;
+    pub const READ_SETTING: u8 = 0x20;
+    pub const WRITE_SETTING: u8 = 0x21;
+    pub const SETTING: u8 = 0x22;
+    pub const START_SEQUENCE: u8 = 0x30;
+    pub const STOP_SEQUENCE: u8 = 0x31;
+    pub const PAUSE_SEQUENCE: u8 = 0x32;
+    pub const RESUME_SEQUENCE: u8 = 0x33;
+    pub const INFEASIBLE: u8 = 0x34;
+    pub const START_VIDEO: u8 = 0x35;
+    pub const START_PANORAMA: u8 = 0x36;
+    pub const AIM_TARGET: u8 = 0x37;
+    pub const START_ANIMATION: u8 = 0x38;
+    pub const STEP_FRAME: u8 = 0x39;
+    pub const START_FOCUS_STACK: u8 = 0x3A;
+    pub const STREAM_TELEMETRY: u8 = 0x40;
+    pub const TELEMETRY: u8 = 0x41;
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, v: u8) -> Result<(), Error> {
+        self.bytes(&[v])
+    }
+
+    fn u16(&mut self, v: u16) -> Result<(), Error> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn u32(&mut self, v: u32) -> Result<(), Error> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn i32(&mut self, v: i32) -> Result<(), Error> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn keyframe(&mut self, k: &Keyframe) -> Result<(), Error> {
+        self.u32(k.time_ms)?;
+        self.i32(k.slider)?;
+        self.i32(k.pan)?;
+        self.i32(k.tilt)
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if self.buf.len() < N {
+            return Err(Error::Malformed);
+        }
+        let (head, tail) = self.buf.split_at(N);
+        self.buf = tail;
+
+        let mut out = [0; N];
+        out.copy_from_slice(head);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn keyframe(&mut self) -> Result<Keyframe, Error> {
+        Ok(Keyframe {
+            time_ms: self.u32()?,
+            slider: self.i32()?,
+            pan: self.i32()?,
+            tilt: self.i32()?,
+        })
+    }
+
+    fn setting_id(&mut self) -> Result<SettingId, Error> {
+        SettingId::from_u8(self.u8()?).ok_or(Error::Malformed)
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        match self.buf.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Malformed),
+        }
+    }
+}
+
+impl Message {
+    // Writes the kind byte followed by the payload
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut w = Writer { buf, len: 0 };
+
+        match self {
+            Message::Ping => w.u8(kind::PING)?,
+            Message::Ack => w.u8(kind::ACK)?,
+            Message::Nack(code) => {
+                w.u8(kind::NACK)?;
+                w.u8(*code as u8)?;
+            }
+            Message::AcknowledgeReset => w.u8(kind::ACKNOWLEDGE_RESET)?,
+            Message::Home => w.u8(kind::HOME)?,
+            Message::SkipHoming => w.u8(kind::SKIP_HOMING)?,
+            Message::ClearKeyframes => w.u8(kind::CLEAR_KEYFRAMES)?,
+            Message::UploadKeyframe { index, keyframe } => {
+                w.u8(kind::UPLOAD_KEYFRAME)?;
+                w.u8(*index)?;
+                w.keyframe(keyframe)?;
+            }
+            Message::ReadKeyframe { index } => {
+                w.u8(kind::READ_KEYFRAME)?;
+                w.u8(*index)?;
+            }
+            Message::Keyframe { index, keyframe } => {
+                w.u8(kind::KEYFRAME)?;
+                w.u8(*index)?;
+                w.keyframe(keyframe)?;
+            }
+            Message::ReadSetting { id } => {
+                w.u8(kind::READ_SETTING)?;
+                w.u8(*id as u8)?;
+            }
+            Message::WriteSetting { id, value } => {
+                w.u8(kind::WRITE_SETTING)?;
+                w.u8(*id as u8)?;
+                w.i32(*value)?;
+            }
+            Message::Setting { id, value } => {
+                w.u8(kind::SETTING)?;
+                w.u8(*id as u8)?;
+                w.i32(*value)?;
+            }
+            Message::StartSequence => w.u8(kind::START_SEQUENCE)?,
+            Message::StopSequence => w.u8(kind::STOP_SEQUENCE)?,
+            Message::PauseSequence => w.u8(kind::PAUSE_SEQUENCE)?,
+            Message::ResumeSequence => w.u8(kind::RESUME_SEQUENCE)?,
+            Message::Infeasible(i) => {
+                w.u8(kind::INFEASIBLE)?;
+                w.u8(i.segment)?;
+                w.u8(i.axis)?;
+                match i.violation {
+                    Violation::Travel { position, limit } => {
+                        w.u8(0)?;
+                        w.i32(position)?;
+                        w.i32(limit)?;
+                    }
+                    Violation::Duration {
+                        available_ms,
+                        needed_ms,
+                    } => {
+                        w.u8(1)?;
+                        w.u32(available_ms)?;
+                        w.u32(needed_ms)?;
+                    }
+                    Violation::Velocity { peak, limit } => {
+                        w.u8(2)?;
+                        w.u32(peak)?;
+                        w.u32(limit)?;
+                    }
+                    Violation::Acceleration { peak, limit } => {
+                        w.u8(3)?;
+                        w.u32(peak)?;
+                        w.u32(limit)?;
+                    }
+                }
+            }
+            Message::StartVideo {
+                duration_ms,
+                preroll_s,
+            } => {
+                w.u8(kind::START_VIDEO)?;
+                w.u32(*duration_ms)?;
+                w.u8(*preroll_s)?;
+            }
+            Message::StartPanorama(p) => {
+                w.u8(kind::START_PANORAMA)?;
+                match p.lens {
+                    Lens::Fov {
+                        horizontal,
+                        vertical,
+                    } => {
+                        w.u8(0)?;
+                        w.u32(horizontal)?;
+                        w.u32(vertical)?;
+                    }
+                    Lens::Optics {
+                        focal_um,
+                        sensor_width_um,
+                        sensor_height_um,
+                    } => {
+                        w.u8(1)?;
+                        w.u32(focal_um)?;
+                        w.u32(sensor_width_um)?;
+                        w.u32(sensor_height_um)?;
+                    }
+                }
+                w.u8(p.overlap_pct)?;
+                w.i32(p.pan_from)?;
+                w.i32(p.pan_to)?;
+                w.i32(p.tilt_from)?;
+                w.i32(p.tilt_to)?;
+                w.u16(p.settle_ms)?;
+                w.u16(p.exposure_ms)?;
+            }
+            Message::AimTarget { aim, follow_tilt } => {
+                w.u8(kind::AIM_TARGET)?;
+                match aim {
+                    Aim::Sighting => w.u8(0)?,
+                    Aim::Distance { distance_um } => {
+                        w.u8(1)?;
+                        w.u32(*distance_um)?;
+                    }
+                    Aim::Off => w.u8(2)?,
+                }
+                w.u8(*follow_tilt as u8)?;
+            }
+            Message::StartAnimation(a) => {
+                w.u8(kind::START_ANIMATION)?;
+                w.u16(a.steps)?;
+                w.u16(a.settle_ms)?;
+                w.u16(a.exposure_ms)?;
+                w.u8(a.resume as u8)?;
+            }
+            Message::StepFrame(step) => {
+                w.u8(kind::STEP_FRAME)?;
+                w.u8(*step as u8)?;
+            }
+            Message::StartFocusStack(f) => {
+                w.u8(kind::START_FOCUS_STACK)?;
+                w.i32(f.near_um)?;
+                w.i32(f.far_um)?;
+                match f.spacing {
+                    Spacing::Step { step_um } => {
+                        w.u8(0)?;
+                        w.u32(step_um)?;
+                    }
+                    Spacing::Shots { shots } => {
+                        w.u8(1)?;
+                        w.u16(shots)?;
+                    }
+                }
+                w.u16(f.backlash_um)?;
+                w.u16(f.settle_ms)?;
+                w.u16(f.exposure_ms)?;
+                w.u8(f.return_to_start as u8)?;
+            }
+            Message::StreamTelemetry { period_ms } => {
+                w.u8(kind::STREAM_TELEMETRY)?;
+                w.u16(*period_ms)?;
+            }
+            Message::Telemetry(t) => {
+                w.u8(kind::TELEMETRY)?;
+                w.u32(t.uptime_ms)?;
+                w.u8(t.state as u8)?;
+                for v in t.position.iter().chain(t.velocity.iter()) {
+                    w.i32(*v)?;
+                }
+                w.u32(t.frame)?;
+                w.u32(t.remaining_s)?;
+                w.u16(t.battery_mv)?;
+                w.u16(t.loop_hz)?;
+                w.u16(t.overruns)?;
+                w.u16(t.max_lateness_ms)?;
+            }
+        }
+
+        Ok(w.len)
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader { buf };
+
+        let message = match r.u8()? {
+            kind::PING => Message::Ping,
+            kind::ACK => Message::Ack,
+            kind::NACK => Message::Nack(ErrorCode::from_u8(r.u8()?).ok_or(Error::Malformed)?),
+            kind::ACKNOWLEDGE_RESET => Message::AcknowledgeReset,
+            kind::HOME => Message::Home,
+            kind::SKIP_HOMING => Message::SkipHoming,
+            kind::CLEAR_KEYFRAMES => Message::ClearKeyframes,
+            kind::UPLOAD_KEYFRAME => Message::UploadKeyframe {
+                index: r.u8()?,
+                keyframe: r.keyframe()?,
+            },
+            kind::READ_KEYFRAME => Message::ReadKeyframe { index: r.u8()? },
+            kind::KEYFRAME => Message::Keyframe {
+                index: r.u8()?,
+                keyframe: r.keyframe()?,
+            },
+            kind::READ_SETTING => Message::ReadSetting {
+                id: r.setting_id()?,
+            },
+            kind::WRITE_SETTING => Message::WriteSetting {
+                id: r.setting_id()?,
+                value: r.i32()?,
+            },
+            kind::SETTING => Message::Setting {
+                id: r.setting_id()?,
+                value: r.i32()?,
+            },
+            kind::START_SEQUENCE => Message::StartSequence,
+            kind::STOP_SEQUENCE => Message::StopSequence,
+            kind::PAUSE_SEQUENCE => Message::PauseSequence,
+            kind::RESUME_SEQUENCE => Message::ResumeSequence,
+            kind::INFEASIBLE => Message::Infeasible(Infeasible {
+                segment: r.u8()?,
+                axis: r.u8()?,
+                violation: match r.u8()? {
+                    0 => Violation::Travel {
+                        position: r.i32()?,
+                        limit: r.i32()?,
+                    },
+                    1 => Violation::Duration {
+                        available_ms: r.u32()?,
+                        needed_ms: r.u32()?,
+                    },
+                    2 => Violation::Velocity {
+                        peak: r.u32()?,
+                        limit: r.u32()?,
+                    },
+                    3 => Violation::Acceleration {
+                        peak: r.u32()?,
+                        limit: r.u32()?,
+                    },
+                    _ => return Err(Error::Malformed),
+                },
+            }),
+            kind::START_VIDEO => Message::StartVideo {
+                duration_ms: r.u32()?,
+                preroll_s: r.u8()?,
+            },
+            kind::START_PANORAMA => Message::StartPanorama(Panorama {
+                lens: match r.u8()? {
+                    0 => Lens::Fov {
+                        horizontal: r.u32()?,
+                        vertical: r.u32()?,
+                    },
+                    1 => Lens::Optics {
+                        focal_um: r.u32()?,
+                        sensor_width_um: r.u32()?,
+                        sensor_height_um: r.u32()?,
+                    },
+                    _ => return Err(Error::Malformed),
+                },
+                overlap_pct: r.u8()?,
+                pan_from: r.i32()?,
+                pan_to: r.i32()?,
+                tilt_from: r.i32()?,
+                tilt_to: r.i32()?,
+                settle_ms: r.u16()?,
+                exposure_ms: r.u16()?,
+            }),
+            kind::AIM_TARGET => Message::AimTarget {
+                aim: match r.u8()? {
+                    0 => Aim::Sighting,
+                    1 => Aim::Distance {
+                        distance_um: r.u32()?,
+                    },
+                    2 => Aim::Off,
+                    _ => return Err(Error::Malformed),
+                },
+                follow_tilt: r.u8()? != 0,
+            },
+            kind::START_ANIMATION => Message::StartAnimation(StopMotion {
+                steps: r.u16()?,
+                settle_ms: r.u16()?,
+                exposure_ms: r.u16()?,
+                resume: r.u8()? != 0,
+            }),
+            kind::STEP_FRAME => Message::StepFrame(Step::from_u8(r.u8()?).ok_or(Error::Malformed)?),
+            kind::START_FOCUS_STACK => Message::StartFocusStack(FocusStack {
+                near_um: r.i32()?,
+                far_um: r.i32()?,
+                spacing: match r.u8()? {
+                    0 => Spacing::Step { step_um: r.u32()? },
+                    1 => Spacing::Shots { shots: r.u16()? },
+                    _ => return Err(Error::Malformed),
+                },
+                backlash_um: r.u16()?,
+                settle_ms: r.u16()?,
+                exposure_ms: r.u16()?,
+                return_to_start: r.u8()? != 0,
+            }),
+            kind::STREAM_TELEMETRY => Message::StreamTelemetry {
+                period_ms: r.u16()?,
+            },
+            kind::TELEMETRY => Message::Telemetry(Telemetry {
+                uptime_ms: r.u32()?,
+                state: State::from_u8(r.u8()?).ok_or(Error::Malformed)?,
+                position: [r.i32()?, r.i32()?, r.i32()?],
+                velocity: [r.i32()?, r.i32()?, r.i32()?],
+                frame: r.u32()?,
+                remaining_s: r.u32()?,
+                battery_mv: r.u16()?,
+                loop_hz: r.u16()?,
+                overruns: r.u16()?,
+                max_lateness_ms: r.u16()?,
+            }),
+            other => return Err(Error::UnknownMessage(other)),
+        };
+
+        r.finish()?;
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_MESSAGE_LEN;
+
+    const KEYFRAME: Keyframe = Keyframe {
+        time_ms: u32::MAX,
+        slider: i32::MIN,
+        pan: i32::MAX,
+        tilt: -1,
+    };
+
+    // Every variant, with the largest payload where there is a choice
+    fn every_message() -> [Message; 37] {
+        let telemetry = Telemetry {
+            uptime_ms: u32::MAX,
+            state: State::Aborting,
+            position: [i32::MIN, 0, i32::MAX],
+            velocity: [-1, 1, 0],
+            frame: u32::MAX,
+            remaining_s: 12345,
+            battery_mv: u16::MAX,
+            loop_hz: 100,
+            overruns: 7,
+            max_lateness_ms: u16::MAX,
+        };
+        let panorama = |lens| Panorama {
+            lens,
+            overlap_pct: 30,
+            pan_from: -180_000,
+            pan_to: 180_000,
+            tilt_from: i32::MIN,
+            tilt_to: i32::MAX,
+            settle_ms: 500,
+            exposure_ms: u16::MAX,
+        };
+        let focus_stack = |spacing| FocusStack {
+            near_um: -5000,
+            far_um: 5000,
+            spacing,
+            backlash_um: 200,
+            settle_ms: 300,
+            exposure_ms: 100,
+            return_to_start: true,
+        };
+
+        [
+            Message::Ping,
+            Message::Ack,
+            Message::Nack(ErrorCode::Unsupported),
+            Message::AcknowledgeReset,
+            Message::Home,
+            Message::SkipHoming,
+            Message::ClearKeyframes,
+            Message::UploadKeyframe {
+                index: 255,
+                keyframe: KEYFRAME,
+            },
+            Message::ReadKeyframe { index: 3 },
+            Message::Keyframe {
+                index: 0,
+                keyframe: KEYFRAME,
+            },
+            Message::ReadSetting {
+                id: SettingId::DwellEnd,
+            },
+            Message::WriteSetting {
+                id: SettingId::TelemetryPeriod,
+                value: i32::MIN,
+            },
+            Message::Setting {
+                id: SettingId::LogLevel,
+                value: i32::MAX,
+            },
+            Message::StartSequence,
+            Message::StopSequence,
+            Message::PauseSequence,
+            Message::ResumeSequence,
+            Message::Infeasible(Infeasible {
+                segment: 4,
+                axis: 2,
+                violation: Violation::Travel {
+                    position: -400_000,
+                    limit: -360_000,
+                },
+            }),
+            Message::Infeasible(Infeasible {
+                segment: 1,
+                axis: 0,
+                violation: Violation::Duration {
+                    available_ms: 1000,
+                    needed_ms: u32::MAX,
+                },
+            }),
+            Message::Infeasible(Infeasible {
+                segment: 2,
+                axis: 1,
+                violation: Violation::Velocity {
+                    peak: 61_234,
+                    limit: 60_000,
+                },
+            }),
+            Message::Infeasible(Infeasible {
+                segment: 15,
+                axis: 2,
+                violation: Violation::Acceleration {
+                    peak: u32::MAX,
+                    limit: 60_000,
+                },
+            }),
+            Message::StartVideo {
+                duration_ms: 60_000,
+                preroll_s: 5,
+            },
+            Message::StartPanorama(panorama(Lens::Fov {
+                horizontal: 40_000,
+                vertical: 27_000,
+            })),
+            Message::StartPanorama(panorama(Lens::Optics {
+                focal_um: 50_000,
+                sensor_width_um: 36_000,
+                sensor_height_um: 24_000,
+            })),
+            Message::AimTarget {
+                aim: Aim::Sighting,
+                follow_tilt: false,
+            },
+            Message::AimTarget {
+                aim: Aim::Distance {
+                    distance_um: u32::MAX,
+                },
+                follow_tilt: true,
+            },
+            Message::AimTarget {
+                aim: Aim::Off,
+                follow_tilt: false,
+            },
+            Message::StartAnimation(StopMotion {
+                steps: u16::MAX,
+                settle_ms: 200,
+                exposure_ms: 0,
+                resume: true,
+            }),
+            Message::StepFrame(Step::Back),
+            Message::StepFrame(Step::Forward),
+            Message::StepFrame(Step::Shoot),
+            Message::StartFocusStack(focus_stack(Spacing::Step { step_um: 250 })),
+            Message::StartFocusStack(focus_stack(Spacing::Shots { shots: 40 })),
+            Message::StreamTelemetry { period_ms: 100 },
+            Message::StreamTelemetry { period_ms: 0 },
+            Message::Telemetry(telemetry),
+            Message::Telemetry(Telemetry {
+                state: State::SetInitPos,
+                ..telemetry
+            }),
+        ]
+    }
+
+    // Fails to build when a variant is added without a case above
+    fn covered(message: &Message) {
+        match message {
+            Message::Ping
+            | Message::Ack
+            | Message::Nack(_)
+            | Message::AcknowledgeReset
+            | Message::Home
+            | Message::SkipHoming
+            | Message::ClearKeyframes
+            | Message::UploadKeyframe { .. }
+            | Message::ReadKeyframe { .. }
+            | Message::Keyframe { .. }
+            | Message::ReadSetting { .. }
+            | Message::WriteSetting { .. }
+            | Message::Setting { .. }
+            | Message::StartSequence
+            | Message::StopSequence
+            | Message::PauseSequence
+            | Message::ResumeSequence
+            | Message::Infeasible(_)
+            | Message::StartVideo { .. }
+            | Message::StartPanorama(_)
+            | Message::AimTarget { .. }
+            | Message::StartAnimation(_)
+            | Message::StepFrame(_)
+            | Message::StartFocusStack(_)
+            | Message::StreamTelemetry { .. }
+            | Message::Telemetry(_) => {}
+        }
+    }
+
+    #[test]
+    fn round_trip_every_message() {
+        for message in every_message() {
+            covered(&message);
+
+            let mut buf = [0u8; MAX_MESSAGE_LEN];
+            let len = message.encode(&mut buf).unwrap();
+            assert_eq!(Message::decode(&buf[..len]), Ok(message));
+        }
+    }
+
+    #[test]
+    fn short_buffer() {
+        for message in every_message() {
+            let mut buf = [0u8; MAX_MESSAGE_LEN];
+            let len = message.encode(&mut buf).unwrap();
+            assert_eq!(
+                message.encode(&mut buf[..len - 1]),
+                Err(Error::BufferTooSmall)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_or_padded_payload() {
+        for message in every_message() {
+            let mut buf = [0u8; MAX_MESSAGE_LEN + 1];
+            let len = message.encode(&mut buf).unwrap();
+            if len > 1 {
+                assert_eq!(Message::decode(&buf[..len - 1]), Err(Error::Malformed));
+            }
+            assert_eq!(Message::decode(&buf[..len + 1]), Err(Error::Malformed));
+        }
+    }
+
+    #[test]
+    fn unknown_kind_and_values() {
+        assert_eq!(Message::decode(&[]), Err(Error::Malformed));
+        assert_eq!(Message::decode(&[0xEE]), Err(Error::UnknownMessage(0xEE)));
+        assert_eq!(Message::decode(&[kind::NACK, 6]), Err(Error::Malformed));
+        assert_eq!(
+            Message::decode(&[kind::READ_SETTING, 8]),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            Message::decode(&[kind::STEP_FRAME, 3]),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn states_round_trip() {
+        for value in 0..=u8::MAX {
+            match State::from_u8(value) {
+                Some(state) => assert_eq!(state as u8, value),
+                None => assert!(value > State::Aborting as u8),
+            }
+        }
+    }
+}
@@ -0,0 +1,385 @@
+// Frame centres of a panorama. Rows go from the lowest tilt up and every
+// other row runs backwards, so the next frame is always a neighbour. Rows
+// away from the horizon need fewer frames for the same overlap, a frame
+// there covers more of the pan circle.
+//
+// Angles are in degrees while planning, positions come out in millidegrees.
+
+use crate::{
+    plan::{Infeasible, Violation, AXES},
+    ErrorCode, Lens, Limits, Panorama,
+};
+
+// Far beyond any real panorama. Keeps the counts inside u16 and the grid
+// quick to work out on the dolly, a fraction of its watchdog timeout.
+pub const MAX_ROWS: u16 = 180;
+pub const MAX_COLUMNS: u16 = 720;
+pub const MAX_FRAMES: u32 = 10_000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Grid {
+    pan_from: f32,
+    pan_span: f32,
+    tilt_from: f32,
+    tilt_span: f32,
+    fov_h: f32,
+    fov_v: f32,
+    // of a frame left after the overlap, the spacing between centres
+    keep: f32,
+    rows: u16,
+    frames: u32,
+}
+
+// Frames to cover `span` with frames `fov` wide spaced at most `step` apart
+fn count(span: f32, fov: f32, step: f32) -> f32 {
+    match span <= fov {
+        true => 1.0,
+        false => libm::ceilf((span - fov) / step) + 1.0,
+    }
+}
+
+fn at_most(n: f32, max: u16) -> Result<u16, ErrorCode> {
+    match n <= max as f32 {
+        true => Ok(n as u16),
+        false => Err(ErrorCode::InvalidValue),
+    }
+}
+
+// Centre of frame `i` of `n` covering `span` from `from` edge to edge
+fn centre(from: f32, span: f32, fov: f32, i: u16, n: u16) -> f32 {
+    match n {
+        1 => from + span / 2.0,
+        _ => from + fov / 2.0 + i as f32 * (span - fov) / (n - 1) as f32,
+    }
+}
+
+fn view(sensor_um: u32, focal_um: u32) -> f32 {
+    2.0 * libm::atanf(sensor_um as f32 / (2.0 * focal_um as f32)).to_degrees()
+}
+
+impl Grid {
+    pub fn new(p: &Panorama) -> Result<Self, ErrorCode> {
+        let (fov_h, fov_v) = match p.lens {
+            Lens::Fov {
+                horizontal,
+                vertical,
+            } => (horizontal as f32 / 1000.0, vertical as f32 / 1000.0),
+            Lens::Optics {
+                focal_um,
+                sensor_width_um,
+                sensor_height_um,
+            } if focal_um > 0 => (
+                view(sensor_width_um, focal_um),
+                view(sensor_height_um, focal_um),
+            ),
+            Lens::Optics { .. } => return Err(ErrorCode::InvalidValue),
+        };
+
+        let usable = |fov: f32| fov > 0.0 && fov < 180.0;
+        if !usable(fov_h) || !usable(fov_v) || p.overlap_pct >= 100 {
+            return Err(ErrorCode::InvalidValue);
+        }
+        if p.pan_from > p.pan_to || p.tilt_from > p.tilt_to {
+            return Err(ErrorCode::InvalidValue);
+        }
+
+        let keep = 1.0 - p.overlap_pct as f32 / 100.0;
+        let tilt_span = (p.tilt_to as f32 - p.tilt_from as f32) / 1000.0;
+        let mut grid = Self {
+            pan_from: p.pan_from as f32 / 1000.0,
+            pan_span: (p.pan_to as f32 - p.pan_from as f32) / 1000.0,
+            tilt_from: p.tilt_from as f32 / 1000.0,
+            tilt_span,
+            fov_h,
+            fov_v,
+            keep,
+            rows: at_most(count(tilt_span, fov_v, fov_v * keep), MAX_ROWS)?,
+            frames: 0,
+        };
+
+        for row in 0..grid.rows {
+            grid.frames += at_most(grid.count_columns(row), MAX_COLUMNS)? as u32;
+            if grid.frames > MAX_FRAMES {
+                return Err(ErrorCode::InvalidValue);
+            }
+        }
+        Ok(grid)
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    fn tilt(&self, row: u16) -> f32 {
+        centre(self.tilt_from, self.tilt_span, self.fov_v, row, self.rows)
+    }
+
+    fn wraps(&self) -> bool {
+        self.pan_span >= 360.0
+    }
+
+    // How much pan a frame covers on this row, measured at the edge of the
+    // row closest to the horizon where the pan circle is widest
+    fn width(&self, row: u16) -> f32 {
+        let tilt = self.tilt(row);
+        let (low, high) = (tilt - self.fov_v / 2.0, tilt + self.fov_v / 2.0);
+        let latitude = match low <= 0.0 && high >= 0.0 {
+            true => 0.0,
+            false => libm::fminf(libm::fabsf(low), libm::fabsf(high)),
+        };
+        let circle = libm::cosf(libm::fminf(latitude, 89.0).to_radians());
+        libm::fminf(self.fov_h / circle, 360.0)
+    }
+
+    fn count_columns(&self, row: u16) -> f32 {
+        let width = self.width(row);
+        match self.wraps() {
+            true => libm::fmaxf(libm::ceilf(360.0 / (width * self.keep)), 1.0),
+            false => count(self.pan_span, width, width * self.keep),
+        }
+    }
+
+    // within MAX_COLUMNS, Grid::new has checked every row
+    pub fn columns(&self, row: u16) -> u16 {
+        self.count_columns(row) as u16
+    }
+
+    // Pan and tilt of frame `column` of `row` in shooting order
+    pub fn position(&self, row: u16, column: u16) -> (i32, i32) {
+        let n = self.columns(row);
+        let column = match row % 2 {
+            0 => column,
+            _ => n - 1 - column,
+        };
+
+        let pan = match self.wraps() {
+            // evenly round the circle, the last frame overlaps the first
+            true => self.pan_from + (column as f32 + 0.5) * 360.0 / n as f32,
+            false => centre(self.pan_from, self.pan_span, self.width(row), column, n),
+        };
+        let millis = |deg: f32| libm::roundf(deg * 1000.0) as i32;
+        (millis(pan), millis(self.tilt(row)))
+    }
+}
+
+// The frames lie within the area, so its edges are enough to check
+pub fn check(limits: &[Limits; AXES], p: &Panorama) -> Result<(), Infeasible> {
+    let axes = [(1, p.pan_from, p.pan_to), (2, p.tilt_from, p.tilt_to)];
+    for (axis, from, to) in axes {
+        let l = &limits[axis];
+        let outside = [from, to].into_iter().find(|&edge| !l.contains(edge));
+        if let Some(position) = outside {
+            let limit = if position < l.min { l.min } else { l.max };
+            return Err(Infeasible {
+                segment: 0,
+                axis: axis as u8,
+                violation: Violation::Travel { position, limit },
+            });
+        }
+    }
+    Ok(())
+}
+
+// A panorama being shot, one frame after the other
+#[derive(Clone, Debug)]
+pub struct Shoot {
+    grid: Grid,
+    row: u16,
+    column: u16,
+    pub taken: u32,
+    pub settle_ms: u32,
+    pub exposure_ms: u32,
+}
+
+impl Shoot {
+    pub fn new(p: &Panorama) -> Result<Self, ErrorCode> {
+        Ok(Self {
+            grid: Grid::new(p)?,
+            row: 0,
+            column: 0,
+            taken: 0,
+            settle_ms: p.settle_ms as u32,
+            exposure_ms: p.exposure_ms as u32,
+        })
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.grid.frames()
+    }
+
+    // Where the next frame is taken, None once they all are
+    pub fn target(&self) -> Option<(i32, i32)> {
+        (self.row < self.grid.rows()).then(|| self.grid.position(self.row, self.column))
+    }
+
+    pub fn advance(&mut self) {
+        self.taken += 1;
+        self.column += 1;
+        if self.column >= self.grid.columns(self.row) {
+            self.row += 1;
+            self.column = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panorama(fov: (u32, u32), pan: (i32, i32), tilt: (i32, i32)) -> Panorama {
+        Panorama {
+            lens: Lens::Fov {
+                horizontal: fov.0,
+                vertical: fov.1,
+            },
+            overlap_pct: 25,
+            pan_from: pan.0,
+            pan_to: pan.1,
+            tilt_from: tilt.0,
+            tilt_to: tilt.1,
+            settle_ms: 0,
+            exposure_ms: 0,
+        }
+    }
+
+    #[test]
+    fn single_frame() {
+        let grid = Grid::new(&panorama((40_000, 30_000), (0, 10_000), (0, 0))).unwrap();
+        assert_eq!((grid.rows(), grid.frames()), (1, 1));
+        assert_eq!(grid.position(0, 0), (5_000, 0));
+    }
+
+    #[test]
+    fn serpentine_within_the_area() {
+        let grid = Grid::new(&panorama(
+            (40_000, 30_000),
+            (-90_000, 90_000),
+            (-30_000, 30_000),
+        ))
+        .unwrap();
+        assert!(grid.rows() > 1);
+
+        let mut frames = 0;
+        for row in 0..grid.rows() {
+            let n = grid.columns(row);
+            for column in 0..n {
+                let (pan, tilt) = grid.position(row, column);
+                assert!((-90_000..=90_000).contains(&pan));
+                assert!((-30_000..=30_000).contains(&tilt));
+                frames += 1;
+            }
+            // every other row runs backwards, so rows join at the same end
+            let (first, _) = grid.position(row, 0);
+            let (last, _) = grid.position(row, n - 1);
+            assert_eq!(first < last, row % 2 == 0);
+        }
+        assert_eq!(frames, grid.frames());
+    }
+
+    #[test]
+    fn fewer_frames_away_from_the_horizon() {
+        let grid = Grid::new(&panorama((20_000, 20_000), (0, 360_000), (0, 80_000))).unwrap();
+        let first = grid.columns(0);
+        let last = grid.columns(grid.rows() - 1);
+        assert!(last < first, "{last} vs {first}");
+    }
+
+    #[test]
+    fn rejects_unusable_input() {
+        let p = panorama((40_000, 30_000), (0, 90_000), (0, 30_000));
+        assert!(Grid::new(&Panorama {
+            overlap_pct: 100,
+            ..p
+        })
+        .is_err());
+        assert!(Grid::new(&Panorama {
+            pan_from: 100_000,
+            ..p
+        })
+        .is_err());
+        assert!(Grid::new(&panorama((0, 30_000), (0, 90_000), (0, 0))).is_err());
+        assert!(Grid::new(&panorama((180_000, 30_000), (0, 90_000), (0, 0))).is_err());
+        assert!(Grid::new(&Panorama {
+            lens: Lens::Optics {
+                focal_um: 0,
+                sensor_width_um: 36_000,
+                sensor_height_um: 24_000,
+            },
+            ..p
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_grids_past_the_maximum() {
+        // a sliver of a degree over a huge area, the counts would saturate u16
+        let tiny = panorama((1, 1), (i32::MIN, i32::MAX), (i32::MIN, i32::MAX));
+        assert_eq!(Grid::new(&tiny).unwrap_err(), ErrorCode::InvalidValue);
+
+        let too_many_columns = panorama((100, 30_000), (0, 360_000), (0, 0));
+        assert_eq!(
+            Grid::new(&too_many_columns).unwrap_err(),
+            ErrorCode::InvalidValue
+        );
+
+        // each row is fine, all of them together are not
+        let too_many_frames = panorama((2_000, 2_000), (0, 360_000), (-60_000, 60_000));
+        assert_eq!(
+            Grid::new(&too_many_frames).unwrap_err(),
+            ErrorCode::InvalidValue
+        );
+    }
+
+    #[test]
+    fn shoots_every_frame_once() {
+        let p = panorama((20_000, 20_000), (0, 360_000), (0, 80_000));
+        let grid = Grid::new(&p).unwrap();
+        let mut shoot = Shoot::new(&p).unwrap();
+
+        for row in 0..grid.rows() {
+            for column in 0..grid.columns(row) {
+                assert_eq!(shoot.target(), Some(grid.position(row, column)));
+                shoot.advance();
+            }
+        }
+        assert_eq!(shoot.target(), None);
+        assert_eq!(shoot.taken, grid.frames());
+    }
+
+    #[test]
+    fn area_within_the_limits() {
+        let limits = crate::rig::LIMITS;
+        let p = panorama((20_000, 20_000), (-180_000, 180_000), (-90_000, 90_000));
+        assert_eq!(check(&limits, &p), Ok(()));
+
+        let e = check(
+            &limits,
+            &Panorama {
+                tilt_to: 95_000,
+                ..p
+            },
+        )
+        .unwrap_err();
+        assert_eq!(e.axis, 2);
+        assert_eq!(
+            e.violation,
+            Violation::Travel {
+                position: 95_000,
+                limit: 90_000
+            }
+        );
+        let e = check(
+            &limits,
+            &Panorama {
+                pan_from: -200_000,
+                ..p
+            },
+        )
+        .unwrap_err();
+        assert_eq!(e.axis, 1);
+    }
+}
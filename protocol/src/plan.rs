@@ -0,0 +1,346 @@
+// Feasibility of a keyframe sequence against per-axis limits. Shared so the
+// simulator refuses exactly what the firmware refuses.
+//
+// Every segment is a trapezoid that starts and ends at rest: accelerate at
+// the limit, cruise, decelerate at the limit. Segment i ends at keyframe i,
+// segment 0 is the untimed move to the first keyframe.
+
+use crate::{profile::Trapezoid, tracking::Target, units::Unit, Keyframe};
+
+pub const AXES: usize = 3;
+pub const AXIS_NAMES: [&str; AXES] = ["slider", "pan", "tilt"];
+pub const AXIS_UNITS: [Unit; AXES] = [Unit::Micrometre, Unit::Millidegree, Unit::Millidegree];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    // micrometres or millidegrees per second
+    pub max_velocity: u32,
+    // per second squared
+    pub max_acceleration: u32,
+    // soft limits of the travel, inclusive
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Limits {
+    // Shortest time to cover `distance` from rest to rest
+    pub fn min_duration_ms(&self, distance: u32) -> u32 {
+        let d = distance as u64;
+        let v = self.max_velocity.max(1) as u64;
+        let a = self.max_acceleration.max(1) as u64;
+
+        // rounded up, a segment that is a millisecond too short still fails
+        let ms = match d * a <= v * v {
+            // triangle, the cruise speed is never reached: 2 * sqrt(d / a)
+            true => {
+                let n = (4 * d * 1_000_000).div_ceil(a);
+                let root = n.isqrt();
+                root + (root * root < n) as u64
+            }
+            false => (d * 1000).div_ceil(v) + (v * 1000).div_ceil(a),
+        };
+        ms.min(u32::MAX as u64) as u32
+    }
+
+    pub fn contains(&self, position: i32) -> bool {
+        (self.min..=self.max).contains(&position)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    // the keyframe lies outside the soft limits
+    Travel { position: i32, limit: i32 },
+    // the segment is shorter than the axis can manage
+    Duration { available_ms: u32, needed_ms: u32 },
+    // a tracked pan or tilt would have to turn faster than the axis can,
+    // per second and per second squared
+    Velocity { peak: u32, limit: u32 },
+    Acceleration { peak: u32, limit: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Infeasible {
+    pub segment: u8,
+    pub axis: u8,
+    pub violation: Violation,
+}
+
+pub fn positions(k: &Keyframe) -> [i32; AXES] {
+    [k.slider, k.pan, k.tilt]
+}
+
+// Shortest rest to rest duration for every axis to get from `from` to `to`,
+// the slowest axis sets it
+pub fn fastest_ms(limits: &[Limits; AXES], from: &[i32; AXES], to: &[i32; AXES]) -> u32 {
+    (0..AXES)
+        .map(|i| limits[i].min_duration_ms(from[i].abs_diff(to[i])))
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn check(limits: &[Limits; AXES], keyframes: &[Keyframe]) -> Result<(), Infeasible> {
+    check_each(limits, keyframes.iter().copied())
+}
+
+// For keyframes that are worked out on the fly, like tracked pan angles
+pub fn check_each(
+    limits: &[Limits; AXES],
+    keyframes: impl IntoIterator<Item = Keyframe>,
+) -> Result<(), Infeasible> {
+    let mut previous: Option<Keyframe> = None;
+
+    for (segment, keyframe) in keyframes.into_iter().enumerate() {
+        let segment = segment as u8;
+        let to = positions(&keyframe);
+
+        for (axis, (l, &position)) in limits.iter().zip(to.iter()).enumerate() {
+            let axis = axis as u8;
+            if !l.contains(position) {
+                let limit = if position < l.min { l.min } else { l.max };
+                return Err(Infeasible {
+                    segment,
+                    axis,
+                    violation: Violation::Travel { position, limit },
+                });
+            }
+
+            let Some(previous) = previous else {
+                continue;
+            };
+            let from = positions(&previous)[axis as usize];
+            let available_ms = keyframe.time_ms.saturating_sub(previous.time_ms);
+            let needed_ms = l.min_duration_ms(position.abs_diff(from));
+            if needed_ms > available_ms {
+                return Err(Infeasible {
+                    segment,
+                    axis,
+                    violation: Violation::Duration {
+                        available_ms,
+                        needed_ms,
+                    },
+                });
+            }
+        }
+
+        previous = Some(keyframe);
+    }
+
+    Ok(())
+}
+
+// Slider positions looked at along each segment when checking tracked pan
+// and tilt, besides the few where the angles change fastest
+const TRACK_SAMPLES: u16 = 64;
+
+// Right by a subject on the rail the rates head for infinity, capped so
+// they still print as signed quantities
+fn printable(rate: f32) -> u32 {
+    libm::fminf(rate, i32::MAX as f32) as u32
+}
+
+// Tracked pan and tilt follow the slider rather than profiles of their own,
+// so they are fastest wherever the slider passes the subject, often well
+// inside a segment. Checks them along every timed segment of `keyframes` as
+// the slider's trapezoid moves it, the keyframes as uploaded.
+pub fn check_tracked(
+    limits: &[Limits; AXES],
+    target: &Target,
+    keyframes: &[Keyframe],
+) -> Result<(), Infeasible> {
+    for (i, pair) in keyframes.windows(2).enumerate() {
+        let (a, b) = (pair[0], pair[1]);
+        let duration_ms = b.time_ms.saturating_sub(a.time_ms);
+        let slider = Trapezoid::new(a.slider, b.slider, duration_ms, &limits[0]);
+        let distance = (b.slider as f32 - a.slider as f32).abs();
+        let direction = if b.slider < a.slider { -1.0 } else { 1.0 };
+
+        let evenly = (0..=TRACK_SAMPLES).map(|k| distance * k as f32 / TRACK_SAMPLES as f32);
+        let turning = target
+            .turning_points()
+            .map(|at| (at - a.slider as f32) * direction);
+        let travels = evenly.chain(turning.into_iter().filter(|d| (0.0..=distance).contains(d)));
+
+        // the fastest each of pan and tilt turn and speed up along the segment
+        let mut peaks = [(0.0, 0.0); 2];
+        for travelled in travels {
+            let (speed, accel) = slider.motion_at(travelled);
+            let rates = target.rates(a.slider as f32 + direction * travelled);
+            for (peak, (d1, d2)) in peaks.iter_mut().zip(rates) {
+                let velocity = libm::fabsf(d1 * speed);
+                let acceleration = libm::fabsf(d2 * speed * speed + d1 * direction * accel);
+                *peak = (
+                    libm::fmaxf(peak.0, velocity),
+                    libm::fmaxf(peak.1, acceleration),
+                );
+            }
+        }
+
+        let followed = if target.follows_tilt() { 2 } else { 1 };
+        for (axis, &(velocity, acceleration)) in peaks.iter().enumerate().take(followed) {
+            let l = &limits[axis + 1];
+            let violation = if velocity > l.max_velocity as f32 {
+                Violation::Velocity {
+                    peak: printable(velocity),
+                    limit: l.max_velocity,
+                }
+            } else if acceleration > l.max_acceleration as f32 {
+                Violation::Acceleration {
+                    peak: printable(acceleration),
+                    limit: l.max_acceleration,
+                }
+            } else {
+                continue;
+            };
+            return Err(Infeasible {
+                segment: (i + 1) as u8,
+                axis: (axis + 1) as u8,
+                violation,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Only the soft limits, for moves that take as long as the axes need
+pub fn check_travel(
+    limits: &[Limits; AXES],
+    points: impl IntoIterator<Item = [i32; AXES]>,
+) -> Result<(), Infeasible> {
+    for (segment, point) in points.into_iter().enumerate() {
+        for (axis, (l, &position)) in limits.iter().zip(point.iter()).enumerate() {
+            if !l.contains(position) {
+                let limit = if position < l.min { l.min } else { l.max };
+                return Err(Infeasible {
+                    segment: segment as u8,
+                    axis: axis as u8,
+                    violation: Violation::Travel { position, limit },
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rig::LIMITS;
+    use crate::units::deg;
+
+    fn keyframe(time_ms: u32, slider: i32) -> Keyframe {
+        Keyframe {
+            time_ms,
+            slider,
+            pan: 0,
+            tilt: 0,
+        }
+    }
+
+    // A subject `distance_um` straight out from the middle of the rail
+    fn subject(distance_um: u32, follow_tilt: bool) -> Target {
+        Target::at_distance([600_000, 0, 5_000], distance_um, follow_tilt).unwrap()
+    }
+
+    #[test]
+    fn min_duration() {
+        let l = &LIMITS[0];
+        assert_eq!(l.min_duration_ms(0), 0);
+        // 100 mm at 100 mm/s after half a second up to speed and down again
+        assert_eq!(l.min_duration_ms(100_000), 1_500);
+        // never reaches full speed: 2 * sqrt(10 mm / 200 mm/s²)
+        assert_eq!(l.min_duration_ms(10_000), 448);
+    }
+
+    #[test]
+    fn fastest_waits_for_the_slowest_axis() {
+        let from = [0, deg(-90), 0];
+        assert_eq!(fastest_ms(&LIMITS, &from, &from), 0);
+        // 1.5 s for the slider and the tilt, half a turn of pan takes 3.5 s
+        assert_eq!(
+            fastest_ms(&LIMITS, &from, &[100_000, deg(90), deg(-30)]),
+            3_500
+        );
+        assert_eq!(fastest_ms(&LIMITS, &from, &[100_000, deg(-90), 0]), 1_500);
+    }
+
+    #[test]
+    fn travel_and_duration() {
+        let fine = [keyframe(0, 0), keyframe(20_000, 1_200_000)];
+        assert_eq!(check(&LIMITS, &fine), Ok(()));
+
+        let beyond = [keyframe(0, 0), keyframe(20_000, 1_300_000)];
+        assert_eq!(
+            check(&LIMITS, &beyond).unwrap_err().violation,
+            Violation::Travel {
+                position: 1_300_000,
+                limit: 1_200_000
+            }
+        );
+
+        let rushed = [keyframe(0, 0), keyframe(1_000, 1_000_000)];
+        let e = check(&LIMITS, &rushed).unwrap_err();
+        assert_eq!((e.segment, e.axis), (1, 0));
+        assert!(matches!(e.violation, Violation::Duration { .. }));
+    }
+
+    #[test]
+    fn tracking_a_far_subject() {
+        let keyframes = [keyframe(0, 0), keyframe(30_000, 1_200_000)];
+        assert_eq!(
+            check_tracked(&LIMITS, &subject(5_000_000, true), &keyframes),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn tracking_fastest_between_the_keyframes() {
+        // the keyframes are far off to either side, pan swings through 0
+        // in the middle where neither end sees it
+        let keyframes = [keyframe(0, 0), keyframe(13_000, 1_200_000)];
+        let target = subject(80_000, false);
+        let e = check_tracked(&LIMITS, &target, &keyframes).unwrap_err();
+        assert_eq!((e.segment, e.axis), (1, 1));
+        let Violation::Velocity { peak, limit } = e.violation else {
+            panic!("{:?}", e.violation);
+        };
+        // straight past at the 95.84 mm/s cruise, 1 radian per distance out
+        let want = 95_840.0 / target.from_rail() as f32 * 57_295.8;
+        assert!(peak.abs_diff(want as u32) < 300, "{peak} vs {want}");
+        assert_eq!(limit, LIMITS[1].max_velocity);
+    }
+
+    #[test]
+    fn tracking_acceleration() {
+        // slow enough to turn, but the slider starts right in front of the
+        // subject at full acceleration
+        let keyframes = [keyframe(0, 600_000), keyframe(4_000, 700_000)];
+        let e = check_tracked(&LIMITS, &subject(60_000, false), &keyframes).unwrap_err();
+        assert!(
+            matches!(e.violation, Violation::Acceleration { .. }),
+            "{:?}",
+            e.violation
+        );
+    }
+
+    #[test]
+    fn tracking_tilt_only_when_it_follows() {
+        // tilt turns at most half as fast as pan, give it a tighter limit
+        let mut limits = LIMITS;
+        limits[2].max_velocity = 1_000;
+        let keyframes = [keyframe(0, 0), keyframe(10_000, 600_000)];
+
+        let level = subject(1_000_000, false);
+        assert_eq!(check_tracked(&limits, &level, &keyframes), Ok(()));
+
+        let followed = Target::at_distance([600_000, 0, -45_000], 1_000_000, true).unwrap();
+        let e = check_tracked(&limits, &followed, &keyframes).unwrap_err();
+        assert_eq!(e.axis, 2);
+        assert!(matches!(
+            e.violation,
+            Violation::Velocity { limit: 1_000, .. }
+        ));
+    }
+}
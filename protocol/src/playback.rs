@@ -0,0 +1,219 @@
+// What happens after the sequence has played from its first keyframe to its
+// last. Shared so the simulator repeats exactly like the firmware.
+//
+// A cycle always ends where it started: ping-pong comes back through the
+// keyframes in reverse, loop and return rewind with the fastest move.
+
+use crate::Keyframe;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // stop at the last keyframe
+    OneShot = 0,
+    // rewind to the first keyframe once
+    Return = 1,
+    PingPong = 2,
+    // rewind and play forward again
+    Loop = 3,
+}
+
+impl Mode {
+    pub fn from_u8(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(Mode::OneShot),
+            1 => Some(Mode::Return),
+            2 => Some(Mode::PingPong),
+            3 => Some(Mode::Loop),
+            _ => None,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "once" => Some(Mode::OneShot),
+            "return" => Some(Mode::Return),
+            "pingpong" => Some(Mode::PingPong),
+            "loop" => Some(Mode::Loop),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::OneShot => "once",
+            Mode::Return => "return",
+            Mode::PingPong => "pingpong",
+            Mode::Loop => "loop",
+        }
+    }
+
+    // For inputs that can only step through the modes
+    pub fn next(self) -> Self {
+        match self {
+            Mode::OneShot => Mode::Return,
+            Mode::Return => Mode::PingPong,
+            Mode::PingPong => Mode::Loop,
+            Mode::Loop => Mode::OneShot,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    // through the keyframes in order, on their timing
+    Forward,
+    // the same segments last to first, mirrored in time
+    Backward,
+    // straight back to the first keyframe as fast as the axes allow
+    Rewind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Playback {
+    pub mode: Mode,
+    // cycles for ping-pong and loop, 0 repeats until stopped
+    pub repeat: u16,
+    // rest at the first keyframe before playing it again
+    pub dwell_start_ms: u32,
+    // rest at the last keyframe before turning back
+    pub dwell_end_ms: u32,
+}
+
+impl Playback {
+    pub const fn new() -> Self {
+        Self {
+            mode: Mode::OneShot,
+            repeat: 1,
+            dwell_start_ms: 0,
+            dwell_end_ms: 0,
+        }
+    }
+
+    // The dwell and pass that follow `pass` of `cycle`, counted from 0, with
+    // the cycle they belong to. None once the playback is over.
+    pub fn after(&self, pass: Pass, cycle: u16) -> Option<(u32, Pass, u16)> {
+        match (self.mode, pass) {
+            (Mode::OneShot, _) => None,
+            (Mode::Return, Pass::Forward) | (Mode::Loop, Pass::Forward) => {
+                Some((self.dwell_end_ms, Pass::Rewind, cycle))
+            }
+            (Mode::PingPong, Pass::Forward) => Some((self.dwell_end_ms, Pass::Backward, cycle)),
+            (Mode::Return, _) => None,
+            (Mode::PingPong | Mode::Loop, _) => {
+                let cycle = cycle.wrapping_add(1);
+                let again = self.repeat == 0 || cycle < self.repeat;
+                again.then_some((self.dwell_start_ms, Pass::Forward, cycle))
+            }
+        }
+    }
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// One move of a forward or backward pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub from: Keyframe,
+    pub to: Keyframe,
+    pub duration_ms: u32,
+    // when it starts on the pass clock
+    pub offset_ms: u32,
+}
+
+// Segment `segment` of `pass`, counted from 1. None past the last one, and
+// for a rewind, which is a single move as fast as the axes allow.
+pub fn segment(keyframes: &[Keyframe], pass: Pass, segment: usize) -> Option<Segment> {
+    let last = keyframes.len().checked_sub(1)?;
+    if segment == 0 || segment > last {
+        return None;
+    }
+
+    match pass {
+        Pass::Forward => {
+            let (from, to) = (keyframes[segment - 1], keyframes[segment]);
+            Some(Segment {
+                from,
+                to,
+                duration_ms: to.time_ms - from.time_ms,
+                offset_ms: from.time_ms - keyframes[0].time_ms,
+            })
+        }
+        // the forward timing mirrored, so the moves ease the same way
+        Pass::Backward => {
+            let (from, to) = (keyframes[last + 1 - segment], keyframes[last - segment]);
+            Some(Segment {
+                from,
+                to,
+                duration_ms: from.time_ms - to.time_ms,
+                offset_ms: keyframes[last].time_ms - from.time_ms,
+            })
+        }
+        Pass::Rewind => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time_ms: u32, slider: i32) -> Keyframe {
+        Keyframe {
+            time_ms,
+            slider,
+            ..Keyframe::default()
+        }
+    }
+
+    #[test]
+    fn segments_both_ways() {
+        let keyframes = [at(500, 0), at(1_500, 10), at(4_500, 20)];
+
+        let forward = segment(&keyframes, Pass::Forward, 2).unwrap();
+        assert_eq!((forward.from, forward.to), (keyframes[1], keyframes[2]));
+        assert_eq!((forward.duration_ms, forward.offset_ms), (3_000, 1_000));
+
+        // the long segment comes first on the way back
+        let backward = segment(&keyframes, Pass::Backward, 1).unwrap();
+        assert_eq!((backward.from, backward.to), (keyframes[2], keyframes[1]));
+        assert_eq!((backward.duration_ms, backward.offset_ms), (3_000, 0));
+        let backward = segment(&keyframes, Pass::Backward, 2).unwrap();
+        assert_eq!((backward.duration_ms, backward.offset_ms), (1_000, 3_000));
+
+        assert_eq!(segment(&keyframes, Pass::Forward, 3), None);
+        assert_eq!(segment(&keyframes, Pass::Rewind, 1), None);
+        assert_eq!(segment(&keyframes[..1], Pass::Forward, 1), None);
+        assert_eq!(segment(&[], Pass::Backward, 1), None);
+    }
+
+    #[test]
+    fn ping_pong_repeats() {
+        let playback = Playback {
+            mode: Mode::PingPong,
+            repeat: 2,
+            dwell_start_ms: 100,
+            dwell_end_ms: 200,
+        };
+        assert_eq!(
+            playback.after(Pass::Forward, 0),
+            Some((200, Pass::Backward, 0))
+        );
+        assert_eq!(
+            playback.after(Pass::Backward, 0),
+            Some((100, Pass::Forward, 1))
+        );
+        assert_eq!(playback.after(Pass::Backward, 1), None);
+
+        let once = Playback::new();
+        assert_eq!(once.after(Pass::Forward, 0), None);
+        let back = Playback {
+            mode: Mode::Return,
+            ..once
+        };
+        assert_eq!(back.after(Pass::Forward, 0), Some((0, Pass::Rewind, 0)));
+        assert_eq!(back.after(Pass::Rewind, 0), None);
+    }
+}
@@ -0,0 +1,404 @@
+use crate::{plan::AXES, tracking::Target, Limits};
+
+// Rest to rest trapezoid over a fixed duration, accelerating at the axis
+// limit. The planner has already checked that the duration is long enough.
+#[derive(Clone, Copy, Debug)]
+pub struct Trapezoid {
+    from: i32,
+    to: i32,
+    distance: f32,
+    duration_s: f32,
+    accel: f32,
+    cruise: f32,
+    ramp_s: f32,
+}
+
+impl Trapezoid {
+    pub fn new(from: i32, to: i32, duration_ms: u32, limits: &Limits) -> Self {
+        let distance = (to - from) as f32;
+        let duration_s = duration_ms as f32 / 1000.0;
+        let accel = limits.max_acceleration.max(1) as f32;
+        let d = libm::fabsf(distance);
+
+        // the slowest cruise that still arrives in time, (aT - sqrt(a²T² - 4aD)) / 2
+        // rearranged so hour long segments don't cancel out in f32. The
+        // discriminant only dips below 0 by rounding.
+        let disc = libm::fmaxf(duration_s * duration_s - 4.0 * d / accel, 0.0);
+        let cruise = match duration_s > 0.0 {
+            true => 2.0 * d / (duration_s + libm::sqrtf(disc)),
+            false => 0.0,
+        };
+
+        Self {
+            from,
+            to,
+            distance,
+            duration_s,
+            accel,
+            cruise,
+            ramp_s: cruise / accel,
+        }
+    }
+
+    // Position at `t_ms` after the start, held at the end afterwards
+    pub fn at(&self, t_ms: f32) -> i32 {
+        let t = t_ms / 1000.0;
+        if t >= self.duration_s || self.cruise <= 0.0 {
+            return self.to;
+        }
+
+        let ramp = self.ramp_s;
+        let travelled = if t < ramp {
+            self.accel * t * t / 2.0
+        } else if t < self.duration_s - ramp {
+            self.accel * ramp * ramp / 2.0 + self.cruise * (t - ramp)
+        } else {
+            let left = self.duration_s - t;
+            libm::fabsf(self.distance) - self.accel * left * left / 2.0
+        };
+
+        let travelled = libm::fminf(travelled, libm::fabsf(self.distance));
+        match self.distance < 0.0 {
+            true => self.from - travelled as i32,
+            false => self.from + travelled as i32,
+        }
+    }
+
+    // Speed, and acceleration along the direction of travel, once `travelled`
+    // of the distance is covered
+    pub fn motion_at(&self, travelled: f32) -> (f32, f32) {
+        if self.cruise <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let d = libm::fabsf(self.distance);
+        let ramp = self.accel * self.ramp_s * self.ramp_s / 2.0;
+        if travelled < ramp {
+            (
+                libm::sqrtf(2.0 * self.accel * libm::fmaxf(travelled, 0.0)),
+                self.accel,
+            )
+        } else if travelled <= d - ramp {
+            (self.cruise, 0.0)
+        } else {
+            let left = libm::fmaxf(d - travelled, 0.0);
+            (libm::sqrtf(2.0 * self.accel * left), -self.accel)
+        }
+    }
+}
+
+// A timed move of every sequenced axis from rest to rest. When tracking,
+// pan and tilt follow the slider's profile instead of their own.
+#[derive(Clone, Copy, Debug)]
+pub struct Path {
+    profiles: [Trapezoid; AXES],
+    track: Option<Target>,
+}
+
+impl Path {
+    pub fn new(
+        limits: &[Limits; AXES],
+        from: &[i32; AXES],
+        to: &[i32; AXES],
+        duration_ms: u32,
+        track: Option<Target>,
+    ) -> Self {
+        Self {
+            profiles: core::array::from_fn(|i| {
+                Trapezoid::new(from[i], to[i], duration_ms, &limits[i])
+            }),
+            track,
+        }
+    }
+
+    // Positions at `at` milliseconds into the move
+    pub fn positions(&self, at: f32) -> [i32; AXES] {
+        let aim = self.track.map(|t| t.aim(self.profiles[0].at(at)));
+        core::array::from_fn(|i| match (i, aim) {
+            (1, Some((pan, _))) => pan,
+            (2, Some((_, Some(tilt)))) => tilt,
+            _ => self.profiles[i].at(at),
+        })
+    }
+}
+
+// How often the executor aims the axes, each time at where the move will be
+// by the next update
+pub const UPDATE_MS: u32 = 10;
+
+// The clock of a move changing its rate linearly between `rates` over `ms`.
+// Every axis brakes or speeds up with it and stays on its path.
+#[derive(Clone, Copy, Debug)]
+struct Ramp {
+    since: u32,
+    // into the move when the ramp began
+    from_ms: f32,
+    ms: u32,
+    rates: (f32, f32),
+    // between standing and full speed, kept so a resume mirrors its pause
+    full_ms: u32,
+    // the move ends once it stands
+    ends: bool,
+}
+
+impl Ramp {
+    fn done(&self, now: u32) -> bool {
+        now.wrapping_sub(self.since) >= self.ms
+    }
+
+    fn rate(&self, now: u32) -> f32 {
+        let (from, to) = self.rates;
+        match self.done(now) {
+            true => to,
+            false => from + (to - from) * now.wrapping_sub(self.since) as f32 / self.ms as f32,
+        }
+    }
+
+    // The area under the rate so far, then on at the final one. Kept in
+    // fractions of a millisecond, whole ones would jerk the speed by a tenth
+    // from one update to the next.
+    fn clock(&self, now: u32) -> f32 {
+        let t = now.wrapping_sub(self.since);
+        let ramp = (self.rates.0 + self.rate(now)) / 2.0 * t.min(self.ms) as f32;
+        let after = self.rates.1 * t.saturating_sub(self.ms) as f32;
+        self.from_ms + ramp + after
+    }
+}
+
+// A path on its schedule, which a pause, resume or abort bends. The dolly's
+// executor steps the axes along it, the simulator just reads it off.
+#[derive(Clone, Copy, Debug)]
+pub struct Move {
+    path: Path,
+    started: u32,
+    duration_ms: u32,
+    ramp: Option<Ramp>,
+}
+
+impl Move {
+    // `started` may lie in the past to keep a longer schedule on time
+    pub fn new(path: Path, duration_ms: u32, started: u32) -> Self {
+        Self {
+            path,
+            started,
+            duration_ms,
+            ramp: None,
+        }
+    }
+
+    // Into the move by its own clock, which only a ramp sets apart from `started`
+    fn clock(&self, now: u32) -> f32 {
+        match &self.ramp {
+            Some(ramp) => ramp.clock(now),
+            None => now.wrapping_sub(self.started) as f32,
+        }
+    }
+
+    fn elapsed(&self, now: u32) -> u32 {
+        match &self.ramp {
+            Some(ramp) => libm::roundf(ramp.clock(now)) as u32,
+            None => now.wrapping_sub(self.started),
+        }
+    }
+
+    // Where the axes belong at `now`
+    pub fn positions(&self, now: u32) -> [i32; AXES] {
+        self.path.positions(self.clock(now))
+    }
+
+    pub fn remaining_ms(&self, now: u32) -> u32 {
+        self.duration_ms.saturating_sub(self.elapsed(now))
+    }
+
+    // The axes brake to a stop on their path and hold there
+    pub fn pause(&mut self, limits: &[Limits; AXES], now: u32) {
+        self.ease(limits, now, 0.0, false);
+    }
+
+    // Back up to speed from wherever the move is held, the rest of it runs
+    // late by as long as it was slowed down
+    pub fn resume(&mut self, limits: &[Limits; AXES], now: u32) {
+        self.ease(limits, now, 1.0, false);
+    }
+
+    // Brakes like a pause, then the move is over
+    pub fn abort(&mut self, limits: &[Limits; AXES], now: u32) {
+        self.ease(limits, now, 0.0, true);
+    }
+
+    // While a pause, resume or abort is still changing speed
+    pub fn is_easing(&self, now: u32) -> bool {
+        self.ramp.is_some_and(|r| !r.done(now))
+    }
+
+    // From wherever the clock's rate is, a part of the full ramp in proportion
+    fn ease(&mut self, limits: &[Limits; AXES], now: u32, rate: f32, ends: bool) {
+        let from_ms = self.clock(now);
+        let (from, full_ms) = match self.ramp {
+            Some(r) => (r.rate(now), r.full_ms),
+            None => (1.0, self.braking_ms(limits, from_ms)),
+        };
+        self.ramp = Some(Ramp {
+            since: now,
+            from_ms,
+            ms: libm::roundf(full_ms as f32 * libm::fabsf(rate - from)) as u32,
+            rates: (from, rate),
+            full_ms,
+            ends,
+        });
+    }
+
+    // How long the slowest axis takes to brake from its speed at `at`, twice
+    // what its limit allows since the profile may be accelerating meanwhile
+    fn braking_ms(&self, limits: &[Limits; AXES], at: f32) -> u32 {
+        let here = self.path.positions(at);
+        let next = self.path.positions(at + UPDATE_MS as f32);
+        limits
+            .iter()
+            .zip(here.iter().zip(next))
+            .map(|(l, (&here, next))| {
+                let velocity = here.abs_diff(next) as u64 * 1000 / UPDATE_MS as u64;
+                (2 * velocity * 1000 / l.max_acceleration.max(1) as u64) as u32
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Called once the axes were sent to where the move is by the next update,
+    // `idle` when they were there already. Some(true) once it has arrived,
+    // Some(false) once an abort has braked it to a stand.
+    pub fn settle(&mut self, now: u32, idle: bool) -> Option<bool> {
+        let elapsed = self.elapsed(now);
+        let mut ended = false;
+        if let Some(ramp) = self.ramp.filter(|r| r.done(now)) {
+            match ramp.ends {
+                true => ended = idle,
+                // back on a schedule, the old one shifted
+                false if ramp.rates.1 > 0.0 => {
+                    self.started = now.wrapping_sub(elapsed);
+                    self.ramp = None;
+                }
+                false => {}
+            }
+        }
+
+        let arrived = idle && elapsed >= self.duration_ms;
+        (arrived || ended).then_some(arrived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rig::SLIDER_LIMITS;
+
+    #[test]
+    fn rest_to_rest_on_time() {
+        let t = Trapezoid::new(100_000, -200_000, 5_000, &SLIDER_LIMITS);
+        assert_eq!(t.at(0.0), 100_000);
+        assert_eq!(t.at(5_000.0), -200_000);
+        assert_eq!(t.at(9_000.0), -200_000);
+        // symmetric, halfway through it is halfway there
+        assert!(t.at(2_500.0).abs_diff(-50_000) <= 1);
+    }
+
+    #[test]
+    fn motion_along_the_way() {
+        // 200 mm/s² up to 60 mm/s, cruise, and down again
+        let t = Trapezoid::new(0, 300_000, 5_300, &SLIDER_LIMITS);
+        let close = |a: f32, b: f32| (a - b).abs() < 0.01 * b.abs().max(1.0);
+
+        let (speed, accel) = t.motion_at(0.0);
+        assert!(close(speed, 0.0) && close(accel, 200_000.0));
+        // a quarter of the way up to speed
+        let (speed, accel) = t.motion_at(2_250.0);
+        assert!(close(speed, 30_000.0), "{speed}");
+        assert!(close(accel, 200_000.0));
+        let (speed, accel) = t.motion_at(150_000.0);
+        assert!(close(speed, 60_000.0) && accel == 0.0, "{speed}");
+        let (speed, accel) = t.motion_at(300_000.0 - 2_250.0);
+        assert!(
+            close(speed, 30_000.0) && close(accel, -200_000.0),
+            "{speed}"
+        );
+        assert_eq!(t.motion_at(300_000.0).0, 0.0);
+    }
+
+    #[test]
+    fn standing_still() {
+        let t = Trapezoid::new(5, 5, 1_000, &SLIDER_LIMITS);
+        assert_eq!(t.at(500.0), 5);
+        assert_eq!(t.motion_at(0.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn path_aims_when_tracking() {
+        let limits = crate::rig::LIMITS;
+        let (from, to) = ([0, 10_000, 5_000], [100_000, -10_000, 5_000]);
+        let plain = Path::new(&limits, &from, &to, 4_000, None);
+        assert_eq!(plain.positions(0.0), from);
+        assert_eq!(plain.positions(4_000.0), to);
+
+        // pan follows the slider, tilt keeps its own profile
+        let target = Target::at_distance([50_000, 0, 0], 200_000, false).unwrap();
+        let tracked = Path::new(&limits, &from, &to, 4_000, Some(target));
+        for at in [0.0, 1_000.0, 2_000.0, 4_000.0] {
+            let [slider, pan, tilt] = tracked.positions(at);
+            assert_eq!(slider, plain.positions(at)[0]);
+            assert_eq!(pan, target.aim(slider).0);
+            assert_eq!(tilt, 5_000);
+        }
+    }
+
+    // Steps a move the way the executor does, the axes always keep up
+    fn play(m: &mut Move, from: u32, to: u32) -> (Option<bool>, [i32; AXES]) {
+        let mut at = m.positions(from);
+        for now in (from..to).step_by(UPDATE_MS as usize) {
+            let next = m.positions(now + UPDATE_MS);
+            let idle = next == at;
+            at = next;
+            if let Some(outcome) = m.settle(now, idle) {
+                return (Some(outcome), at);
+            }
+        }
+        (None, at)
+    }
+
+    #[test]
+    fn pause_holds_and_resume_runs_late() {
+        let limits = crate::rig::LIMITS;
+        let to = [100_000, 0, 0];
+        let path = Path::new(&limits, &[0; AXES], &to, 4_000, None);
+        let mut m = Move::new(path, 4_000, 0);
+
+        let (_, before) = play(&mut m, 0, 1_000);
+        m.pause(&limits, 1_000);
+        assert!(m.is_easing(1_000));
+        let (outcome, held) = play(&mut m, 1_000, 3_000);
+        assert_eq!(outcome, None);
+        assert!(held[0] > before[0] && held[0] < to[0]);
+        assert!(!m.is_easing(3_000));
+        // standing still, the schedule slips by the whole pause
+        assert_eq!(m.positions(6_000), held);
+
+        m.resume(&limits, 6_000);
+        // what was left of the schedule, less what braking covered
+        let (outcome, end) = play(&mut m, 6_000, 7_500);
+        assert_eq!(outcome, None);
+        assert!(end[0] < to[0]);
+        assert_eq!(play(&mut m, 9_000, 20_000), (Some(true), to));
+    }
+
+    #[test]
+    fn abort_ends_on_the_path() {
+        let limits = crate::rig::LIMITS;
+        let path = Path::new(&limits, &[0; AXES], &[100_000, 0, 0], 4_000, None);
+        let mut m = Move::new(path, 4_000, 0);
+
+        play(&mut m, 0, 2_000);
+        m.abort(&limits, 2_000);
+        let (outcome, at) = play(&mut m, 2_000, 4_000);
+        assert_eq!(outcome, Some(false));
+        assert!(at[0] > 0 && at[0] < 100_000);
+    }
+}
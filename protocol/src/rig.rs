@@ -0,0 +1,47 @@
+// The rig the firmware is built for. The firmware drives its axes with these
+// calibrations and within these limits and the simulator plays against the
+// same ones, so a sequence refused by one is refused by the other.
+
+use crate::{
+    plan::AXES,
+    units::{deg, mm, Calibration},
+    Limits,
+};
+
+// 1/8 microstepping on a GT2 20T pulley, 40 steps per mm
+pub const SLIDER: Calibration = Calibration::belt(200, 8, 2_000, 20);
+// 1/16 microstepping through a 60:20 belt reduction
+pub const PAN: Calibration = Calibration::rotary(200, 16, 60, 20);
+pub const TILT: Calibration = Calibration::rotary(200, 16, 60, 20);
+// direct drive
+pub const AUX: Calibration = Calibration::rotary(200, 16, 1, 1);
+
+// 1.2m rail, zero is the homing end
+pub const SLIDER_LIMITS: Limits = Limits {
+    max_velocity: mm(100) as u32,
+    max_acceleration: mm(200) as u32,
+    min: 0,
+    max: mm(1_200),
+};
+pub const PAN_LIMITS: Limits = Limits {
+    max_velocity: deg(60) as u32,
+    max_acceleration: deg(120) as u32,
+    min: deg(-180),
+    max: deg(180),
+};
+pub const TILT_LIMITS: Limits = Limits {
+    max_velocity: deg(30) as u32,
+    max_acceleration: deg(60) as u32,
+    min: deg(-90),
+    max: deg(90),
+};
+// the fourth axis of the Mega 2560, never part of a sequence
+pub const AUX_LIMITS: Limits = Limits {
+    max_velocity: deg(90) as u32,
+    max_acceleration: deg(180) as u32,
+    min: deg(-360),
+    max: deg(360),
+};
+
+// in plan::AXIS_NAMES order
+pub const LIMITS: [Limits; AXES] = [SLIDER_LIMITS, PAN_LIMITS, TILT_LIMITS];
@@ -0,0 +1,110 @@
+use crate::{ErrorCode, Keyframe};
+
+// The keyframes the dolly keeps, in order of their time
+
+#[derive(Clone, Debug)]
+pub struct Sequence {
+    keyframes: [Keyframe; Self::MAX_KEYFRAMES],
+    len: usize,
+}
+
+impl Sequence {
+    pub const MAX_KEYFRAMES: usize = 16;
+
+    pub const fn new() -> Self {
+        Self {
+            keyframes: [Keyframe {
+                time_ms: 0,
+                slider: 0,
+                pan: 0,
+                tilt: 0,
+            }; Self::MAX_KEYFRAMES],
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // Replaces an existing keyframe or appends right after the last one
+    pub fn set(&mut self, index: usize, keyframe: Keyframe) -> Result<(), ErrorCode> {
+        if index > self.len || index >= Self::MAX_KEYFRAMES {
+            return Err(ErrorCode::OutOfRange);
+        }
+
+        // keyframes must stay in chronological order
+        let after_previous = index == 0 || self.keyframes[index - 1].time_ms < keyframe.time_ms;
+        let before_next =
+            index + 1 >= self.len || keyframe.time_ms < self.keyframes[index + 1].time_ms;
+        if !after_previous || !before_next {
+            return Err(ErrorCode::InvalidValue);
+        }
+
+        self.keyframes[index] = keyframe;
+        self.len = self.len.max(index + 1);
+        Ok(())
+    }
+
+    pub fn get(&self, index: usize) -> Option<Keyframe> {
+        self.keyframes().get(index).copied()
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes[..self.len]
+    }
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time_ms: u32) -> Keyframe {
+        Keyframe {
+            time_ms,
+            ..Keyframe::default()
+        }
+    }
+
+    #[test]
+    fn appends_and_replaces_in_order() {
+        let mut s = Sequence::new();
+        assert_eq!(s.set(0, at(0)), Ok(()));
+        assert_eq!(s.set(1, at(1_000)), Ok(()));
+        assert_eq!(s.set(1, at(2_000)), Ok(()));
+        assert_eq!(s.keyframes(), &[at(0), at(2_000)]);
+        assert_eq!(s.get(1), Some(at(2_000)));
+        assert_eq!(s.get(2), None);
+
+        s.clear();
+        assert!(s.keyframes().is_empty());
+    }
+
+    #[test]
+    fn refuses_gaps_and_disorder() {
+        let mut s = Sequence::new();
+        assert_eq!(s.set(1, at(0)), Err(ErrorCode::OutOfRange));
+        s.set(0, at(1_000)).unwrap();
+        s.set(1, at(2_000)).unwrap();
+        assert_eq!(s.set(2, at(2_000)), Err(ErrorCode::InvalidValue));
+        // not past the next one either
+        assert_eq!(s.set(0, at(3_000)), Err(ErrorCode::InvalidValue));
+        assert_eq!(s.keyframes(), &[at(1_000), at(2_000)]);
+    }
+
+    #[test]
+    fn holds_so_many() {
+        let mut s = Sequence::new();
+        for i in 0..Sequence::MAX_KEYFRAMES {
+            s.set(i, at(i as u32)).unwrap();
+        }
+        let full = Sequence::MAX_KEYFRAMES;
+        assert_eq!(s.set(full, at(100)), Err(ErrorCode::OutOfRange));
+    }
+}
@@ -0,0 +1,233 @@
+// The text shell both the dolly and the simulator answer on the serial line,
+// one command per line. Commands of features the dolly was built without
+// still parse, it answers them as unknown.
+
+use crate::Mode;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    pub fn from_u8(level: u8) -> Option<Self> {
+        match level {
+            0 => Some(Level::Off),
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+// How telemetry is streamed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    // `TLM ms state slider pan tilt v_slider v_pan v_tilt frame remaining_s battery_mv
+    // loop_hz overruns max_lateness_ms`
+    Line = 0,
+    Binary = 1,
+}
+
+impl Format {
+    pub fn from_u8(format: u8) -> Option<Self> {
+        match format {
+            0 => Some(Format::Line),
+            1 => Some(Format::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "line" => Some(Format::Line),
+            "binary" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Line => "line",
+            Format::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    ShowLogLevel,
+    SetLogLevel(Level),
+    Acknowledge,
+    // homes again, or with `skip` allows motion without it
+    Home,
+    SkipHoming,
+    ShowPosition,
+    Pause,
+    Resume,
+    Abort,
+    ShowCrash,
+    ClearCrash,
+    ShowPlayback,
+    SetPlayback {
+        mode: Mode,
+        repeat: Option<u16>,
+    },
+    SetDwell {
+        start_ms: u32,
+        end_ms: u32,
+    },
+    ShowTelemetry,
+    SetTelemetry {
+        period_ms: u16,
+        format: Option<Format>,
+    },
+    Unknown,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Self {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("ack"), None) => Command::Acknowledge,
+            (Some("home"), None) => Command::Home,
+            (Some("home"), Some("skip")) => Command::SkipHoming,
+            (Some("pos"), None) => Command::ShowPosition,
+            (Some("pause"), None) => Command::Pause,
+            (Some("resume"), None) => Command::Resume,
+            (Some("abort"), None) => Command::Abort,
+            (Some("crash"), None) => Command::ShowCrash,
+            (Some("crash"), Some("clear")) => Command::ClearCrash,
+            (Some("play"), None) => Command::ShowPlayback,
+            (Some("play"), Some(mode)) => {
+                let Some(mode) = Mode::parse(mode) else {
+                    return Command::Unknown;
+                };
+                let repeat = match words.next().map(str::parse) {
+                    Some(Ok(repeat)) => Some(repeat),
+                    Some(Err(_)) => return Command::Unknown,
+                    None => None,
+                };
+                Command::SetPlayback { mode, repeat }
+            }
+            (Some("dwell"), Some(start)) => match (start.parse(), words.next().map(str::parse)) {
+                (Ok(start_ms), Some(Ok(end_ms))) => Command::SetDwell { start_ms, end_ms },
+                _ => Command::Unknown,
+            },
+            (Some("telemetry"), None) => Command::ShowTelemetry,
+            (Some("telemetry"), Some("off")) => Command::SetTelemetry {
+                period_ms: 0,
+                format: None,
+            },
+            (Some("telemetry"), Some(period)) => {
+                let format = match words.next() {
+                    Some(name) => match Format::parse(name) {
+                        Some(format) => Some(format),
+                        None => return Command::Unknown,
+                    },
+                    None => None,
+                };
+                match period.parse() {
+                    Ok(period_ms) => Command::SetTelemetry { period_ms, format },
+                    Err(_) => Command::Unknown,
+                }
+            }
+            (Some("log"), None) => Command::ShowLogLevel,
+            (Some("log"), Some(level)) => match Level::parse(level) {
+                Some(level) => Command::SetLogLevel(level),
+                None => Command::Unknown,
+            },
+            _ => Command::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(Command::parse("home skip"), Command::SkipHoming);
+        assert_eq!(Command::parse("  pause "), Command::Pause);
+        assert_eq!(
+            Command::parse("log debug"),
+            Command::SetLogLevel(Level::Debug)
+        );
+        assert_eq!(
+            Command::parse("play pingpong 3"),
+            Command::SetPlayback {
+                mode: Mode::PingPong,
+                repeat: Some(3)
+            }
+        );
+        assert_eq!(
+            Command::parse("dwell 500 1000"),
+            Command::SetDwell {
+                start_ms: 500,
+                end_ms: 1_000
+            }
+        );
+        assert_eq!(
+            Command::parse("telemetry 250 binary"),
+            Command::SetTelemetry {
+                period_ms: 250,
+                format: Some(Format::Binary)
+            }
+        );
+        assert_eq!(
+            Command::parse("telemetry off"),
+            Command::SetTelemetry {
+                period_ms: 0,
+                format: None
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_commands() {
+        for line in [
+            "",
+            "jump",
+            "log loud",
+            "play sideways",
+            "play loop x",
+            "dwell 500",
+            "telemetry 70000",
+            "telemetry 250 morse",
+        ] {
+            assert_eq!(Command::parse(line), Command::Unknown, "{line}");
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{crc::crc16, plan, plan::AXES, ErrorCode, Keyframe, Step, StopMotion};
+
+// A stop-motion animation: the move from the first keyframe to the last cut
+// into equal increments, one per step. Frame n lies n/steps of the way along,
+// worked out from the ends every time so rounding never adds up.
+//
+// The frame is kept together with the move it belongs to, the dolly in its
+// EEPROM, so an animation shot over days carries on after the power was off.
+
+const MAGIC: u16 = 0x5A3D;
+// magic, both ends, steps, frame and the CRC over all of that
+pub const RECORD_LEN: usize = 2 + 2 * 4 * AXES + 2 + 2 + 2;
+const FRAME_AT: usize = RECORD_LEN - 4;
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    from: [i32; AXES],
+    to: [i32; AXES],
+    steps: u16,
+    frame: u16,
+    pub settle_ms: u32,
+    pub exposure_ms: u32,
+}
+
+impl Animation {
+    pub fn new(first: &Keyframe, last: &Keyframe, setup: &StopMotion) -> Result<Self, ErrorCode> {
+        if setup.steps == 0 {
+            return Err(ErrorCode::InvalidValue);
+        }
+
+        Ok(Self {
+            from: plan::positions(first),
+            to: plan::positions(last),
+            steps: setup.steps,
+            frame: 0,
+            settle_ms: setup.settle_ms as u32,
+            exposure_ms: setup.exposure_ms as u32,
+        })
+    }
+
+    pub fn frame(&self) -> u16 {
+        self.frame
+    }
+
+    pub fn steps(&self) -> u16 {
+        self.steps
+    }
+
+    // The frame it starts or resumes at is shot on arrival, a power cut may
+    // have come before its exposure
+    pub fn shoots(&self) -> bool {
+        self.exposure_ms > 0
+    }
+
+    // Where the current frame is, before any tracking
+    pub fn position(&self) -> Keyframe {
+        let at = |axis: usize| {
+            let (from, to) = (self.from[axis] as i64, self.to[axis] as i64);
+            (from + (to - from) * self.frame as i64 / self.steps as i64) as i32
+        };
+
+        Keyframe {
+            time_ms: 0,
+            slider: at(0),
+            pan: at(1),
+            tilt: at(2),
+        }
+    }
+
+    // False at either end, the frame stays where it is
+    pub fn step(&mut self, forward: bool) -> bool {
+        let next = match forward {
+            true => self.frame.checked_add(1).filter(|&f| f <= self.steps),
+            false => self.frame.checked_sub(1),
+        };
+
+        match next {
+            Some(frame) => {
+                self.frame = frame;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Some with whether to shoot the frame it got to, None at either end.
+    // Only a Shoot step shoots.
+    pub fn take(&mut self, step: Step) -> Option<bool> {
+        match self.step(step != Step::Back) {
+            true => Some(step == Step::Shoot && self.shoots()),
+            false => None,
+        }
+    }
+
+    pub fn record(&self) -> [u8; RECORD_LEN] {
+        let mut record = [0; RECORD_LEN];
+        let fields = self.from.iter().chain(self.to.iter());
+        let bytes = MAGIC
+            .to_le_bytes()
+            .into_iter()
+            .chain(fields.flat_map(|v| v.to_le_bytes()))
+            .chain(self.steps.to_le_bytes())
+            .chain(self.frame.to_le_bytes());
+        for (slot, byte) in record.iter_mut().zip(bytes) {
+            *slot = byte;
+        }
+
+        let crc = crc16(&record[..RECORD_LEN - 2]);
+        record[RECORD_LEN - 2..].copy_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    // Takes up the remembered frame when it was saved for this same move
+    pub fn restore(&mut self, stored: &[u8; RECORD_LEN]) -> bool {
+        let crc = u16::from_le_bytes([stored[RECORD_LEN - 2], stored[RECORD_LEN - 1]]);
+        let frame = u16::from_le_bytes([stored[FRAME_AT], stored[FRAME_AT + 1]]);
+        // everything before the frame is the magic and the move
+        let same = stored[..FRAME_AT] == self.record()[..FRAME_AT];
+        if !same || crc != crc16(&stored[..RECORD_LEN - 2]) || frame > self.steps {
+            return false;
+        }
+
+        self.frame = frame;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(steps: u16) -> StopMotion {
+        StopMotion {
+            steps,
+            settle_ms: 0,
+            exposure_ms: 0,
+            resume: true,
+        }
+    }
+
+    fn ends() -> (Keyframe, Keyframe) {
+        let first = Keyframe::default();
+        let last = Keyframe {
+            time_ms: 10_000,
+            slider: 100_000,
+            pan: -30_000,
+            tilt: 10_000,
+        };
+        (first, last)
+    }
+
+    #[test]
+    fn frames_cut_the_move_evenly() {
+        let (first, last) = ends();
+        let mut a = Animation::new(&first, &last, &setup(3)).unwrap();
+        assert_eq!(a.position(), Keyframe::default());
+        assert!(!a.step(false));
+
+        assert!(a.step(true));
+        let k = a.position();
+        assert_eq!((k.slider, k.pan, k.tilt), (33_333, -10_000, 3_333));
+        assert!(a.step(true) && a.step(true));
+        assert_eq!(a.position(), Keyframe { time_ms: 0, ..last });
+        assert!(!a.step(true));
+        assert_eq!(a.frame(), 3);
+
+        assert!(Animation::new(&first, &last, &setup(0)).is_err());
+    }
+
+    #[test]
+    fn shoots_the_start_and_every_shoot_step() {
+        let (first, last) = ends();
+        let exposing = StopMotion {
+            exposure_ms: 100,
+            ..setup(4)
+        };
+        let mut a = Animation::new(&first, &last, &exposing).unwrap();
+        let mut shot = [false; 5];
+        shot[a.frame() as usize] = a.shoots();
+        let steps = [Step::Forward, Step::Shoot, Step::Back, Step::Shoot];
+        for step in steps.into_iter().chain([Step::Forward, Step::Shoot]) {
+            if a.take(step).unwrap() {
+                shot[a.frame() as usize] = true;
+            }
+        }
+        assert_eq!(shot, [true, false, true, false, true]);
+        assert_eq!(a.take(Step::Shoot), None);
+
+        // resumed, the frame it stopped at is shot again
+        let mut again = Animation::new(&first, &last, &exposing).unwrap();
+        assert!(again.restore(&a.record()) && again.shoots());
+        assert_eq!(again.frame(), 4);
+
+        let mut still = Animation::new(&first, &last, &setup(4)).unwrap();
+        assert!(!still.shoots());
+        assert_eq!(still.take(Step::Shoot), Some(false));
+    }
+
+    #[test]
+    fn resumes_only_the_same_move() {
+        let (first, last) = ends();
+        let mut a = Animation::new(&first, &last, &setup(24)).unwrap();
+        a.step(true);
+        a.step(true);
+        let saved = a.record();
+
+        let mut again = Animation::new(&first, &last, &setup(24)).unwrap();
+        assert!(again.restore(&saved));
+        assert_eq!(again.frame(), 2);
+
+        let mut other = Animation::new(&first, &last, &setup(12)).unwrap();
+        assert!(!other.restore(&saved));
+        assert_eq!(other.frame(), 0);
+
+        let mut worn = saved;
+        worn[3] ^= 1;
+        assert!(!again.restore(&worn));
+        assert!(!again.restore(&[0xFF; RECORD_LEN]));
+    }
+}
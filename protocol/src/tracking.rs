@@ -0,0 +1,233 @@
+use crate::{plan::AXES, Keyframe};
+
+// A subject fixed in space, for pan and tilt to follow while the slider moves.
+//
+// Coordinates are micrometres with the rail as the x axis from slider zero,
+// y straight out from the rail towards the subject and z up from the lens.
+// Pan 0 looks along y and turns towards +x, tilt 0 is level. Sightings are
+// slider, pan and tilt in plan::AXIS_NAMES order, as keyframes have them.
+
+const NEAR_PARALLEL: f32 = 1e-3;
+
+fn radians(millidegrees: i32) -> f32 {
+    (millidegrees as f32 / 1000.0).to_radians()
+}
+
+fn millidegrees(radians: f32) -> i32 {
+    libm::roundf(radians.to_degrees() * 1000.0) as i32
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Target {
+    x: f32,
+    y: f32,
+    z: f32,
+    follow_tilt: bool,
+}
+
+impl Target {
+    // None behind the rail, the head can't look there without a half turn
+    fn located(x: f32, y: f32, z: f32, follow_tilt: bool) -> Option<Self> {
+        (y > 0.0).then_some(Self {
+            x,
+            y,
+            z,
+            follow_tilt,
+        })
+    }
+
+    pub fn at_distance(sighting: [i32; AXES], distance_um: u32, follow_tilt: bool) -> Option<Self> {
+        let [slider, pan, tilt] = sighting;
+        let (pan, tilt) = (radians(pan), radians(tilt));
+        let distance = distance_um as f32;
+        let flat = distance * libm::cosf(tilt);
+
+        Self::located(
+            slider as f32 + flat * libm::sinf(pan),
+            flat * libm::cosf(pan),
+            distance * libm::sinf(tilt),
+            follow_tilt,
+        )
+    }
+
+    // Where the lines of sight from two slider positions cross. None when
+    // they are close to parallel, the crossing would be mostly noise.
+    pub fn triangulate(a: [i32; AXES], b: [i32; AXES], follow_tilt: bool) -> Option<Self> {
+        let (slider_a, slider_b) = (a[0] as f32, b[0] as f32);
+        let (tan_a, tan_b) = (libm::tanf(radians(a[1])), libm::tanf(radians(b[1])));
+        if libm::fabsf(tan_a - tan_b) < NEAR_PARALLEL {
+            return None;
+        }
+
+        let y = (slider_b - slider_a) / (tan_a - tan_b);
+        let x = slider_a + y * tan_a;
+        // both tilts give a height, they only differ by aiming error
+        let height =
+            |slider: f32, tilt: i32| libm::tanf(radians(tilt)) * libm::hypotf(x - slider, y);
+        let z = (height(slider_a, a[2]) + height(slider_b, b[2])) / 2.0;
+
+        Self::located(x, y, z, follow_tilt)
+    }
+
+    // micrometres, for the log
+    pub fn from_rail(&self) -> i32 {
+        self.y as i32
+    }
+
+    // Pan, and tilt when it follows, that look at the subject from `slider`
+    pub fn aim(&self, slider: i32) -> (i32, Option<i32>) {
+        let dx = self.x - slider as f32;
+        let pan = millidegrees(libm::atan2f(dx, self.y));
+        let tilt = millidegrees(libm::atan2f(self.z, libm::hypotf(dx, self.y)));
+        (pan, self.follow_tilt.then_some(tilt))
+    }
+
+    pub fn follows_tilt(&self) -> bool {
+        self.follow_tilt
+    }
+
+    // Slider positions where pan turns fastest, then where its turning
+    // speeds up and slows down the most
+    pub fn turning_points(&self) -> [f32; 3] {
+        let off = self.y / libm::sqrtf(3.0);
+        [self.x, self.x - off, self.x + off]
+    }
+
+    // How pan and tilt change as the slider moves, first and second
+    // derivatives in millidegrees per micrometre. Worked out rather than
+    // sampled, the differences between nearby angles drown in f32 rounding.
+    pub fn rates(&self, slider: f32) -> [(f32, f32); 2] {
+        const MILLIDEGREES: f32 = 180_000.0 / core::f32::consts::PI;
+
+        // u shrinks as the slider moves towards +x
+        let (u, y, z) = (self.x - slider, self.y, self.z);
+        let flat2 = u * u + y * y;
+        let flat = libm::sqrtf(flat2);
+        let full2 = flat2 + z * z;
+
+        // pan = atan2(u, y)
+        let pan = (-y / flat2, -2.0 * y * u / (flat2 * flat2));
+        // tilt = atan2(z, flat)
+        let tilt = (
+            z * u / (flat * full2),
+            -z * (y * y * full2 / flat - 2.0 * flat * u * u) / (flat2 * full2 * full2),
+        );
+
+        [pan, tilt].map(|(d1, d2)| (d1 * MILLIDEGREES, d2 * MILLIDEGREES))
+    }
+
+    // The keyframe as it will be played, with its angles replaced
+    pub fn apply(&self, k: Keyframe) -> Keyframe {
+        let (pan, tilt) = self.aim(k.slider);
+        Keyframe {
+            pan,
+            tilt: tilt.unwrap_or(k.tilt),
+            ..k
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a millidegree off after the round trip through f32
+    fn close(a: i32, b: i32) -> bool {
+        a.abs_diff(b) <= 2
+    }
+
+    #[test]
+    fn aims_back_along_the_sighting() {
+        let sighting = [300_000, 20_000, -5_000];
+        let target = Target::at_distance(sighting, 2_000_000, true).unwrap();
+        let (pan, tilt) = target.aim(sighting[0]);
+        assert!(close(pan, sighting[1]), "{pan}");
+        assert!(close(tilt.unwrap(), sighting[2]));
+    }
+
+    #[test]
+    fn triangulates_what_it_aims_at() {
+        let target = Target::at_distance([500_000, 0, 10_000], 3_000_000, true).unwrap();
+        let sighting = |slider| {
+            let (pan, tilt) = target.aim(slider);
+            [slider, pan, tilt.unwrap()]
+        };
+
+        let found = Target::triangulate(sighting(0), sighting(1_000_000), true).unwrap();
+        assert!(found.from_rail().abs_diff(target.from_rail()) < 1_000);
+        for slider in [0, 250_000, 1_200_000] {
+            let (want, found) = (target.aim(slider), found.aim(slider));
+            assert!(close(want.0, found.0));
+            assert!(close(want.1.unwrap(), found.1.unwrap()));
+        }
+    }
+
+    #[test]
+    fn tilt_only_when_it_follows() {
+        let target = Target::at_distance([0, 0, 0], 1_000_000, false).unwrap();
+        let k = Keyframe {
+            time_ms: 5,
+            slider: 1_000_000,
+            pan: 0,
+            tilt: 12_000,
+        };
+        let played = target.apply(k);
+        assert!(close(played.pan, -45_000), "{}", played.pan);
+        assert_eq!((played.tilt, played.time_ms), (12_000, 5));
+    }
+
+    #[test]
+    fn refuses_subjects_it_cant_place() {
+        // behind the rail
+        assert!(Target::at_distance([0, 180_000, 0], 1_000_000, false).is_none());
+        // the same angle from both ends, the lines of sight never cross
+        assert!(Target::triangulate([0, 30_000, 0], [500_000, 30_000, 0], false).is_none());
+        // they cross behind the rail
+        assert!(Target::triangulate([0, -10_000, 0], [500_000, 10_000, 0], false).is_none());
+    }
+
+    #[test]
+    fn rates_follow_the_angles() {
+        let target = Target::at_distance([400_000, 30_000, -20_000], 500_000, true).unwrap();
+        let angles = |slider| {
+            let (pan, tilt) = target.aim(slider);
+            [pan as f32, tilt.unwrap() as f32]
+        };
+        // the aimed angles are rounded, wide steps keep that out of the way
+        let (h1, h2) = (2_000, 50_000);
+
+        for slider in [0, 300_000, 400_000, 650_000, 1_200_000] {
+            let rates = target.rates(slider as f32);
+            let (a, b) = (angles(slider - h1), angles(slider + h1));
+            let (c, here, d) = (angles(slider - h2), angles(slider), angles(slider + h2));
+
+            for (i, (d1, d2)) in rates.into_iter().enumerate() {
+                let want1 = (b[i] - a[i]) / (2 * h1) as f32;
+                let want2 = (c[i] - 2.0 * here[i] + d[i]) / (h2 as f32 * h2 as f32);
+                assert!(
+                    (d1 - want1).abs() <= 0.01 * want1.abs() + 1e-4,
+                    "{d1} {want1}"
+                );
+                assert!(
+                    (d2 - want2).abs() <= 0.05 * want2.abs() + 2e-9,
+                    "{d2} {want2}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn turns_fastest_passing_the_subject() {
+        let target = Target::at_distance([250_000, 0, 0], 100_000, false).unwrap();
+        let [passing, before, after] = target.turning_points();
+        assert!((passing - 250_000.0).abs() < 1.0);
+
+        let pan = |slider: f32| target.rates(slider)[0];
+        assert!(pan(passing).0.abs() > pan(passing + 1_000.0).0.abs());
+        // pan speeds up the most ahead of the subject and slows down the most after it
+        for at in [before, after] {
+            let d2 = pan(at).1.abs();
+            assert!(d2 >= pan(at - 1_000.0).1.abs() && d2 >= pan(at + 1_000.0).1.abs());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Positions are integers in thousandths of the unit people think in:
+// micrometres on linear axes and millidegrees on rotary ones. Speeds and
+// accelerations use the same units per second and per second squared, so one
+// calibration converts all three.
+
+pub const fn mm(v: i32) -> i32 {
+    v * 1000
+}
+
+pub const fn deg(v: i32) -> i32 {
+    v * 1000
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Micrometre,
+    Millidegree,
+}
+
+impl Unit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Micrometre => "mm",
+            Unit::Millidegree => "deg",
+        }
+    }
+}
+
+const fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+// rounds half away from zero, so forward and backward moves stay symmetric
+const fn div_round(n: i64, d: i64) -> i64 {
+    match n >= 0 {
+        true => (n + d / 2) / d,
+        false => (n - d / 2) / d,
+    }
+}
+
+// Steps per unit as a reduced fraction, a float would drift over a long move
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    unit: Unit,
+    steps: u32,
+    units: u32,
+}
+
+impl Calibration {
+    const fn new(unit: Unit, steps: u32, units: u32) -> Self {
+        assert!(steps > 0 && units > 0, "calibration needs a non-zero ratio");
+
+        let d = gcd(steps, units);
+        Self {
+            unit,
+            steps: steps / d,
+            units: units / d,
+        }
+    }
+
+    // One pulley revolution moves the carriage by pitch * teeth
+    pub const fn belt(full_steps: u32, microsteps: u32, pitch_um: u32, teeth: u32) -> Self {
+        Self::new(Unit::Micrometre, full_steps * microsteps, pitch_um * teeth)
+    }
+
+    // Gear or belt reduction between the motor and the head, as driven:driving
+    // teeth, e.g. 60:20 for a 3:1 reduction
+    pub const fn rotary(full_steps: u32, microsteps: u32, driven: u32, driving: u32) -> Self {
+        Self::new(
+            Unit::Millidegree,
+            full_steps * microsteps * driven,
+            deg(360) as u32 * driving,
+        )
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    pub const fn to_steps(&self, units: i32) -> i32 {
+        div_round(units as i64 * self.steps as i64, self.units as i64) as i32
+    }
+
+    pub const fn to_units(&self, steps: i32) -> i32 {
+        div_round(steps as i64 * self.units as i64, self.steps as i64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn belt_and_rotary() {
+        // GT2 20T at 1/8 microstepping, 40 steps per mm
+        let slider = Calibration::belt(200, 8, 2_000, 20);
+        assert_eq!(slider.to_steps(mm(1)), 40);
+        assert_eq!(slider.to_units(1), 25);
+        assert_eq!(slider.unit(), Unit::Micrometre);
+
+        // 3:1 at 1/16, 9600 steps per turn
+        let pan = Calibration::rotary(200, 16, 60, 20);
+        assert_eq!(pan.to_steps(deg(360)), 9_600);
+        assert_eq!(pan.to_steps(deg(-90)), -2_400);
+        assert_eq!(pan.unit(), Unit::Millidegree);
+    }
+
+    #[test]
+    fn rounds_both_ways_alike() {
+        let pan = Calibration::rotary(200, 16, 60, 20);
+        // 37.5 millidegrees per step
+        assert_eq!(pan.to_units(1), 38);
+        assert_eq!(pan.to_units(-1), -38);
+        assert_eq!(pan.to_steps(19), 1);
+        assert_eq!(pan.to_steps(-19), -1);
+        assert_eq!(pan.to_steps(18), 0);
+    }
+}
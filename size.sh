@@ -0,0 +1,54 @@
+#!/bin/bash
+
+set -e
+
+# Builds every board profile with the features it ships with and fails when
+# an image outgrows its part. Flash holds .text and .data, and RAM holds .data
+# and .bss, including the .noinit crash report. Whatever RAM is left over is
+# the stack.
+
+if [ "$1" = "--help" ] || [ "$1" = "-h" ]; then
+    echo "usage: $0 [uno|nano|mega2560]..." >&2
+    exit 1
+fi
+
+# the Cargo.toml defaults, less the board. The RAM budgets keep 512 bytes
+# of the 328P and 1 KB of the Mega for the stack.
+subsystems="ir,joystick,serial-shell,telemetry,timelapse"
+
+check() {
+    case "$1" in
+        # Optiboot takes the top 512 bytes
+        uno) mcu=atmega328p; features="uno,$subsystems"; flash=32256; ram=1536 ;;
+        # clones still ship the old 2 KB bootloader
+        nano) mcu=atmega328p; features="nano,$subsystems"; flash=30720; ram=1536 ;;
+        # 8 KB bootloader, and room for the runs the 328P leaves out
+        mega2560) mcu=atmega2560; features="mega2560,$subsystems,panorama,stop-motion,focus-stack"; flash=253952; ram=7168 ;;
+        *) echo "$0: Unknown board $1" >&2; exit 1 ;;
+    esac
+
+    elf="target/avr-${mcu}/release/rust-camera-dolly.elf"
+    docker run --rm -v "${PWD}":/usr/src/myapp dolly \
+        rustup run nightly cargo build --release --no-default-features \
+        --features "$features" --target "avr-${mcu}.json"
+    read -r text data bss _ < <(docker run --rm -v "${PWD}":/usr/src/myapp dolly avr-size "$elf" | tail -n 1)
+
+    used_flash=$((text + data))
+    used_ram=$((data + bss))
+    echo "$1: flash $used_flash/$flash bytes, RAM $used_ram/$ram bytes"
+    if [ "$used_flash" -gt "$flash" ] || [ "$used_ram" -gt "$ram" ]; then
+        echo "$0: $1 is over budget" >&2
+        failed=1
+    fi
+}
+
+boards=("$@")
+if [ "${#boards[@]}" -eq 0 ]; then
+    boards=(uno nano mega2560)
+fi
+
+failed=0
+for board in "${boards[@]}"; do
+    check "$board"
+done
+exit $failed
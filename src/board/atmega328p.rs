@@ -0,0 +1,92 @@
+use arduino_hal::{
+    hal::port::{PD0, PD1, PD2},
+    port::{
+        mode::{Floating, Input, Output},
+        Pin,
+    },
+};
+
+use crate::{
+    dolly::components::{arduino::ChannelType, stepper::tmc2209::SoftTx},
+    pcint::PinId,
+};
+
+pub type IrPin = PD2;
+pub type TmcLink = SoftTx;
+
+// A3 is PC3
+pub const SLIDER_DIAG_PCINT: PinId = PinId::pcint(11);
+
+// Maps PCINT group n to its bits, PCINT0..7 is PB, 8..14 PC and 16..23 PD
+pub fn read_pcint_group(group: u8) -> u8 {
+    // SAFETY: reading PINx has no side effects
+    unsafe {
+        match group {
+            0 => (*arduino_hal::pac::PORTB::ptr()).pinb.read().bits(),
+            1 => (*arduino_hal::pac::PORTC::ptr()).pinc.read().bits(),
+            _ => (*arduino_hal::pac::PORTD::ptr()).pind.read().bits(),
+        }
+    }
+}
+
+pub struct PinMap {
+    pub console_rx: Pin<Input<Floating>, PD0>,
+    pub console_tx: Pin<Output, PD1>,
+    #[cfg(feature = "ir")]
+    pub ir: Pin<Input<Floating>, IrPin>,
+    #[cfg(feature = "joystick")]
+    pub joy_switch: Pin<Input>,
+    #[cfg(feature = "joystick")]
+    pub joy_x: ChannelType,
+    #[cfg(feature = "joystick")]
+    pub joy_y: ChannelType,
+    #[cfg(feature = "telemetry")]
+    pub battery: ChannelType,
+    pub builtin_led: Pin<Output>,
+    pub in_led: Pin<Output>,
+    pub out_led: Pin<Output>,
+    pub drivers_enable: Pin<Output>,
+    pub shutter: Pin<Output>,
+    pub tmc_tx: Pin<Output>,
+    pub slider_diag: Pin<Input>,
+    pub slider_step: Pin<Output>,
+    pub slider_dir: Pin<Output>,
+    pub pan_step: Pin<Output>,
+    pub pan_dir: Pin<Output>,
+    pub tilt_step: Pin<Output>,
+    pub tilt_dir: Pin<Output>,
+}
+
+// Outputs come up in their safe state, drivers disabled and shutter released
+pub fn pin_map(pins: arduino_hal::Pins, adc: &mut arduino_hal::Adc) -> PinMap {
+    PinMap {
+        console_rx: pins.d0,
+        console_tx: pins.d1.into_output(),
+        #[cfg(feature = "ir")]
+        ir: pins.d2,
+        #[cfg(feature = "joystick")]
+        joy_switch: pins.d4.into_pull_up_input().downgrade().forget_imode(),
+        #[cfg(feature = "joystick")]
+        joy_x: pins.a0.into_analog_input(adc).into_channel(),
+        #[cfg(feature = "joystick")]
+        joy_y: pins.a1.into_analog_input(adc).into_channel(),
+        // the Nano has analog-only A6, which keeps A4 free for I2C
+        #[cfg(all(feature = "telemetry", feature = "uno"))]
+        battery: pins.a4.into_analog_input(adc).into_channel(),
+        #[cfg(all(feature = "telemetry", feature = "nano"))]
+        battery: arduino_hal::adc::channel::ADC6.into_channel(),
+        builtin_led: pins.d13.into_output().downgrade(),
+        in_led: pins.d6.into_output().downgrade(),
+        out_led: pins.d5.into_output().downgrade(),
+        drivers_enable: pins.a2.into_output_high().downgrade(),
+        shutter: pins.a5.into_output().downgrade(),
+        tmc_tx: pins.d3.into_output_high().downgrade(),
+        slider_diag: pins.a3.into_floating_input().downgrade().forget_imode(),
+        slider_step: pins.d7.into_output().downgrade(),
+        slider_dir: pins.d8.into_output().downgrade(),
+        pan_step: pins.d9.into_output().downgrade(),
+        pan_dir: pins.d10.into_output().downgrade(),
+        tilt_step: pins.d11.into_output().downgrade(),
+        tilt_dir: pins.d12.into_output().downgrade(),
+    }
+}
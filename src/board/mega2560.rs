@@ -0,0 +1,98 @@
+use arduino_hal::{
+    hal::port::{PD2, PD3, PE0, PE1, PE4},
+    port::{
+        mode::{Floating, Input, Output},
+        Pin,
+    },
+};
+
+use crate::{dolly::components::arduino::ChannelType, pcint::PinId};
+
+pub type IrPin = PE4;
+pub type TmcLink = arduino_hal::hal::usart::Usart1<arduino_hal::DefaultClock>;
+
+// A8 is PK0
+pub const SLIDER_DIAG_PCINT: PinId = PinId::pcint(16);
+
+// Maps PCINT group n to its bits, PCINT0..7 is PB, 8 is PE0 with 9..15 on
+// PJ0..6, and 16..23 PK
+pub fn read_pcint_group(group: u8) -> u8 {
+    // SAFETY: reading PINx has no side effects
+    unsafe {
+        match group {
+            0 => (*arduino_hal::pac::PORTB::ptr()).pinb.read().bits(),
+            1 => {
+                let pe0 = (*arduino_hal::pac::PORTE::ptr()).pine.read().bits() & 0x01;
+                let pj = (*arduino_hal::pac::PORTJ::ptr()).pinj.read().bits();
+                pe0 | pj << 1
+            }
+            _ => (*arduino_hal::pac::PORTK::ptr()).pink.read().bits(),
+        }
+    }
+}
+
+pub struct PinMap {
+    pub console_rx: Pin<Input<Floating>, PE0>,
+    pub console_tx: Pin<Output, PE1>,
+    #[cfg(feature = "ir")]
+    pub ir: Pin<Input<Floating>, IrPin>,
+    #[cfg(feature = "joystick")]
+    pub joy_switch: Pin<Input>,
+    #[cfg(feature = "joystick")]
+    pub joy_x: ChannelType,
+    #[cfg(feature = "joystick")]
+    pub joy_y: ChannelType,
+    #[cfg(feature = "telemetry")]
+    pub battery: ChannelType,
+    pub builtin_led: Pin<Output>,
+    pub in_led: Pin<Output>,
+    pub out_led: Pin<Output>,
+    pub drivers_enable: Pin<Output>,
+    pub shutter: Pin<Output>,
+    // USART1, RX1 is D19 and TX1 is D18
+    pub tmc_rx: Pin<Input<Floating>, PD2>,
+    pub tmc_tx: Pin<Output, PD3>,
+    pub slider_diag: Pin<Input>,
+    pub slider_step: Pin<Output>,
+    pub slider_dir: Pin<Output>,
+    pub pan_step: Pin<Output>,
+    pub pan_dir: Pin<Output>,
+    pub tilt_step: Pin<Output>,
+    pub tilt_dir: Pin<Output>,
+    pub aux_step: Pin<Output>,
+    pub aux_dir: Pin<Output>,
+}
+
+// Outputs come up in their safe state, drivers disabled and shutter released
+pub fn pin_map(pins: arduino_hal::Pins, adc: &mut arduino_hal::Adc) -> PinMap {
+    PinMap {
+        console_rx: pins.d0,
+        console_tx: pins.d1.into_output(),
+        #[cfg(feature = "ir")]
+        ir: pins.d2,
+        #[cfg(feature = "joystick")]
+        joy_switch: pins.d4.into_pull_up_input().downgrade().forget_imode(),
+        #[cfg(feature = "joystick")]
+        joy_x: pins.a0.into_analog_input(adc).into_channel(),
+        #[cfg(feature = "joystick")]
+        joy_y: pins.a1.into_analog_input(adc).into_channel(),
+        #[cfg(feature = "telemetry")]
+        battery: pins.a4.into_analog_input(adc).into_channel(),
+        builtin_led: pins.d13.into_output().downgrade(),
+        in_led: pins.d6.into_output().downgrade(),
+        out_led: pins.d5.into_output().downgrade(),
+        drivers_enable: pins.a2.into_output_high().downgrade(),
+        shutter: pins.a5.into_output().downgrade(),
+        tmc_rx: pins.d19,
+        tmc_tx: pins.d18.into_output(),
+        slider_diag: pins.a8.into_floating_input().downgrade().forget_imode(),
+        slider_step: pins.d7.into_output().downgrade(),
+        slider_dir: pins.d8.into_output().downgrade(),
+        pan_step: pins.d9.into_output().downgrade(),
+        pan_dir: pins.d10.into_output().downgrade(),
+        tilt_step: pins.d11.into_output().downgrade(),
+        tilt_dir: pins.d12.into_output().downgrade(),
+        aux_step: pins.d22.into_output().downgrade(),
+        aux_dir: pins.d23.into_output().downgrade(),
+    }
+}
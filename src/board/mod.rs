@@ -0,0 +1,35 @@
+/*
+* Board profiles, selected with exactly one cargo feature:
+* - uno (default) and nano: ATmega328P, three axes, TMC2209 on a transmit-only
+*   software UART
+* - mega2560: ATmega2560, a fourth axis and the TMC2209 on USART1, USART2 and
+*   USART3 stay free
+*
+* Each profile maps the pins to their roles, everything else only ever sees
+* the `PinMap`. Timers are the same on every board, see timer/mod.rs.
+*
+* Build the Mega with `--no-default-features --features mega2560` and
+* `--target avr-atmega2560.json`, plus the subsystems and runs it should have.
+* size.sh builds each profile with the features it ships with and checks the
+* result against the part's flash and RAM.
+*/
+
+#[cfg(not(any(feature = "uno", feature = "nano", feature = "mega2560")))]
+compile_error!("select a board with the uno, nano or mega2560 feature");
+
+#[cfg(any(
+    all(feature = "uno", feature = "nano"),
+    all(feature = "uno", feature = "mega2560"),
+    all(feature = "nano", feature = "mega2560"),
+))]
+compile_error!("only one board feature can be enabled, add --no-default-features");
+
+#[cfg(any(feature = "uno", feature = "nano"))]
+mod atmega328p;
+#[cfg(any(feature = "uno", feature = "nano"))]
+pub use self::atmega328p::*;
+
+#[cfg(feature = "mega2560")]
+mod mega2560;
+#[cfg(feature = "mega2560")]
+pub use self::mega2560::*;
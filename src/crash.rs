@@ -0,0 +1,113 @@
+// Crash report kept in .noinit RAM. The startup code neither clears nor
+// initialises that section, so the report survives the reset after a panic
+// (but not a power cycle). A magic number and CRC tell it apart from garbage.
+
+use core::{
+    mem::{size_of, MaybeUninit},
+    ptr::{self, addr_of, addr_of_mut},
+};
+
+use dolly_protocol::crc::crc16;
+use ufmt::{uDisplay, uwrite};
+
+const MAGIC: u16 = 0xC4A5;
+const FILE_LEN: usize = 24;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Panic = 1,
+    PanicWithoutLocation = 2,
+    // line holds the checkpoints that did check in
+    Watchdog = 3,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Report {
+    pub code: u8,
+    pub line: u32,
+    pub column: u32,
+    file: [u8; FILE_LEN],
+    file_len: u8,
+}
+
+#[repr(C)]
+struct Stored {
+    magic: u16,
+    report: Report,
+    crc: u16,
+}
+
+#[link_section = ".noinit"]
+static mut STORED: MaybeUninit<Stored> = MaybeUninit::uninit();
+
+impl Report {
+    pub fn new(code: Code, file: &str, line: u32, column: u32) -> Self {
+        // keep the end of the path, the file name is the useful part
+        let bytes = file.as_bytes();
+        let tail = &bytes[bytes.len().saturating_sub(FILE_LEN)..];
+
+        let mut report = Self {
+            code: code as u8,
+            line,
+            column,
+            file: [0; FILE_LEN],
+            file_len: tail.len() as u8,
+        };
+        report.file[..tail.len()].copy_from_slice(tail);
+        report
+    }
+
+    pub fn file(&self) -> &str {
+        core::str::from_utf8(&self.file[..self.file_len as usize]).unwrap_or("?")
+    }
+
+    fn crc(&self) -> u16 {
+        // SAFETY: Report is repr(C) and made of integers only, AVR has no padding
+        let bytes = unsafe {
+            core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>())
+        };
+        crc16(bytes)
+    }
+}
+
+impl uDisplay for Report {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: avr_hal_generic::prelude::_ufmt_uWrite + ?Sized,
+    {
+        uwrite!(
+            f,
+            "code {} at {}:{}:{}",
+            self.code,
+            self.file(),
+            self.line,
+            self.column
+        )
+    }
+}
+
+pub fn record(report: Report) {
+    let stored = Stored {
+        magic: MAGIC,
+        crc: report.crc(),
+        report,
+    };
+    // SAFETY: only called with interrupts disabled
+    unsafe { ptr::write_volatile(addr_of_mut!(STORED), MaybeUninit::new(stored)) };
+}
+
+pub fn last() -> Option<Report> {
+    // SAFETY: every bit pattern is a valid Stored, whatever the RAM held at power on
+    let stored = unsafe { ptr::read_volatile(addr_of!(STORED)).assume_init() };
+
+    let valid = stored.magic == MAGIC
+        && (stored.report.file_len as usize) <= FILE_LEN
+        && stored.report.crc() == stored.crc;
+    valid.then_some(stored.report)
+}
+
+pub fn clear() {
+    // SAFETY: magic is the first field of the repr(C) struct, zeroing it is enough
+    unsafe { ptr::write_volatile(addr_of_mut!(STORED).cast::<u16>(), 0) };
+}
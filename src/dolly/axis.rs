@@ -0,0 +1,81 @@
+use dolly_protocol::{plan::AXES, Limits};
+
+use super::{
+    components::stepper::{
+        homing::{HomingConfig, StallGuard},
+        Stepper,
+    },
+    motion::pulse::Channel,
+    units::{Calibration, Quantity},
+};
+
+pub struct Homing {
+    pub config: HomingConfig,
+    pub stall: StallGuard,
+}
+
+pub struct Axis {
+    pub channel: Channel,
+    pub calibration: Calibration,
+    // in the calibrated units
+    pub limits: Limits,
+    pub homing: Option<Homing>,
+}
+
+impl Axis {
+    // The stepper moves into the step generator, the axis keeps a handle
+    pub fn new(stepper: Stepper, calibration: Calibration, limits: Limits) -> Self {
+        Self {
+            channel: Channel::attach(stepper),
+            calibration,
+            limits,
+            homing: None,
+        }
+    }
+
+    // In micrometres or millidegrees, see units.rs
+    pub fn position(&self) -> i32 {
+        self.calibration.to_units(self.channel.position())
+    }
+
+    pub fn quantity(&self) -> Quantity {
+        Quantity(self.position(), self.calibration.unit())
+    }
+
+    pub fn with_homing(mut self, homing: Homing) -> Self {
+        self.homing = Some(homing);
+        self
+    }
+}
+
+pub struct Axes {
+    pub slider: Axis,
+    pub pan: Axis,
+    pub tilt: Axis,
+    #[cfg(feature = "mega2560")]
+    pub aux: Axis,
+}
+
+impl Axes {
+    // The axes keyframes address, in plan::AXIS_NAMES order
+    pub fn sequenced(&self) -> [&Axis; AXES] {
+        [&self.slider, &self.pan, &self.tilt]
+    }
+
+    pub fn limits(&self) -> [Limits; AXES] {
+        self.sequenced().map(|axis| axis.limits)
+    }
+
+    // Visits every axis the board has, stops at the first error
+    pub fn try_for_each<E>(
+        &mut self,
+        mut f: impl FnMut(&'static str, &mut Axis) -> Result<(), E>,
+    ) -> Result<(), E> {
+        f("slider", &mut self.slider)?;
+        f("pan", &mut self.pan)?;
+        f("tilt", &mut self.tilt)?;
+        #[cfg(feature = "mega2560")]
+        f("aux", &mut self.aux)?;
+        Ok(())
+    }
+}
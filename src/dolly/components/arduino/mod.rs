@@ -1,4 +1,4 @@
-use arduino_hal::hal::{port::PD2, Atmega};
+use arduino_hal::hal::Atmega;
 use avr_hal_generic::{
     adc::{Adc, Channel},
     clock::MHz16,
@@ -13,5 +13,3 @@ pub type AdcType = arduino_hal::pac::ADC;
 pub type Clock = MHz16;
 pub type AdcConcreteType = Adc<HType, AdcType, Clock>;
 pub type ChannelType = Channel<HType, AdcType>;
-
-pub type IRPin = PD2;
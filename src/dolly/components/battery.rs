@@ -0,0 +1,23 @@
+use super::arduino::{io::AnalogRead, pins::analog_pin::AnalogInput};
+
+// Battery voltage through a 100k / 33k divider, so a 3S pack stays below the
+// 5V reference of the ADC
+pub struct Battery {
+    pin: AnalogInput,
+}
+
+impl Battery {
+    const VREF_MV: u32 = 5_000;
+    const DIVIDER_NUM: u32 = 133;
+    const DIVIDER_DEN: u32 = 33;
+
+    pub fn new(pin: AnalogInput) -> Self {
+        Self { pin }
+    }
+
+    pub fn millivolts(&self) -> u16 {
+        let raw = self.pin.read() as u32;
+        let mv = raw * Self::VREF_MV * Self::DIVIDER_NUM / (1023 * Self::DIVIDER_DEN);
+        mv as u16
+    }
+}
@@ -10,9 +10,7 @@ use infrared::{
 };
 use ufmt::{uDisplay, uwrite};
 
-use crate::println;
-
-use super::arduino::IRPin;
+use crate::{board::IrPin as IRPin, println, timer::tc0::ClockTC0};
 
 type IrPin = Pin<Input<Floating>, IRPin>;
 type IrProto = Nec;
@@ -68,8 +66,7 @@ impl uDisplay for Command {
 
 impl IRRemote {
     pub fn initialize(pin: IrPin) {
-        const POLL_FREQ: u32 = 20_000;
-        let ir = PeriodicPoll::with_pin(POLL_FREQ, pin);
+        let ir = PeriodicPoll::with_pin(ClockTC0::TARGET_FREQ, pin);
         unsafe { RECEIVER.replace(ir) };
     }
 
@@ -119,9 +116,11 @@ impl IRRemote {
     }
 }
 
-#[avr_device::interrupt(atmega328p)]
-fn TIMER0_COMPA() {
-    let recv = unsafe { RECEIVER.as_mut().unwrap() };
+// Runs at the clock rate from the shared TIMER0_COMPA vector
+pub fn poll() {
+    let Some(recv) = (unsafe { RECEIVER.as_mut() }) else {
+        return;
+    };
 
     if let Ok(Some(cmd)) = recv.poll() {
         // Command received
@@ -8,6 +8,21 @@ pub struct Joystick {
     y_pin: AnalogInput,
     switch_pin: DigitalInput,
     initial_pos: (u16, u16),
+    // the debounced button, and since when the pin has read otherwise
+    pressed: bool,
+    bouncing_since: Option<u32>,
+    pressed_at: u32,
+    // the long press of the current press was already reported
+    held: bool,
+}
+
+// What the button did since it was last read
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Press {
+    // let go before it was held
+    Click,
+    // still down, for the whole hold time
+    Hold,
 }
 
 impl Joystick {
@@ -20,6 +35,10 @@ impl Joystick {
             y_pin,
             switch_pin,
             initial_pos: (x0, y0),
+            pressed: false,
+            bouncing_since: None,
+            pressed_at: 0,
+            held: false,
         }
     }
 
@@ -40,4 +59,34 @@ impl Joystick {
             super::arduino::io::State::LOW => true,
         }
     }
+
+    // Once per press: a hold when it has been down for `hold_ms`, otherwise
+    // a click when it is let go. The pin has to read the same for
+    // `DEBOUNCE_MS` before a change counts.
+    pub fn press(&mut self, now: u32, hold_ms: u32) -> Option<Press> {
+        const DEBOUNCE_MS: u32 = 20;
+
+        if self.is_pressed() == self.pressed {
+            self.bouncing_since = None;
+        } else {
+            let since = *self.bouncing_since.get_or_insert(now);
+            if now.wrapping_sub(since) >= DEBOUNCE_MS {
+                self.bouncing_since = None;
+                self.pressed = !self.pressed;
+
+                if self.pressed {
+                    self.pressed_at = now;
+                    self.held = false;
+                } else if !self.held {
+                    return Some(Press::Click);
+                }
+            }
+        }
+
+        if self.pressed && !self.held && now.wrapping_sub(self.pressed_at) >= hold_ms {
+            self.held = true;
+            return Some(Press::Hold);
+        }
+        None
+    }
 }
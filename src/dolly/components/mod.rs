@@ -1,4 +1,10 @@
 pub mod arduino;
+#[cfg(feature = "telemetry")]
+pub mod battery;
+#[cfg(feature = "ir")]
 pub mod irremote;
+#[cfg(feature = "joystick")]
 pub mod joystick;
+pub mod shutter;
+pub mod stepper;
 pub mod switch;
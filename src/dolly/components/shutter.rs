@@ -0,0 +1,34 @@
+use super::arduino::{
+    io::{DigitalWrite, State},
+    pins::digital_pin::DigitalOutput,
+};
+
+// Camera remote release through an optocoupler, high closes the contact
+pub struct Shutter {
+    pin: DigitalOutput,
+    pressed: bool,
+}
+
+impl Shutter {
+    pub fn new(mut pin: DigitalOutput) -> Self {
+        pin.write(State::LOW);
+        Self {
+            pin,
+            pressed: false,
+        }
+    }
+
+    pub fn press(&mut self) {
+        self.pin.write(State::HIGH);
+        self.pressed = true;
+    }
+
+    pub fn release(&mut self) {
+        self.pin.write(State::LOW);
+        self.pressed = false;
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
@@ -0,0 +1,132 @@
+use ufmt::{uDisplay, uwrite};
+
+use crate::{
+    dolly::{
+        components::arduino::{
+            io::{DigitalRead, State},
+            pins::digital_pin::DigitalInput,
+        },
+        motion::pulse::Channel,
+    },
+    pcint::Flag,
+};
+
+use super::{
+    tmc2209::{Tmc2209, TmcUart},
+    Direction,
+};
+
+#[derive(Clone, Copy)]
+pub struct HomingConfig {
+    pub direction: Direction,
+    // steps per second, StallGuard needs a minimum velocity to be reliable
+    pub speed: u16,
+    // SGTHRS, higher values detect a stall with less load
+    pub stall_threshold: u8,
+    // steps to move away from the end after the stall
+    pub backoff: u16,
+    // give up after this many steps without a stall
+    pub max_travel: u32,
+}
+
+pub enum HomingError {
+    StalledAtStart,
+    NoStall { travelled: u32 },
+    DriverNotResponding,
+}
+
+impl uDisplay for HomingError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: avr_hal_generic::prelude::_ufmt_uWrite + ?Sized,
+    {
+        match self {
+            HomingError::StalledAtStart => uwrite!(f, "StalledAtStart"),
+            HomingError::NoStall { travelled } => {
+                uwrite!(f, "NoStall(travelled {} steps)", travelled)
+            }
+            HomingError::DriverNotResponding => uwrite!(f, "DriverNotResponding"),
+        }
+    }
+}
+
+pub enum StallSource {
+    // DIAG goes high while SG_RESULT is below 2 * SGTHRS, the latch catches
+    // pulses shorter than a step
+    Diag {
+        pin: DigitalInput,
+        latch: &'static Flag,
+    },
+    // poll SG_RESULT over UART, needs a bidirectional link to the driver
+    SgResult,
+}
+
+pub struct StallGuard {
+    driver: Tmc2209,
+    source: StallSource,
+}
+
+impl StallGuard {
+    // StallGuard reads garbage while the motor accelerates from standstill
+    const BLANKING_STEPS: u32 = 64;
+
+    pub fn new(driver: Tmc2209, source: StallSource) -> Self {
+        Self { driver, source }
+    }
+
+    fn is_stalled<U: TmcUart>(&self, uart: &mut U, threshold: u8) -> Result<bool, HomingError> {
+        match &self.source {
+            StallSource::Diag { pin, latch } => {
+                Ok(latch.take() | matches!(pin.read(), State::HIGH))
+            }
+            StallSource::SgResult => match self.driver.sg_result(uart) {
+                Some(sg) => Ok(sg <= 2 * threshold as u16),
+                None => Err(HomingError::DriverNotResponding),
+            },
+        }
+    }
+
+    pub fn home<U: TmcUart>(
+        &self,
+        stepper: Channel,
+        uart: &mut U,
+        cfg: &HomingConfig,
+    ) -> Result<(), HomingError> {
+        let step_delay_us = 1_000_000 / cfg.speed.max(1) as u32;
+
+        self.driver.set_stall_threshold(uart, cfg.stall_threshold);
+        if self.is_stalled(uart, cfg.stall_threshold)? {
+            return Err(HomingError::StalledAtStart);
+        }
+
+        stepper.set_direction(cfg.direction);
+        if let StallSource::Diag { latch, .. } = &self.source {
+            latch.take();
+        }
+
+        let mut travelled: u32 = 0;
+        loop {
+            if travelled >= cfg.max_travel {
+                return Err(HomingError::NoStall { travelled });
+            }
+
+            stepper.step();
+            travelled += 1;
+            arduino_hal::delay_us(step_delay_us);
+
+            if travelled > Self::BLANKING_STEPS && self.is_stalled(uart, cfg.stall_threshold)? {
+                break;
+            }
+        }
+
+        stepper.set_position(0);
+
+        stepper.set_direction(cfg.direction.reverse());
+        for _ in 0..cfg.backoff {
+            stepper.step();
+            arduino_hal::delay_us(step_delay_us);
+        }
+
+        Ok(())
+    }
+}
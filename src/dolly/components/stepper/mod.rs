@@ -1,63 +1,97 @@
-#[allow(dead_code)]
+use super::arduino::{
+    io::{DigitalWrite, State},
+    pins::digital_pin::DigitalOutput,
+};
+
+pub mod homing;
+pub mod tmc2209;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Clockwise,
     CounterClockwise,
 }
 
-pub trait Motor {
-    fn set_direction(dir: Direction);
-    fn run();
-    fn stop();
+impl Direction {
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::Clockwise => Direction::CounterClockwise,
+            Direction::CounterClockwise => Direction::Clockwise,
+        }
+    }
+}
+
+// Shared EN line of the drivers, active low on both A4988 and TMC2209 boards
+pub struct DriverEnable {
+    pin: DigitalOutput,
+}
+
+impl DriverEnable {
+    pub fn new(mut pin: DigitalOutput) -> Self {
+        pin.write(State::HIGH);
+        Self { pin }
+    }
+
+    pub fn enable(&mut self) {
+        self.pin.write(State::LOW);
+    }
+
+    pub fn disable(&mut self) {
+        self.pin.write(State::HIGH);
+    }
 }
 
 pub struct Stepper {
+    step_pin: DigitalOutput,
+    dir_pin: DigitalOutput,
     direction: Direction,
+    position: i32,
 }
 
-// TEMP
+impl Stepper {
+    // TMC2209 needs 100ns, A4988 needs 1us for STEP high and DIR setup
+    const PULSE_US: u32 = 2;
 
-enum PinState {
-    HIGH,
-    LOW,
-}
+    pub fn new(step_pin: DigitalOutput, dir_pin: DigitalOutput) -> Self {
+        let mut stepper = Self {
+            step_pin,
+            dir_pin,
+            direction: Direction::Clockwise,
+            position: 0,
+        };
+        stepper.set_direction(Direction::Clockwise);
+        stepper
+    }
 
-fn set_pins_state(pins: &mut [Pin<Output>], state: PinState) {
-    for p in pins {
-        match state {
-            PinState::HIGH => {
-                p.set_high();
-            }
-            PinState::LOW => {
-                p.set_low();
-            }
+    pub fn set_direction(&mut self, dir: Direction) {
+        match dir {
+            Direction::Clockwise => self.dir_pin.write(State::HIGH),
+            Direction::CounterClockwise => self.dir_pin.write(State::LOW),
         }
+        self.direction = dir;
+        arduino_hal::delay_us(Self::PULSE_US);
     }
-}
 
-// const CYCLE_PULSES: u32 = 200;
-// const PULSE_DELAY: u32 = 500;
-//
-// loop {
-//     set_pins_state(&mut dir_pins, PinState::HIGH);
-//     builtin_led.set_high();
-//
-//     for _ in 0..CYCLE_PULSES {
-//         set_pins_state(&mut step_pins, PinState::HIGH);
-//         arduino_hal::delay_us(PULSE_DELAY);
-//         set_pins_state(&mut step_pins, PinState::LOW);
-//         arduino_hal::delay_us(PULSE_DELAY);
-//     }
-//
-//     arduino_hal::delay_ms(1000);
-//
-//     set_pins_state(&mut dir_pins, PinState::LOW);
-//     builtin_led.set_low();
-//     for _ in 0..2 * CYCLE_PULSES {
-//         set_pins_state(&mut step_pins, PinState::HIGH);
-//         arduino_hal::delay_us(PULSE_DELAY);
-//         set_pins_state(&mut step_pins, PinState::LOW);
-//         arduino_hal::delay_us(PULSE_DELAY);
-//     }
-//
-//     arduino_hal::delay_ms(1000);
-// }
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn step(&mut self) {
+        self.step_pin.write(State::HIGH);
+        arduino_hal::delay_us(Self::PULSE_US);
+        self.step_pin.write(State::LOW);
+
+        match self.direction {
+            Direction::Clockwise => self.position += 1,
+            Direction::CounterClockwise => self.position -= 1,
+        }
+    }
+
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: i32) {
+        self.position = position;
+    }
+}
@@ -0,0 +1,184 @@
+/*
+* REFERENCE
+* - TMC2209 datasheet, section 4.1 (UART Single Wire Interface)
+* - TMC2209 datasheet, section 11 (StallGuard4 Load Measurement)
+*/
+
+use arduino_hal::prelude::*;
+use avr_hal_generic::usart::{Usart, UsartOps};
+
+use crate::dolly::components::arduino::{
+    io::{DigitalWrite, State},
+    pins::digital_pin::DigitalOutput,
+};
+
+pub trait TmcUart {
+    fn write_byte(&mut self, byte: u8);
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
+pub mod reg {
+    pub const TCOOLTHRS: u8 = 0x14;
+    pub const SGTHRS: u8 = 0x40;
+    pub const SG_RESULT: u8 = 0x41;
+}
+
+pub struct Tmc2209 {
+    address: u8,
+}
+
+impl Tmc2209 {
+    const SYNC: u8 = 0x05;
+    const WRITE: u8 = 0x80;
+    const MASTER_ADDRESS: u8 = 0xFF;
+
+    // StallGuard is only evaluated below this TSTEP, so use the widest window
+    const TCOOLTHRS_ALWAYS: u32 = 0xF_FFFF;
+
+    // address is set with the MS1/MS2 pins, 0 to 3
+    pub const fn new(address: u8) -> Self {
+        Self { address }
+    }
+
+    fn crc(datagram: &[u8]) -> u8 {
+        let mut crc: u8 = 0;
+        for byte in datagram {
+            let mut current = *byte;
+            for _ in 0..8 {
+                if (crc >> 7) ^ (current & 0x01) != 0 {
+                    crc = (crc << 1) ^ 0x07;
+                } else {
+                    crc <<= 1;
+                }
+                current >>= 1;
+            }
+        }
+        crc
+    }
+
+    pub fn write_register<U: TmcUart>(&self, uart: &mut U, reg: u8, value: u32) {
+        let mut datagram = [
+            Self::SYNC,
+            self.address,
+            reg | Self::WRITE,
+            (value >> 24) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+            0,
+        ];
+        datagram[7] = Self::crc(&datagram[..7]);
+
+        for byte in datagram {
+            uart.write_byte(byte);
+        }
+
+        // single wire bus, so the request is echoed back to us
+        for _ in 0..datagram.len() {
+            uart.read_byte();
+        }
+    }
+
+    pub fn read_register<U: TmcUart>(&self, uart: &mut U, reg: u8) -> Option<u32> {
+        let mut request = [Self::SYNC, self.address, reg, 0];
+        request[3] = Self::crc(&request[..3]);
+
+        for byte in request {
+            uart.write_byte(byte);
+        }
+        for _ in 0..request.len() {
+            uart.read_byte()?;
+        }
+
+        let mut reply = [0u8; 8];
+        for byte in reply.iter_mut() {
+            *byte = uart.read_byte()?;
+        }
+
+        if reply[0] != Self::SYNC
+            || reply[1] != Self::MASTER_ADDRESS
+            || reply[2] != reg
+            || reply[7] != Self::crc(&reply[..7])
+        {
+            return None;
+        }
+
+        Some(u32::from_be_bytes([reply[3], reply[4], reply[5], reply[6]]))
+    }
+
+    // DIAG is raised when SG_RESULT falls below 2 * threshold
+    pub fn set_stall_threshold<U: TmcUart>(&self, uart: &mut U, threshold: u8) {
+        self.write_register(uart, reg::TCOOLTHRS, Self::TCOOLTHRS_ALWAYS);
+        self.write_register(uart, reg::SGTHRS, threshold as u32);
+    }
+
+    pub fn sg_result<U: TmcUart>(&self, uart: &mut U) -> Option<u16> {
+        self.read_register(uart, reg::SG_RESULT)
+            .map(|v| (v & 0x3FF) as u16)
+    }
+}
+
+// Write-only bit-banged UART for boards without a spare USART. It is enough to
+// configure the drivers, but registers cannot be read back through it.
+pub struct SoftTx {
+    pin: DigitalOutput,
+}
+
+impl SoftTx {
+    const BAUD: u32 = 19_200;
+    const BIT_US: u32 = 1_000_000 / Self::BAUD;
+
+    pub fn new(mut pin: DigitalOutput) -> Self {
+        // idle line is high
+        pin.write(State::HIGH);
+        Self { pin }
+    }
+}
+
+impl TmcUart for SoftTx {
+    fn write_byte(&mut self, byte: u8) {
+        avr_device::interrupt::free(|_| {
+            self.pin.write(State::LOW);
+            arduino_hal::delay_us(Self::BIT_US);
+
+            for bit in 0..8 {
+                match (byte >> bit) & 0x01 {
+                    0 => self.pin.write(State::LOW),
+                    _ => self.pin.write(State::HIGH),
+                }
+                arduino_hal::delay_us(Self::BIT_US);
+            }
+
+            self.pin.write(State::HIGH);
+            arduino_hal::delay_us(Self::BIT_US);
+        });
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+impl<H, USART, RX, TX, CLOCK> TmcUart for Usart<H, USART, RX, TX, CLOCK>
+where
+    USART: UsartOps<H, RX, TX>,
+{
+    fn write_byte(&mut self, byte: u8) {
+        Usart::write_byte(self, byte);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        // a full 8 byte reply takes ~4ms at the driver's slowest baud rate
+        const TIMEOUT_US: u32 = 5_000;
+        const POLL_US: u32 = 10;
+
+        for _ in 0..(TIMEOUT_US / POLL_US) {
+            if let Ok(byte) = self.read() {
+                return Some(byte);
+            }
+            arduino_hal::delay_us(POLL_US);
+        }
+
+        None
+    }
+}
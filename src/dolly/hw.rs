@@ -0,0 +1,170 @@
+#[cfg(feature = "stop-motion")]
+use dolly_protocol::stopmotion::RECORD_LEN;
+use dolly_protocol::{
+    machine::{Hardware, Note, Sink},
+    plan::AXES,
+    tracking::Target,
+    Limits,
+};
+
+use crate::{error, info, log, print, println, timer};
+
+use super::{
+    components::{
+        arduino::io::{DigitalWrite, State},
+        stepper::homing::HomingError,
+    },
+    units::{Quantity, Unit},
+    Settings,
+};
+
+// where the stop motion record starts in EEPROM
+#[cfg(feature = "stop-motion")]
+const RECORD_OFFSET: u16 = 0;
+
+// Notes go out like any other log line, through the same runtime level
+struct Console;
+
+impl Sink for Console {
+    fn text(&mut self, text: &str) {
+        print!("{}", text);
+    }
+
+    fn number(&mut self, n: u32) {
+        print!("{}", n);
+    }
+
+    fn quantity(&mut self, value: i32, unit: Unit) {
+        print!("{}", Quantity(value, unit));
+    }
+}
+
+impl Hardware for Settings {
+    type HomingError = HomingError;
+
+    fn positions(&self) -> [i32; AXES] {
+        self.axes.sequenced().map(|axis| axis.position())
+    }
+
+    fn limits(&self) -> [Limits; AXES] {
+        self.axes.limits()
+    }
+
+    fn slider_step_um(&self) -> i32 {
+        self.axes.slider.calibration.to_units(1)
+    }
+
+    fn start(&mut self, to: &[i32; AXES], duration_ms: u32, started: u32, track: Option<Target>) {
+        self.drivers.enable();
+        self.motion
+            .start(&self.axes, to, duration_ms, started, track);
+    }
+
+    fn update(&mut self, now: u32) -> bool {
+        self.motion.update(&self.axes, now)
+    }
+
+    fn pause(&mut self, now: u32) {
+        self.motion.pause(&self.axes, now);
+    }
+
+    fn resume(&mut self, now: u32) {
+        self.motion.resume(&self.axes, now);
+    }
+
+    fn abort(&mut self, now: u32) {
+        self.motion.abort(&self.axes, now);
+    }
+
+    fn halt(&mut self) {
+        self.motion.halt(&self.axes);
+    }
+
+    fn is_running(&self) -> bool {
+        self.motion.is_running()
+    }
+
+    fn is_jogging(&self) -> bool {
+        self.motion.is_jogging()
+    }
+
+    fn is_easing(&self, now: u32) -> bool {
+        self.motion.is_easing(now)
+    }
+
+    fn remaining_ms(&self, now: u32) -> u32 {
+        self.motion.remaining_ms(now)
+    }
+
+    fn shutter(&mut self, pressed: bool) {
+        match pressed {
+            true => self.shutter.press(),
+            false => self.shutter.release(),
+        }
+    }
+
+    fn leds(&mut self, in_on: bool, out_on: bool) {
+        let state = |on| if on { State::HIGH } else { State::LOW };
+        self.in_led.write(state(in_on));
+        self.out_led.write(state(out_on));
+    }
+
+    #[cfg(feature = "stop-motion")]
+    fn load(&self) -> [u8; RECORD_LEN] {
+        let mut stored = [0; RECORD_LEN];
+        for (offset, byte) in (RECORD_OFFSET..).zip(stored.iter_mut()) {
+            *byte = self.eeprom.read_byte(offset);
+        }
+        stored
+    }
+
+    // A cell wears out after about 100k writes, only the changed ones are
+    // written, which is the frame and the CRC on every step
+    #[cfg(feature = "stop-motion")]
+    fn store(&mut self, record: &[u8; RECORD_LEN]) {
+        for (offset, &byte) in (RECORD_OFFSET..).zip(record) {
+            if self.eeprom.read_byte(offset) != byte {
+                self.eeprom.write_byte(offset, byte);
+            }
+        }
+    }
+
+    // Blocks the loop for far longer than the watchdog timeout, which is
+    // only running once the dolly is past its boot
+    fn home(&mut self) -> Result<(), HomingError> {
+        let watchdog = self.watchdog.is_running();
+        self.watchdog.stop();
+
+        let uart = &mut self.tmc_uart;
+        let drivers = &mut self.drivers;
+        drivers.enable();
+        let homed = self.axes.try_for_each(|name, axis| {
+            let Some(homing) = &axis.homing else {
+                return Ok(());
+            };
+
+            if let Err(e) = homing.stall.home(axis.channel, uart, &homing.config) {
+                // never leave a motor pushing against the end of the rail
+                drivers.disable();
+                error!("Homing {} failed: {}", name, e);
+                return Err(e);
+            }
+            info!("Homed {}", name);
+            Ok(())
+        });
+
+        if watchdog {
+            self.watchdog.start();
+        }
+        homed
+    }
+
+    fn note(&mut self, note: Note) {
+        let level = note.level();
+        if level <= log::level() {
+            print!("[{} {}] ", timer::millis(), level.tag());
+            note.write(&mut Console);
+            println!("");
+        }
+    }
+}
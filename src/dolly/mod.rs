@@ -1,60 +1,205 @@
-use core::ops::Range;
+#[cfg(feature = "serial-shell")]
+use dolly_protocol::shell::Command;
+#[cfg(feature = "timelapse")]
+use dolly_protocol::Mode;
+#[cfg(feature = "stop-motion")]
+use dolly_protocol::Step;
+#[cfg(feature = "telemetry")]
+use dolly_protocol::Telemetry;
+#[cfg(feature = "joystick")]
+use dolly_protocol::{machine::Hardware, plan::AXES};
+use dolly_protocol::{machine::Machine, ErrorCode, Message, Packet, SettingId};
 
+#[cfg(feature = "ir")]
+use crate::debug;
+#[cfg(feature = "joystick")]
+use crate::trace;
 use crate::{
-    println,
-    timer::{tc0::ClockTC0, tc1::ClockTC1},
+    board::TmcLink,
+    crash, info,
+    link::{Incoming, Link},
+    log::{self, Level},
+    scheduler::Scheduler,
+    serial::{self, FullPolicy},
+    timer, warn,
+    watchdog::{ResetCause, Watchdog},
 };
 
-use self::components::{
-    arduino::{
-        io::{DigitalWrite, State},
-        pins::digital_pin::DigitalOutput,
+#[cfg(feature = "ir")]
+use self::components::irremote::Command as IrCommand;
+#[cfg(all(feature = "ir", feature = "stop-motion"))]
+use self::components::irremote::Dir;
+#[cfg(feature = "ir")]
+use self::components::irremote::IRRemote;
+#[cfg(feature = "joystick")]
+use self::components::joystick::{Joystick, Press};
+use self::{
+    axis::Axes,
+    components::{
+        arduino::{io::DigitalWrite, pins::digital_pin::DigitalOutput},
+        shutter::Shutter,
+        stepper::{homing::HomingError, DriverEnable},
     },
-    irremote::IRRemote,
-    joystick::Joystick,
+    motion::Executor,
+};
+#[cfg(feature = "telemetry")]
+use self::{
+    components::battery::Battery,
+    telemetry::{Format, Stream},
 };
 
+pub mod axis;
 pub mod components;
+mod hw;
+pub mod motion;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+pub mod units;
 
 pub struct Settings {
-    pub tc0_clock: ClockTC0,
-    pub tc1_clock: ClockTC1,
+    pub motion: Executor,
+    #[cfg(feature = "ir")]
     pub irremote: IRRemote,
+    #[cfg(feature = "joystick")]
     pub joystick: Joystick,
     pub builtin_led: DigitalOutput,
     pub in_led: DigitalOutput,
     pub out_led: DigitalOutput,
+    pub drivers: DriverEnable,
+    pub axes: Axes,
+    pub tmc_uart: TmcLink,
+    #[cfg(feature = "telemetry")]
+    pub battery: Battery,
+    pub shutter: Shutter,
+    pub watchdog: Watchdog,
+    #[cfg(feature = "stop-motion")]
+    pub eeprom: arduino_hal::Eeprom,
 }
 
-struct Position {
-    slider: i32,
-    pan: i32,
-    tilt: i32,
+// The axes the joystick moves, its x and y as indices into the sequenced axes
+#[cfg(feature = "joystick")]
+#[derive(Clone, Copy)]
+enum JogPair {
+    SliderPan,
+    PanTilt,
 }
 
-enum DollyState {
-    SetInitPos,
-    SetEndPos(Position),
-    GotoInit {
-        range: Range<Position>,
-        current: Position,
-    },
-    Ready(Range<Position>),
-    Moving {
-        range: Range<Position>,
-        current: Position,
-    },
+#[cfg(feature = "joystick")]
+impl JogPair {
+    fn axes(self) -> (usize, usize) {
+        match self {
+            JogPair::SliderPan => (0, 1),
+            JogPair::PanTilt => (1, 2),
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            JogPair::SliderPan => JogPair::PanTilt,
+            JogPair::PanTilt => JogPair::SliderPan,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JogPair::SliderPan => "slider/pan",
+            JogPair::PanTilt => "pan/tilt",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Task {
+    Heartbeat,
+    #[cfg(feature = "joystick")]
+    Joystick,
+    #[cfg(feature = "ir")]
+    IrRemote,
+    #[cfg(feature = "telemetry")]
+    Telemetry,
+    Watchdog,
+    Motion,
+}
+
+impl Task {
+    const CRITICAL: &'static [Task] = &[
+        #[cfg(feature = "joystick")]
+        Task::Joystick,
+        #[cfg(feature = "ir")]
+        Task::IrRemote,
+    ];
+
+    // tasks that have to keep running for the watchdog to be fed, with none
+    // of them compiled in the watchdog task alone proves the loop is alive
+    fn checkpoint(self) -> Option<u8> {
+        match self {
+            #[cfg(feature = "joystick")]
+            Task::Joystick => Some(0),
+            #[cfg(feature = "ir")]
+            Task::IrRemote => Some(1),
+            _ => None,
+        }
+    }
 }
 
 pub struct Dolly {
     cfg: Settings,
+    scheduler: Scheduler<Task, 8>,
+    link: Link,
+    // runs, pauses and homing, shared with the simulator, see hw.rs
+    machine: Machine,
+    #[cfg(feature = "joystick")]
+    jog_pair: JogPair,
+    #[cfg(feature = "telemetry")]
+    telemetry: Stream,
 }
 
 impl Dolly {
-    pub fn new(cfg: Settings) -> Self {
-        Self { cfg }
+    const HEARTBEAT_PERIOD_MS: u32 = 50;
+    #[cfg(feature = "joystick")]
+    const JOYSTICK_PERIOD_MS: u32 = 10; // 100 Hz
+    #[cfg(feature = "joystick")]
+    const ABORT_HOLD_MS: u32 = 1000; // button held down
+    #[cfg(feature = "ir")]
+    const IRREMOTE_PERIOD_MS: u32 = 20;
+    // well below the watchdog timeout, well above the critical task periods
+    const WATCHDOG_PERIOD_MS: u32 = 100;
+
+    pub fn new(mut cfg: Settings, reset_cause: ResetCause) -> Self {
+        let now = timer::millis();
+        let mut scheduler = Scheduler::new();
+        scheduler.every(Task::Heartbeat, Self::HEARTBEAT_PERIOD_MS, now);
+        #[cfg(feature = "joystick")]
+        scheduler.every(Task::Joystick, Self::JOYSTICK_PERIOD_MS, now);
+        #[cfg(feature = "ir")]
+        scheduler.every(Task::IrRemote, Self::IRREMOTE_PERIOD_MS, now);
+        scheduler.every(Task::Watchdog, Self::WATCHDOG_PERIOD_MS, now);
+        scheduler.every(Task::Motion, Executor::UPDATE_MS, now);
+
+        for checkpoint in Task::CRITICAL.iter().filter_map(|t| t.checkpoint()) {
+            cfg.watchdog.require(checkpoint);
+        }
+
+        // the bootloader may have cleared MCUSR, the note left by the ISR survives it
+        let watchdog_reset = reset_cause == ResetCause::Watchdog
+            || crash::last().is_some_and(|r| r.code == crash::Code::Watchdog as u8);
+        if watchdog_reset {
+            warn!("Watchdog reset, motion blocked until `ack`");
+        }
+
+        Self {
+            cfg,
+            scheduler,
+            link: Link::new(),
+            machine: Machine::new(watchdog_reset),
+            #[cfg(feature = "joystick")]
+            jog_pair: JogPair::SliderPan,
+            #[cfg(feature = "telemetry")]
+            telemetry: Stream::new(),
+        }
     }
 
+    #[cfg(feature = "joystick")]
     fn map(value: i32, from_range: (i32, i32), to_range: (i32, i32)) -> i32 {
         let (out_min, out_max) = to_range;
         let (in_min, in_max) = from_range;
@@ -69,28 +214,396 @@ impl Dolly {
         v as i32
     }
 
+    // Put off until `ack` after a watchdog reset
+    pub fn home(&mut self) -> Result<(), HomingError> {
+        self.machine.home(&mut self.cfg)
+    }
+
+    // Stopped at boot until setup is done, see main
+    pub fn arm_watchdog(&mut self) {
+        self.cfg.watchdog.start();
+    }
+
     pub fn run(&mut self) {
-        self.cfg.builtin_led.toggle();
+        #[cfg(feature = "telemetry")]
+        self.telemetry.count_loop();
+
+        match self.link.poll() {
+            #[cfg(feature = "serial-shell")]
+            Some(Incoming::Command(cmd)) => self.handle_command(cmd),
+            #[cfg(feature = "serial-shell")]
+            Some(Incoming::Rejected(e)) => warn!("Line rejected: {}", e),
+            Some(Incoming::Packet(packet)) => self.handle_packet(packet),
+            Some(Incoming::Error(e)) => warn!("Frame dropped: {}", e.as_str()),
+            None => {}
+        }
+
+        let Some((id, task)) = self.scheduler.next(timer::millis()) else {
+            return;
+        };
+
+        match task {
+            Task::Heartbeat => self.cfg.builtin_led.toggle(),
+            #[cfg(feature = "joystick")]
+            Task::Joystick => self.read_joystick(),
+            #[cfg(feature = "ir")]
+            Task::IrRemote => self.read_irremote(),
+            #[cfg(feature = "telemetry")]
+            Task::Telemetry => self.send_telemetry(),
+            Task::Watchdog => {
+                self.cfg.watchdog.service();
+            }
+            Task::Motion => self.machine.update(&mut self.cfg, timer::millis()),
+        }
+
+        if let Some(checkpoint) = task.checkpoint() {
+            self.cfg.watchdog.check_in(checkpoint);
+        }
+
+        self.scheduler.done(id, timer::millis());
+    }
+
+    #[cfg(feature = "serial-shell")]
+    fn handle_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::ShowLogLevel => info!("Log level: {}", log::level().tag()),
+            Command::SetLogLevel(level) => {
+                log::set_level(level);
+                info!("Log level: {}", level.tag());
+            }
+            Command::ShowCrash => match crash::last() {
+                Some(report) => info!("Last crash: {}", report),
+                None => info!("No crash recorded"),
+            },
+            Command::ClearCrash => {
+                crash::clear();
+                info!("Crash report cleared");
+            }
+            Command::Acknowledge => self.acknowledge(),
+            Command::ShowPosition => {
+                let axes = &self.cfg.axes;
+                info!(
+                    "Position: slider {} pan {} tilt {}",
+                    axes.slider.quantity(),
+                    axes.pan.quantity(),
+                    axes.tilt.quantity()
+                );
+                #[cfg(feature = "mega2560")]
+                info!("Position: aux {}", axes.aux.quantity());
+            }
+            #[cfg(feature = "telemetry")]
+            Command::ShowTelemetry => self.show_telemetry(),
+            #[cfg(feature = "telemetry")]
+            Command::SetTelemetry { period_ms, format } => {
+                if let Some(format) = format {
+                    self.telemetry.set_format(format);
+                }
+                self.stream_telemetry(period_ms);
+                self.show_telemetry();
+            }
+            // runs, playback and homing, or left out of this build
+            cmd => {
+                if !self.machine.command(&mut self.cfg, &cmd, timer::millis()) {
+                    warn!("Unknown command");
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: Packet) {
+        let reply = match packet.message {
+            Message::Ping => Message::Ack,
+            Message::AcknowledgeReset => {
+                self.acknowledge();
+                Message::Ack
+            }
+            Message::ReadSetting { id } => Message::Setting {
+                id,
+                value: self.read_setting(id),
+            },
+            Message::WriteSetting { id, value } => match self.write_setting(id, value) {
+                Ok(()) => Message::Ack,
+                Err(e) => Message::Nack(e),
+            },
+            #[cfg(feature = "telemetry")]
+            Message::StreamTelemetry { period_ms } => {
+                self.telemetry.set_format(Format::Binary);
+                self.stream_telemetry(period_ms);
+                Message::Ack
+            }
+            #[cfg(not(feature = "telemetry"))]
+            Message::StreamTelemetry { .. } => Message::Nack(ErrorCode::Unsupported),
+            message => self
+                .machine
+                .handle(&mut self.cfg, &message, timer::millis())
+                .unwrap_or(Message::Nack(ErrorCode::UnexpectedMessage)),
+        };
+
+        self.link.reply(&packet, reply);
+    }
+
+    fn read_setting(&self, id: SettingId) -> i32 {
+        match id {
+            SettingId::LogLevel => log::level() as i32,
+            SettingId::TxFullPolicy => serial::full_policy() as i32,
+            #[cfg(feature = "telemetry")]
+            SettingId::TelemetryPeriod => self.telemetry.period_ms() as i32,
+            #[cfg(feature = "telemetry")]
+            SettingId::TelemetryFormat => self.telemetry.format() as i32,
+            #[cfg(not(feature = "telemetry"))]
+            SettingId::TelemetryPeriod | SettingId::TelemetryFormat => 0,
+            #[cfg(feature = "timelapse")]
+            SettingId::PlaybackMode => self.machine.playback.mode as i32,
+            #[cfg(feature = "timelapse")]
+            SettingId::PlaybackRepeat => self.machine.playback.repeat as i32,
+            #[cfg(feature = "timelapse")]
+            SettingId::DwellStart => self.machine.playback.dwell_start_ms as i32,
+            #[cfg(feature = "timelapse")]
+            SettingId::DwellEnd => self.machine.playback.dwell_end_ms as i32,
+            #[cfg(not(feature = "timelapse"))]
+            SettingId::PlaybackMode
+            | SettingId::PlaybackRepeat
+            | SettingId::DwellStart
+            | SettingId::DwellEnd => 0,
+        }
+    }
+
+    fn write_setting(&mut self, id: SettingId, value: i32) -> Result<(), ErrorCode> {
+        match id {
+            SettingId::LogLevel => {
+                let level = u8::try_from(value)
+                    .ok()
+                    .and_then(Level::from_u8)
+                    .ok_or(ErrorCode::InvalidValue)?;
+                log::set_level(level);
+            }
+            SettingId::TxFullPolicy => match value {
+                0 => serial::set_full_policy(FullPolicy::Drop),
+                1 => serial::set_full_policy(FullPolicy::Block),
+                _ => return Err(ErrorCode::InvalidValue),
+            },
+            #[cfg(feature = "telemetry")]
+            SettingId::TelemetryPeriod => {
+                let period_ms = u16::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+                self.stream_telemetry(period_ms);
+                if period_ms > 0 && self.telemetry.period_ms() == 0 {
+                    // no free scheduler slot
+                    return Err(ErrorCode::NotReady);
+                }
+            }
+            #[cfg(feature = "telemetry")]
+            SettingId::TelemetryFormat => {
+                let format = u8::try_from(value)
+                    .ok()
+                    .and_then(Format::from_u8)
+                    .ok_or(ErrorCode::InvalidValue)?;
+                self.telemetry.set_format(format);
+            }
+            #[cfg(not(feature = "telemetry"))]
+            SettingId::TelemetryPeriod | SettingId::TelemetryFormat => {
+                return Err(ErrorCode::Unsupported)
+            }
+            // take effect when the running pass ends
+            #[cfg(feature = "timelapse")]
+            SettingId::PlaybackMode => {
+                self.machine.playback.mode = u8::try_from(value)
+                    .ok()
+                    .and_then(Mode::from_u8)
+                    .ok_or(ErrorCode::InvalidValue)?;
+            }
+            #[cfg(feature = "timelapse")]
+            SettingId::PlaybackRepeat => {
+                self.machine.playback.repeat =
+                    u16::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+            }
+            #[cfg(feature = "timelapse")]
+            SettingId::DwellStart => {
+                self.machine.playback.dwell_start_ms =
+                    u32::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+            }
+            #[cfg(feature = "timelapse")]
+            SettingId::DwellEnd => {
+                self.machine.playback.dwell_end_ms =
+                    u32::try_from(value).map_err(|_| ErrorCode::InvalidValue)?;
+            }
+            #[cfg(not(feature = "timelapse"))]
+            SettingId::PlaybackMode
+            | SettingId::PlaybackRepeat
+            | SettingId::DwellStart
+            | SettingId::DwellEnd => return Err(ErrorCode::Unsupported),
+        }
 
+        Ok(())
+    }
+
+    fn acknowledge(&mut self) {
+        let acknowledged = self.machine.acknowledge(&mut self.cfg);
+        if acknowledged && crash::last().is_some_and(|r| r.code == crash::Code::Watchdog as u8) {
+            crash::clear();
+        }
+    }
+
+    #[cfg(feature = "telemetry")]
+    fn stream_telemetry(&mut self, period_ms: u16) {
+        self.telemetry.set_period(
+            &mut self.scheduler,
+            Task::Telemetry,
+            period_ms,
+            timer::millis(),
+        );
+    }
+
+    #[cfg(feature = "telemetry")]
+    fn show_telemetry(&self) {
+        let format = self.telemetry.format().as_str();
+        info!("Telemetry: {}ms {}", self.telemetry.period_ms(), format);
+    }
+
+    #[cfg(feature = "telemetry")]
+    fn send_telemetry(&mut self) {
+        let now = timer::millis();
+        let axes = &self.cfg.axes;
+        let sample = Telemetry {
+            uptime_ms: now,
+            state: self.machine.state(),
+            position: [
+                axes.slider.position(),
+                axes.pan.position(),
+                axes.tilt.position(),
+            ],
+            // derived by the stream from the previous sample
+            velocity: [0; 3],
+            frame: self.machine.frame(),
+            remaining_s: self.machine.remaining_ms(&self.cfg, now) / 1000,
+            battery_mv: self.cfg.battery.millivolts(),
+            loop_hz: 0,
+            overruns: self.scheduler.overruns().min(u16::MAX as u32) as u16,
+            max_lateness_ms: self.scheduler.max_lateness(),
+        };
+        self.telemetry.emit(&mut self.link, sample);
+    }
+
+    // Per mille of full deflection, nothing within the dead band around the
+    // centre so a stick at rest doesn't creep, and no jump past its edge
+    #[cfg(feature = "joystick")]
+    fn deflection(raw: i16) -> i32 {
+        const DEAD: i32 = 60;
+        let v = Self::map(raw as i32, (-500, 500), (-1000, 1000));
+        match v.abs() <= DEAD {
+            true => 0,
+            false => v.signum() * Self::map(v.abs(), (DEAD, 1000), (0, 1000)),
+        }
+    }
+
+    #[cfg(feature = "joystick")]
+    fn read_joystick(&mut self) {
         let pos = self.cfg.joystick.get_pos();
-        let x = Self::map(pos.0 as i32, (-500, 500), (-200, 200));
-        let y = Self::map(pos.1 as i32, (-500, 500), (-200, 200));
-        // println!("Joystick: ({}, {})", x, y);
+        let x = Self::deflection(pos.0);
+        let y = Self::deflection(pos.1);
+        trace!("Joystick: ({}, {})", x, y);
+
+        // held down, the button aborts whatever is playing, so only a press
+        // let go sooner is a click
+        let press = self
+            .cfg
+            .joystick
+            .press(timer::millis(), Self::ABORT_HOLD_MS);
+        if press == Some(Press::Hold) && self.machine.is_playing() {
+            let _ = self.machine.abort(&mut self.cfg, timer::millis());
+            return;
+        }
+        let clicked = press == Some(Press::Click);
 
-        match x > 0 {
-            true => self.cfg.in_led.write(State::HIGH),
-            false => self.cfg.in_led.write(State::LOW),
+        // a click takes the next frame of an animation, or pauses whatever
+        // else is playing and picks it up again
+        #[cfg(feature = "stop-motion")]
+        if clicked && self.machine.is_animating() {
+            let _ = self
+                .machine
+                .step_frame(&mut self.cfg, Step::Shoot, timer::millis());
+        }
+        #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+        if clicked && self.machine.is_playing() && !self.machine.is_animating() {
+            let _ = self.machine.toggle_pause(&mut self.cfg, timer::millis());
         }
 
-        match y > 0 {
-            true => self.cfg.out_led.write(State::HIGH),
-            false => self.cfg.out_led.write(State::LOW),
+        // the LEDs belong to whatever is playing, the pre-roll counts down on them
+        if !self.machine.can_jog() {
+            return;
         }
 
-        if let Some(cmd) = self.cfg.irremote.get_cmd() {
-            println!("Cmd: {}", cmd);
+        if clicked {
+            self.jog_pair = self.jog_pair.other();
+            info!("Joystick moves {}", self.jog_pair.as_str());
         }
+        self.cfg.leds(
+            matches!(self.jog_pair, JogPair::SliderPan),
+            matches!(self.jog_pair, JogPair::PanTilt),
+        );
+
+        // deflection scales the top speed, the executor ramps towards it
+        let (across, along) = self.jog_pair.axes();
+        let sequenced = self.cfg.axes.sequenced();
+        let mut velocity = [0; AXES];
+        velocity[across] = x * sequenced[across].limits.max_velocity as i32 / 1000;
+        velocity[along] = y * sequenced[along].limits.max_velocity as i32 / 1000;
 
-        arduino_hal::delay_ms(50);
+        if velocity != [0; AXES] {
+            self.cfg.drivers.enable();
+        }
+        self.cfg
+            .motion
+            .jog(&self.cfg.axes, velocity, timer::millis());
+    }
+
+    #[cfg(feature = "ir")]
+    fn read_irremote(&mut self) {
+        let Some(cmd) = self.cfg.irremote.get_cmd() else {
+            return;
+        };
+        debug!("Cmd: {}", cmd);
+
+        // # aborts whatever is playing
+        if matches!(cmd, IrCommand::Numeral) && self.machine.is_playing() {
+            let _ = self.machine.abort(&mut self.cfg, timer::millis());
+            return;
+        }
+
+        // OK takes the next frame of an animation, the arrows step without shooting
+        #[cfg(feature = "stop-motion")]
+        if self.machine.is_animating() {
+            let step = match cmd {
+                IrCommand::Ok => Some(Step::Shoot),
+                IrCommand::Direction(Dir::Left) => Some(Step::Back),
+                IrCommand::Direction(Dir::Right) => Some(Step::Forward),
+                _ => None,
+            };
+            if let Some(step) = step {
+                let _ = self
+                    .machine
+                    .step_frame(&mut self.cfg, step, timer::millis());
+            }
+            return;
+        }
+
+        // OK pauses whatever is playing and picks it up again
+        #[cfg(any(feature = "timelapse", feature = "panorama", feature = "focus-stack"))]
+        if matches!(cmd, IrCommand::Ok) {
+            let _ = self.machine.toggle_pause(&mut self.cfg, timer::millis());
+        }
+
+        // * steps through the modes, a digit sets the repeats, 0 forever
+        #[cfg(feature = "timelapse")]
+        match cmd {
+            IrCommand::Asterisc => {
+                self.machine.playback.mode = self.machine.playback.mode.next();
+                self.machine.show_playback(&mut self.cfg);
+            }
+            IrCommand::Number(n) => {
+                self.machine.playback.repeat = n as u16;
+                self.machine.show_playback(&mut self.cfg);
+            }
+            _ => {}
+        }
     }
 }
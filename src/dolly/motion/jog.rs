@@ -0,0 +1,79 @@
+use dolly_protocol::{plan::AXES, Limits};
+
+// Velocity control for manual moves. Every update the velocity of each axis
+// closes in on the wanted one by at most its acceleration limit, so letting
+// go of the stick ramps down instead of stopping dead. Towards a soft limit
+// the wanted velocity is capped to what can still stop in the room left, the
+// axis comes to rest on the limit rather than running into it.
+pub struct Jog {
+    // units per second
+    wanted: [f32; AXES],
+    velocity: [f32; AXES],
+    // where the axes are sent by the next update
+    position: [f32; AXES],
+    last: u32,
+}
+
+impl Jog {
+    pub fn new(from: [i32; AXES], now: u32) -> Self {
+        Self {
+            wanted: [0.0; AXES],
+            velocity: [0.0; AXES],
+            position: from.map(|p| p as f32),
+            last: now,
+        }
+    }
+
+    pub fn set(&mut self, wanted: [i32; AXES]) {
+        self.wanted = wanted.map(|v| v as f32);
+    }
+
+    // Nothing wanted and everything has ramped down
+    pub fn is_stopped(&self) -> bool {
+        self.wanted
+            .iter()
+            .chain(self.velocity.iter())
+            .all(|&v| v == 0.0)
+    }
+
+    pub fn advance(&mut self, limits: &[Limits; AXES], now: u32) -> [i32; AXES] {
+        let dt = now.wrapping_sub(self.last) as f32 / 1000.0;
+        self.last = now;
+
+        for (i, l) in limits.iter().enumerate() {
+            let (min, max) = (l.min as f32, l.max as f32);
+            let accel = l.max_acceleration.max(1) as f32;
+            let top = l.max_velocity as f32;
+            let position = self.position[i];
+
+            let wanted = libm::fminf(libm::fmaxf(self.wanted[i], -top), top);
+            let room = match wanted > 0.0 {
+                true => max - position,
+                false => position - min,
+            };
+            let stoppable = libm::sqrtf(2.0 * accel * libm::fmaxf(room, 0.0));
+            let wanted = match wanted > 0.0 {
+                true => libm::fminf(wanted, stoppable),
+                false => libm::fmaxf(wanted, -stoppable),
+            };
+
+            let step = accel * dt;
+            let dv = libm::fminf(libm::fmaxf(wanted - self.velocity[i], -step), step);
+            let mut velocity = self.velocity[i] + dv;
+            let mut next = position + velocity * dt;
+            // stop on the limit, but never jump onto it from outside
+            if velocity > 0.0 && next > max {
+                next = libm::fmaxf(max, position);
+                velocity = 0.0;
+            } else if velocity < 0.0 && next < min {
+                next = libm::fminf(min, position);
+                velocity = 0.0;
+            }
+
+            self.velocity[i] = velocity;
+            self.position[i] = next;
+        }
+
+        self.position.map(|p| libm::roundf(p) as i32)
+    }
+}
@@ -0,0 +1,174 @@
+use dolly_protocol::{
+    plan::AXES,
+    profile::{self, Move, Path},
+    tracking::Target,
+    Limits,
+};
+
+use crate::timer::tc1::StepTimerTC1;
+
+use self::{
+    jog::Jog,
+    pulse::{ONE_STEP, TICK_HZ},
+};
+
+use super::{axis::Axes, units::Calibration};
+
+pub mod jog;
+pub mod pulse;
+
+// The fastest an axis may be asked to go has to fit the step generator
+pub const fn within_step_rate(calibration: &Calibration, limits: &Limits) -> bool {
+    calibration.to_steps(limits.max_velocity as i32) <= TICK_HZ as i32
+}
+
+// Plays one timed move at a time on the sequenced axes, or a jog. Every
+// update aims each step channel at where its profile will be by the next
+// update, so a late update costs smoothness but never position.
+pub struct Executor {
+    timer: StepTimerTC1,
+    current: Option<Move>,
+    jog: Option<Jog>,
+}
+
+impl Executor {
+    pub const UPDATE_MS: u32 = profile::UPDATE_MS;
+    const TICKS_PER_UPDATE: u32 = TICK_HZ * Self::UPDATE_MS / 1000;
+
+    pub fn new(timer: StepTimerTC1) -> Self {
+        Self {
+            timer,
+            current: None,
+            jog: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.current.is_some() || self.jog.is_some()
+    }
+
+    pub fn is_jogging(&self) -> bool {
+        self.jog.is_some()
+    }
+
+    // Starts from wherever the axes are, `started` may lie in the past to
+    // keep a longer schedule on time
+    pub fn start(
+        &mut self,
+        axes: &Axes,
+        to: &[i32; AXES],
+        duration_ms: u32,
+        started: u32,
+        track: Option<Target>,
+    ) {
+        let from = axes.sequenced().map(|axis| axis.position());
+        let path = Path::new(&axes.limits(), &from, to, duration_ms, track);
+
+        if !self.is_running() {
+            self.timer.start(pulse::tick);
+        }
+        self.jog = None;
+        self.current = Some(Move::new(path, duration_ms, started));
+    }
+
+    pub fn remaining_ms(&self, now: u32) -> u32 {
+        self.current.as_ref().map_or(0, |m| m.remaining_ms(now))
+    }
+
+    // The axes brake to a stop on their path and hold there
+    pub fn pause(&mut self, axes: &Axes, now: u32) {
+        if let Some(m) = &mut self.current {
+            m.pause(&axes.limits(), now);
+        }
+    }
+
+    // Back up to speed from wherever the move is held
+    pub fn resume(&mut self, axes: &Axes, now: u32) {
+        if let Some(m) = &mut self.current {
+            m.resume(&axes.limits(), now);
+        }
+    }
+
+    // Brakes like a pause, then drops the move
+    pub fn abort(&mut self, axes: &Axes, now: u32) {
+        if let Some(m) = &mut self.current {
+            m.abort(&axes.limits(), now);
+        }
+    }
+
+    // While a pause, resume or abort is still changing speed
+    pub fn is_easing(&self, now: u32) -> bool {
+        self.current.as_ref().is_some_and(|m| m.is_easing(now))
+    }
+
+    // Velocities in units per second, ramped within the axis limits. All
+    // zero winds the jog down, it ends by itself once everything stands.
+    pub fn jog(&mut self, axes: &Axes, velocity: [i32; AXES], now: u32) {
+        if self.current.is_some() {
+            return;
+        }
+        if self.jog.is_none() {
+            if velocity == [0; AXES] {
+                return;
+            }
+            self.timer.start(pulse::tick);
+            self.jog = Some(Jog::new(axes.sequenced().map(|a| a.position()), now));
+        }
+        if let Some(jog) = &mut self.jog {
+            jog.set(velocity);
+        }
+    }
+
+    // Returns true once, when the running move has arrived
+    pub fn update(&mut self, axes: &Axes, now: u32) -> bool {
+        if let Some(jog) = &mut self.jog {
+            let positions = jog.advance(&axes.limits(), now);
+            let stopped = jog.is_stopped();
+            if Self::aim(axes, &positions) && stopped {
+                self.finish();
+            }
+            return false;
+        }
+
+        let Some(m) = &mut self.current else {
+            return false;
+        };
+
+        let idle = Self::aim(axes, &m.positions(now.wrapping_add(Self::UPDATE_MS)));
+        match m.settle(now, idle) {
+            Some(arrived) => {
+                self.finish();
+                arrived
+            }
+            None => false,
+        }
+    }
+
+    // Sends every axis towards its position by the next update, true when
+    // they are all there already
+    fn aim(axes: &Axes, positions: &[i32; AXES]) -> bool {
+        let mut idle = true;
+        for (axis, &position) in axes.sequenced().iter().zip(positions) {
+            let target = axis.calibration.to_steps(position);
+            let distance = axis.channel.position().abs_diff(target);
+            let rate = distance.saturating_mul(ONE_STEP) / Self::TICKS_PER_UPDATE;
+            axis.channel.move_to(target, rate);
+            idle &= distance == 0;
+        }
+        idle
+    }
+
+    // Stops every axis where it is, without a ramp
+    pub fn halt(&mut self, axes: &Axes) {
+        for axis in axes.sequenced() {
+            axis.channel.halt();
+        }
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        self.timer.stop();
+        self.current = None;
+        self.jog = None;
+    }
+}
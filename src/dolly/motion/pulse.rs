@@ -0,0 +1,142 @@
+use core::cell::RefCell;
+
+use avr_device::interrupt::{self, Mutex};
+
+use crate::{
+    dolly::components::stepper::{Direction, Stepper},
+    timer::tc1::StepTimerTC1,
+};
+
+// Step generation runs in the TC1 tick. Every axis is a DDA: each tick adds
+// its rate to a phase accumulator and steps when the phase wraps, until the
+// position reaches the target. The main loop only moves targets and rates.
+
+#[cfg(not(feature = "mega2560"))]
+pub const CHANNELS: usize = 3;
+#[cfg(feature = "mega2560")]
+pub const CHANNELS: usize = 4;
+
+// rates are steps per tick in 16.16 fixed point, so one step per tick at most
+pub const ONE_STEP: u32 = 1 << 16;
+pub const TICK_HZ: u32 = StepTimerTC1::TARGET_FREQ;
+
+struct Slot {
+    stepper: Stepper,
+    target: i32,
+    rate: u32,
+    phase: u32,
+}
+
+impl Slot {
+    fn tick(&mut self) {
+        let position = self.stepper.position();
+        if position == self.target {
+            return;
+        }
+
+        self.phase += self.rate;
+        if self.phase < ONE_STEP {
+            return;
+        }
+        self.phase -= ONE_STEP;
+        self.stepper.step();
+    }
+
+    fn aim(&mut self) {
+        let wanted = match self.target > self.stepper.position() {
+            true => Direction::Clockwise,
+            false => Direction::CounterClockwise,
+        };
+        if self.target != self.stepper.position() && wanted != self.stepper.direction() {
+            self.stepper.set_direction(wanted);
+        }
+    }
+}
+
+const EMPTY: Option<Slot> = None;
+static SLOTS: Mutex<RefCell<[Option<Slot>; CHANNELS]>> =
+    Mutex::new(RefCell::new([EMPTY; CHANNELS]));
+
+// Handed to StepTimerTC1::start
+pub fn tick() {
+    interrupt::free(|cs| {
+        for slot in SLOTS.borrow(cs).borrow_mut().iter_mut().flatten() {
+            slot.tick();
+        }
+    });
+}
+
+// Handle to a stepper owned by the step generator. Direct steps are for
+// homing and other blocking moves while no rate is set.
+#[derive(Clone, Copy)]
+pub struct Channel(usize);
+
+impl Channel {
+    // Panics when the board has more steppers than channels, a wiring bug
+    pub fn attach(stepper: Stepper) -> Self {
+        interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            let index = slots.iter().position(|s| s.is_none()).unwrap();
+            let target = stepper.position();
+            slots[index] = Some(Slot {
+                stepper,
+                target,
+                rate: 0,
+                phase: 0,
+            });
+            Self(index)
+        })
+    }
+
+    fn with<R>(self, f: impl FnOnce(&mut Slot) -> R) -> R {
+        interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            // attach is the only way to get a channel
+            f(slots[self.0].as_mut().unwrap())
+        })
+    }
+
+    pub fn position(self) -> i32 {
+        self.with(|s| s.stepper.position())
+    }
+
+    pub fn set_position(self, position: i32) {
+        self.with(|s| {
+            s.stepper.set_position(position);
+            s.target = position;
+        })
+    }
+
+    pub fn set_direction(self, dir: Direction) {
+        self.with(|s| s.stepper.set_direction(dir))
+    }
+
+    pub fn step(self) {
+        self.with(|s| {
+            s.stepper.step();
+            s.target = s.stepper.position();
+        })
+    }
+
+    // Steps towards `target` at `rate` from the next tick on
+    pub fn move_to(self, target: i32, rate: u32) {
+        self.with(|s| {
+            s.target = target;
+            s.rate = rate.min(ONE_STEP);
+            s.aim();
+        })
+    }
+
+    // Stops at the current position, whatever the target was
+    pub fn halt(self) {
+        self.with(|s| {
+            s.target = s.stepper.position();
+            s.rate = 0;
+            s.phase = 0;
+        })
+    }
+
+    pub fn is_idle(self) -> bool {
+        self.with(|s| s.target == s.stepper.position())
+    }
+}
@@ -0,0 +1,112 @@
+use dolly_protocol::{Message, Telemetry};
+
+use crate::{
+    link::Link,
+    println,
+    scheduler::{Scheduler, TaskId},
+};
+
+pub use dolly_protocol::shell::Format;
+
+// Owns the periodic telemetry task and derives rates between two samples
+pub struct Stream {
+    task: Option<TaskId>,
+    period_ms: u16,
+    format: Format,
+    last: Option<(u32, [i32; 3])>,
+    loops: u32,
+}
+
+impl Stream {
+    pub const fn new() -> Self {
+        Self {
+            task: None,
+            period_ms: 0,
+            format: Format::Line,
+            last: None,
+            loops: 0,
+        }
+    }
+
+    pub fn period_ms(&self) -> u16 {
+        self.period_ms
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    // 0 stops the stream
+    pub fn set_period<T: Copy, const N: usize>(
+        &mut self,
+        scheduler: &mut Scheduler<T, N>,
+        task: T,
+        period_ms: u16,
+        now: u32,
+    ) {
+        if let Some(id) = self.task.take() {
+            scheduler.cancel(id);
+        }
+
+        self.last = None;
+        self.loops = 0;
+        self.period_ms = 0;
+        if period_ms > 0 {
+            self.task = scheduler.every(task, period_ms as u32, now);
+            if self.task.is_some() {
+                self.period_ms = period_ms;
+            }
+        }
+    }
+
+    // Called once per main loop iteration
+    pub fn count_loop(&mut self) {
+        self.loops = self.loops.wrapping_add(1);
+    }
+
+    // Fills in velocities and loop rate, then sends the sample in the current format
+    pub fn emit(&mut self, link: &mut Link, mut t: Telemetry) {
+        if let Some((last_ms, last_pos)) = self.last {
+            let dt = t.uptime_ms.wrapping_sub(last_ms) as i64;
+            if dt > 0 {
+                for (v, (pos, last)) in t.velocity.iter_mut().zip(t.position.iter().zip(last_pos)) {
+                    *v = ((*pos as i64 - last as i64) * 1000 / dt) as i32;
+                }
+                t.loop_hz = (self.loops as i64 * 1000 / dt).min(u16::MAX as i64) as u16;
+            }
+        }
+        self.last = Some((t.uptime_ms, t.position));
+        self.loops = 0;
+
+        match self.format {
+            Format::Line => Self::write_line(&t),
+            Format::Binary => link.notify(Message::Telemetry(t)),
+        }
+    }
+
+    fn write_line(t: &Telemetry) {
+        let [slider, pan, tilt] = t.position;
+        let [v_slider, v_pan, v_tilt] = t.velocity;
+        println!(
+            "TLM {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            t.uptime_ms,
+            t.state as u8,
+            slider,
+            pan,
+            tilt,
+            v_slider,
+            v_pan,
+            v_tilt,
+            t.frame,
+            t.remaining_s,
+            t.battery_mv,
+            t.loop_hz,
+            t.overruns,
+            t.max_lateness_ms
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use ufmt::{uDisplay, uwrite};
+
+pub use dolly_protocol::units::{deg, mm, Calibration, Unit};
+
+// A value in thousandths, printed as the whole unit with three decimals
+pub struct Quantity(pub i32, pub Unit);
+
+impl uDisplay for Quantity {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: avr_hal_generic::prelude::_ufmt_uWrite + ?Sized,
+    {
+        let (value, unit) = (self.0, self.1);
+        let sign = if value < 0 { "-" } else { "" };
+        let abs = value.unsigned_abs();
+        let frac = abs % 1000;
+        let pad = match frac {
+            0..=9 => "00",
+            10..=99 => "0",
+            _ => "",
+        };
+        uwrite!(f, "{}{}.{}{}{}", sign, abs / 1000, pad, frac, unit.symbol())
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(feature = "serial-shell")]
+use dolly_protocol::shell::Command;
+use dolly_protocol::{Demux, Error, Event, Message, Packet, Replay, MAX_FRAME_LEN};
+
+use crate::serial;
+#[cfg(feature = "serial-shell")]
+use crate::{serial::line::LineError, shell::Shell};
+
+pub enum Incoming {
+    #[cfg(feature = "serial-shell")]
+    Command(Command),
+    #[cfg(feature = "serial-shell")]
+    Rejected(LineError),
+    Packet(Packet),
+    Error(Error),
+}
+
+// USART0 carries both the text shell and the binary protocol, without the
+// shell text is dropped
+pub struct Link {
+    demux: Demux,
+    #[cfg(feature = "serial-shell")]
+    shell: Shell,
+    rx_dropped: u32,
+    seq: u8,
+    replay: Replay,
+}
+
+impl Link {
+    pub const fn new() -> Self {
+        Self {
+            demux: Demux::new(),
+            #[cfg(feature = "serial-shell")]
+            shell: Shell::new(),
+            rx_dropped: 0,
+            seq: 0,
+            replay: Replay::new(),
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<Incoming> {
+        while let Some(byte) = serial::read_byte() {
+            let dropped = serial::rx_dropped();
+            if dropped != self.rx_dropped {
+                self.rx_dropped = dropped;
+                #[cfg(feature = "serial-shell")]
+                self.shell.overrun();
+                self.demux.reset();
+            }
+
+            match self.demux.push(byte) {
+                #[cfg(feature = "serial-shell")]
+                Some(Event::Text(byte)) => match self.shell.push(byte) {
+                    Some(Ok(cmd)) => return Some(Incoming::Command(cmd)),
+                    Some(Err(e)) => return Some(Incoming::Rejected(e)),
+                    None => {}
+                },
+                #[cfg(not(feature = "serial-shell"))]
+                Some(Event::Text(_)) => {}
+                // a retry of a late reply, e.g. during an EEPROM write, must
+                // not step or start anything a second time
+                Some(Event::Packet(packet)) => match self.replay.get(&packet) {
+                    Some(reply) => Self::send(&Packet::new(packet.seq, reply)),
+                    None => return Some(Incoming::Packet(packet)),
+                },
+                Some(Event::Error(e)) => return Some(Incoming::Error(e)),
+                None => {}
+            }
+        }
+
+        None
+    }
+
+    pub fn reply(&mut self, request: &Packet, message: Message) {
+        self.replay.record(request, message);
+        Self::send(&Packet::new(request.seq, message));
+    }
+
+    // Unsolicited messages get their own sequence numbers
+    pub fn notify(&mut self, message: Message) {
+        self.seq = self.seq.wrapping_add(1);
+        Self::send(&Packet::new(self.seq, message));
+    }
+
+    fn send(packet: &Packet) {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        if let Ok(len) = packet.encode(&mut frame) {
+            for byte in &frame[..len] {
+                serial::write_byte(*byte);
+            }
+        }
+    }
+}
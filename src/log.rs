@@ -0,0 +1,101 @@
+use core::cell::Cell;
+
+use avr_device::interrupt::Mutex;
+
+pub use dolly_protocol::shell::Level;
+
+// Compile time filters, the longest matching module prefix wins. Anything above
+// the level of its module is compiled out, strings included.
+const DEFAULT_LEVEL: Level = Level::Info;
+const FILTERS: &[(&str, Level)] = &[
+    (
+        "rust_camera_dolly::dolly::components::irremote",
+        Level::Debug,
+    ),
+    (
+        "rust_camera_dolly::dolly::components::stepper",
+        Level::Debug,
+    ),
+];
+
+const fn starts_with(module: &str, prefix: &str) -> bool {
+    let (module, prefix) = (module.as_bytes(), prefix.as_bytes());
+    if prefix.len() > module.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < prefix.len() {
+        if module[i] != prefix[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+pub const fn static_level(module: &str) -> Level {
+    let mut level = DEFAULT_LEVEL;
+    let mut matched = 0;
+
+    let mut i = 0;
+    while i < FILTERS.len() {
+        let (prefix, filter) = FILTERS[i];
+        if prefix.len() >= matched && starts_with(module, prefix) {
+            level = filter;
+            matched = prefix.len();
+        }
+        i += 1;
+    }
+    level
+}
+
+pub const fn enabled(module: &str, level: Level) -> bool {
+    level as u8 <= static_level(module) as u8
+}
+
+static RUNTIME_LEVEL: Mutex<Cell<Level>> = Mutex::new(Cell::new(Level::Info));
+
+pub fn level() -> Level {
+    avr_device::interrupt::free(|cs| RUNTIME_LEVEL.borrow(cs).get())
+}
+
+pub fn set_level(level: Level) {
+    avr_device::interrupt::free(|cs| RUNTIME_LEVEL.borrow(cs).set(level));
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($t:tt)*) => {{
+        const ENABLED: bool = crate::log::enabled(module_path!(), $level);
+        if ENABLED && $level <= crate::log::level() {
+            crate::print!("[{} {}] ", crate::timer::millis(), $level.tag());
+            crate::println!($($t)*);
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($t:tt)*) => { crate::log!(crate::log::Level::Error, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($t:tt)*) => { crate::log!(crate::log::Level::Warn, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($t:tt)*) => { crate::log!(crate::log::Level::Info, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($t:tt)*) => { crate::log!(crate::log::Level::Debug, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($t:tt)*) => { crate::log!(crate::log::Level::Trace, $($t)*) };
+}
@@ -2,20 +2,51 @@
 #![no_main]
 #![feature(abi_avr_interrupt)]
 
-use arduino_hal::{prelude::*, Peripherals};
+use arduino_hal::hal::usart::BaudrateArduinoExt;
+use arduino_hal::hal::wdt::{Timeout, Wdt};
+use arduino_hal::prelude::*;
 use dolly::components::arduino::io::{DigitalWrite, State};
+#[cfg(feature = "ir")]
 use dolly::components::irremote::IRRemote;
+#[cfg(feature = "mega2560")]
+use dolly_protocol::rig::{AUX, AUX_LIMITS};
+use dolly_protocol::rig::{PAN, PAN_LIMITS, SLIDER, SLIDER_LIMITS, TILT, TILT_LIMITS};
 
+use crate::dolly::axis::{Axes, Axis, Homing};
 use crate::dolly::components::arduino::adc_manager::AdcManager;
+#[cfg(any(feature = "joystick", feature = "telemetry"))]
 use crate::dolly::components::arduino::pins::analog_pin::AnalogInput;
 use crate::dolly::components::arduino::pins::digital_pin::{DigitalInput, DigitalOutput};
+#[cfg(feature = "telemetry")]
+use crate::dolly::components::battery::Battery;
+#[cfg(feature = "joystick")]
 use crate::dolly::components::joystick::Joystick;
+use crate::dolly::components::shutter::Shutter;
+use crate::dolly::components::stepper::homing::{HomingConfig, StallGuard, StallSource};
+#[cfg(not(feature = "mega2560"))]
+use crate::dolly::components::stepper::tmc2209::SoftTx;
+use crate::dolly::components::stepper::tmc2209::Tmc2209;
+use crate::dolly::components::stepper::{Direction, DriverEnable, Stepper};
+use crate::dolly::motion::{within_step_rate, Executor};
+use crate::dolly::units::mm;
+use crate::pcint::{Action, Edge, Flag, PinChange};
 use crate::timer::tc0::ClockTC0;
-use crate::timer::tc1::ClockTC1;
+use crate::timer::tc1::StepTimerTC1;
+use crate::timer::Timers;
+use crate::watchdog::{ResetCause, Watchdog};
 
+mod board;
+mod crash;
 mod dolly;
+mod link;
+mod log;
+mod pcint;
+mod scheduler;
 mod serial;
+#[cfg(feature = "serial-shell")]
+mod shell;
 mod timer;
+mod watchdog;
 
 #[cfg(not(doc))]
 #[panic_handler]
@@ -30,23 +61,31 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     // we know it is okay.
     let dp = unsafe { arduino_hal::Peripherals::steal() };
     let pins = arduino_hal::pins!(dp);
-    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+
+    // Motors and camera first, the pin map brings every output up in its
+    // safe state: drivers disabled, step pins low and the shutter released
+    let map = board::pin_map(pins, &mut adc);
+
+    let report = match info.location() {
+        Some(loc) => crash::Report::new(crash::Code::Panic, loc.file(), loc.line(), loc.column()),
+        None => crash::Report::new(crash::Code::PanicWithoutLocation, "?", 0, 0),
+    };
+    crash::record(report);
+
+    let mut serial = arduino_hal::Usart::new(
+        dp.USART0,
+        map.console_rx,
+        map.console_tx,
+        BaudrateArduinoExt::into_baudrate(CONSOLE_BAUD),
+    );
 
     // Print out panic location
     ufmt::uwriteln!(&mut serial, "Firmware panic!\r").unwrap_infallible();
-    if let Some(loc) = info.location() {
-        ufmt::uwriteln!(
-            &mut serial,
-            "  At {}:{}:{}\r",
-            loc.file(),
-            loc.line(),
-            loc.column(),
-        )
-        .unwrap_infallible();
-    }
+    ufmt::uwriteln!(&mut serial, "  {}\r", report).unwrap_infallible();
 
     // Blink LED rapidly
-    let mut led = pins.d13.into_output();
+    let mut led = map.builtin_led;
     loop {
         led.toggle();
         arduino_hal::delay_ms(30);
@@ -57,12 +96,42 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 * TODO LIST
 * [x] Implement Switch
 * [x] Implement IR Remote
-* [ ] Implement Clock (millis function equivalent)
+* [x] Implement Clock (millis function equivalent)
 * [ ] Implement LCD Display
-* [ ] Implement Stepper
+* [x] Implement Stepper
+* [x] Implement sensorless homing
 * [?] Implement Potentiometer
 * */
 
+const CONSOLE_BAUD: u32 = 57_600;
+#[cfg(feature = "mega2560")]
+const TMC_BAUD: u32 = 115_200;
+
+// The interrupt fires after one timeout and the reset follows after another
+const WATCHDOG_TIMEOUT: Timeout = Timeout::Ms500;
+
+const _: () = assert!(within_step_rate(&SLIDER, &SLIDER_LIMITS));
+const _: () = assert!(within_step_rate(&PAN, &PAN_LIMITS));
+const _: () = assert!(within_step_rate(&TILT, &TILT_LIMITS));
+#[cfg(feature = "mega2560")]
+const _: () = assert!(within_step_rate(&AUX, &AUX_LIMITS));
+
+// Slider homing in steps, the build fails if a calibration no longer fits
+// the fields
+const HOMING_SPEED: u16 = fit_u16(SLIDER.to_steps(mm(10)));
+const HOMING_BACKOFF: u16 = fit_u16(SLIDER.to_steps(mm(20)));
+const HOMING_TRAVEL: u32 = fit_u32(SLIDER.to_steps(mm(1_300)));
+
+const fn fit_u16(steps: i32) -> u16 {
+    assert!(steps >= 0 && steps <= u16::MAX as i32, "out of u16 range");
+    steps as u16
+}
+
+const fn fit_u32(steps: i32) -> u32 {
+    assert!(steps >= 0, "out of u32 range");
+    steps as u32
+}
+
 fn signal_hardware_is_ready(bled: &mut DigitalOutput) {
     for _ in 0..10 {
         bled.write(State::HIGH);
@@ -74,100 +143,187 @@ fn signal_hardware_is_ready(bled: &mut DigitalOutput) {
     bled.write(State::LOW);
 }
 
-// For IR Remote interrupts
-// Reference: https://github.com/steveio/arduino/blob/master/PinChangeInterrupts/PinChangeInterrupts.ino
-// https://www.arduino.cc/reference/en/language/functions/external-interrupts/attachinterrupt/
-fn configure_interrupts(dp: &Peripherals) {
-    const PCICR_PORTB: u8 = 0b001; // turn on port B (PCINT0 – PCINT7)
-    const PCICR_PORTC: u8 = 0b010; // turn on port C (PCINT8 – PCINT14)
-    const PCICR_PORTD: u8 = 0b100; // turn on port D (PCINT16 – PCINT23)
-
-    avr_device::interrupt::free(|_| {
-        // WARNING: Change this if IR Remote port changes
-        // Port D2 (IR Remote is port PD2 / PCINT18)
-        dp.EXINT.pcicr.write(|w| unsafe { w.bits(PCICR_PORTD) });
-        dp.EXINT.pcmsk2.write(|w| w.bits(0b100));
-    })
-}
-
 #[arduino_hal::entry]
 fn main() -> ! {
     avr_device::interrupt::disable();
 
     let dp = arduino_hal::Peripherals::take().unwrap();
-    // configure_interrupts(&dp);
+
+    // before anything slow, a watchdog reset leaves WDE set at the 16 ms
+    // default and Wdt::new only clears WDRF, so stop it until arm_watchdog
+    let reset_cause = ResetCause::take(&dp.CPU.mcusr);
+    let mut watchdog = Watchdog::new(Wdt::new(dp.WDT, &dp.CPU.mcusr), WATCHDOG_TIMEOUT);
+    watchdog.stop();
 
     let pins = arduino_hal::pins!(dp);
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+    let map = board::pin_map(pins, &mut adc);
+
     {
-        let console = arduino_hal::default_serial!(dp, pins, 57600);
+        let console = arduino_hal::Usart::new(
+            dp.USART0,
+            map.console_rx,
+            map.console_tx,
+            BaudrateArduinoExt::into_baudrate(CONSOLE_BAUD),
+        );
         serial::put_console(console);
     }
-    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
-
-    let tc0_clock = ClockTC0::new();
-    tc0_clock.start(dp.TC0);
 
-    let tc1_clock = ClockTC1::new();
-    tc1_clock.start(dp.TC1);
+    let timers = Timers::claim(dp.TC0, dp.TC1, dp.TC2);
+    timers.clock.start();
+    // TC2 is left alone until something needs PWM
+    let _ = timers.spare;
 
-    let mut v: i64 = 1_000;
-    v *= ClockTC0::PRESCALER as i64;
-    v *= ClockTC0::TIMER_COUNTS as i64;
-    v /= 16_000;
-    println!("Time delta: {}ms", v);
-
-    println!("Camera Dolly setup ...");
+    debug!(
+        "Clock: {}mHz ({}ppm), step timer: {}mHz ({}ppm)",
+        ClockTC0::TIMER.actual_millihertz(),
+        ClockTC0::TIMER.error_ppm(),
+        StepTimerTC1::TIMER.actual_millihertz(),
+        StepTimerTC1::TIMER.error_ppm()
+    );
 
-    let joy_switch_pin = pins.d4.into_pull_up_input();
-    let joy_x = pins.a0.into_analog_input(&mut adc);
-    let joy_y = pins.a1.into_analog_input(&mut adc);
+    info!("Camera Dolly setup ...");
+    info!("Reset cause: {}", reset_cause);
+    if let Some(report) = crash::last() {
+        error!("Crashed before reset: {}", report);
+    }
 
     AdcManager::initialize(adc);
 
-    let analog_x_pos = AnalogInput::new(joy_x.into_channel(), AdcManager::new());
-    let analog_y_pos = AnalogInput::new(joy_y.into_channel(), AdcManager::new());
-    let pull_up_switch_pin = DigitalInput::new(
-        joy_switch_pin
-            .into_pull_up_input()
-            .downgrade()
-            .forget_imode(),
-    );
-
-    let joystick = Joystick::new(analog_x_pos, analog_y_pos, pull_up_switch_pin);
+    #[cfg(feature = "joystick")]
+    let joystick = {
+        let analog_x_pos = AnalogInput::new(map.joy_x, AdcManager::new());
+        let analog_y_pos = AnalogInput::new(map.joy_y, AdcManager::new());
+        let pull_up_switch_pin = DigitalInput::new(map.joy_switch);
 
-    let mut builtin_led = {
-        let pin = pins.d13.into_output();
-        DigitalOutput::new(pin.downgrade())
+        Joystick::new(analog_x_pos, analog_y_pos, pull_up_switch_pin)
     };
+    #[cfg(feature = "telemetry")]
+    let battery = Battery::new(AnalogInput::new(map.battery, AdcManager::new()));
+
+    let mut builtin_led = DigitalOutput::new(map.builtin_led);
     signal_hardware_is_ready(&mut builtin_led);
 
-    let in_led = {
-        let pin = pins.d6.into_output();
-        DigitalOutput::new(pin.downgrade())
-    };
-    let out_led = {
-        let pin = pins.d5.into_output();
-        DigitalOutput::new(pin.downgrade())
+    let in_led = DigitalOutput::new(map.in_led);
+    let out_led = DigitalOutput::new(map.out_led);
+
+    #[cfg(feature = "ir")]
+    let irremote = {
+        IRRemote::initialize(map.ir);
+        IRRemote::new()
     };
 
-    IRRemote::initialize(pins.d2);
-    let irremote = IRRemote::new();
+    let drivers = DriverEnable::new(DigitalOutput::new(map.drivers_enable));
+    let shutter = Shutter::new(DigitalOutput::new(map.shutter));
+    #[cfg(feature = "stop-motion")]
+    let eeprom = arduino_hal::Eeprom::new(dp.EEPROM);
+
+    #[cfg(not(feature = "mega2560"))]
+    let tmc_uart = SoftTx::new(DigitalOutput::new(map.tmc_tx));
+    #[cfg(feature = "mega2560")]
+    let tmc_uart = arduino_hal::Usart::new(
+        dp.USART1,
+        map.tmc_rx,
+        map.tmc_tx,
+        BaudrateArduinoExt::into_baudrate(TMC_BAUD),
+    );
+
+    let mut pin_change = PinChange::new(dp.EXINT);
+
+    static SLIDER_STALL: Flag = Flag::new();
+    pin_change
+        .attach(
+            board::SLIDER_DIAG_PCINT,
+            Edge::Rising,
+            Action::Flag(&SLIDER_STALL),
+        )
+        .unwrap();
+    let slider_diag = DigitalInput::new(map.slider_diag);
+    let axes = Axes {
+        slider: Axis::new(
+            Stepper::new(
+                DigitalOutput::new(map.slider_step),
+                DigitalOutput::new(map.slider_dir),
+            ),
+            SLIDER,
+            SLIDER_LIMITS,
+        )
+        .with_homing(Homing {
+            config: HomingConfig {
+                direction: Direction::CounterClockwise,
+                speed: HOMING_SPEED,
+                stall_threshold: 80,
+                backoff: HOMING_BACKOFF,
+                max_travel: HOMING_TRAVEL,
+            },
+            stall: StallGuard::new(
+                Tmc2209::new(0),
+                StallSource::Diag {
+                    pin: slider_diag,
+                    latch: &SLIDER_STALL,
+                },
+            ),
+        }),
+        pan: Axis::new(
+            Stepper::new(
+                DigitalOutput::new(map.pan_step),
+                DigitalOutput::new(map.pan_dir),
+            ),
+            PAN,
+            PAN_LIMITS,
+        ),
+        tilt: Axis::new(
+            Stepper::new(
+                DigitalOutput::new(map.tilt_step),
+                DigitalOutput::new(map.tilt_dir),
+            ),
+            TILT,
+            TILT_LIMITS,
+        ),
+        #[cfg(feature = "mega2560")]
+        aux: Axis::new(
+            Stepper::new(
+                DigitalOutput::new(map.aux_step),
+                DigitalOutput::new(map.aux_dir),
+            ),
+            AUX,
+            AUX_LIMITS,
+        ),
+    };
 
     let settings = dolly::Settings {
-        tc0_clock,
-        tc1_clock,
+        motion: Executor::new(timers.step),
+        #[cfg(feature = "ir")]
         irremote,
+        #[cfg(feature = "joystick")]
         joystick,
         builtin_led,
         in_led,
         out_led,
+        drivers,
+        axes,
+        tmc_uart,
+        #[cfg(feature = "telemetry")]
+        battery,
+        shutter,
+        watchdog,
+        #[cfg(feature = "stop-motion")]
+        eeprom,
     };
-    let mut dolly = dolly::Dolly::new(settings);
+    let mut dolly = dolly::Dolly::new(settings, reset_cause);
 
-    // Enable interrupts globally
+    // Enable interrupts globally, homing relies on the pin-change latch
     unsafe { avr_device::interrupt::enable() };
 
-    println!("Started ...");
+    if let Err(e) = dolly.home() {
+        error!(
+            "Homing failed: {}, motion blocked until `home` or `home skip`",
+            e
+        );
+    }
+    dolly.arm_watchdog();
+
+    info!("Started ...");
 
     loop {
         dolly.run();
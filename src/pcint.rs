@@ -0,0 +1,213 @@
+/*
+* REFERENCE
+* - ATmega328P datasheet, 12.2 Register Description (PCICR, PCMSK0..2)
+* - https://github.com/steveio/arduino/blob/master/PinChangeInterrupts/PinChangeInterrupts.ino
+*
+* Pins are named by their PCINT number, which pin that is depends on the
+* board, see board/. Each group of eight shares a single vector, so the ISR
+* compares the group against its last state to find the pins that changed.
+*/
+
+use core::cell::{Cell, RefCell};
+
+use avr_device::interrupt::{CriticalSection, Mutex};
+
+use crate::board;
+
+const MAX_HANDLERS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PinId {
+    group: u8,
+    bit: u8,
+}
+
+impl PinId {
+    pub const fn pcint(n: u8) -> Self {
+        assert!(n < 24);
+        Self {
+            group: n / 8,
+            bit: n % 8,
+        }
+    }
+
+    fn mask(&self) -> u8 {
+        1 << self.bit
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Any,
+}
+
+// Latched by the ISR, cleared by whoever reads it
+pub struct Flag(Mutex<Cell<bool>>);
+
+impl Flag {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Cell::new(false)))
+    }
+
+    pub fn take(&self) -> bool {
+        avr_device::interrupt::free(|cs| self.0.borrow(cs).replace(false))
+    }
+
+    fn raise(&self, cs: CriticalSection<'_>) {
+        self.0.borrow(cs).set(true);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Action {
+    // runs inside the ISR with the new pin level, keep it short
+    Handler(fn(bool)),
+    Flag(&'static Flag),
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    pin: PinId,
+    edge: Edge,
+    action: Action,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PcintError {
+    Full,
+    AlreadyAttached,
+}
+
+static SLOTS: Mutex<RefCell<[Option<Slot>; MAX_HANDLERS]>> =
+    Mutex::new(RefCell::new([None; MAX_HANDLERS]));
+static LAST: Mutex<Cell<[u8; 3]>> = Mutex::new(Cell::new([0; 3]));
+
+fn dispatch(group: u8, cs: CriticalSection<'_>) {
+    let now = board::read_pcint_group(group);
+    let mut last = LAST.borrow(cs).get();
+    let changed = now ^ last[group as usize];
+    last[group as usize] = now;
+    LAST.borrow(cs).set(last);
+
+    for slot in SLOTS.borrow(cs).borrow().iter().flatten() {
+        if slot.pin.group != group || changed & slot.pin.mask() == 0 {
+            continue;
+        }
+
+        let high = now & slot.pin.mask() != 0;
+        let wanted = match slot.edge {
+            Edge::Rising => high,
+            Edge::Falling => !high,
+            Edge::Any => true,
+        };
+        if !wanted {
+            continue;
+        }
+
+        match slot.action {
+            Action::Handler(handler) => handler(high),
+            Action::Flag(flag) => flag.raise(cs),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "mega2560"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega2560", avr_device::interrupt(atmega2560))]
+fn PCINT0() {
+    avr_device::interrupt::free(|cs| dispatch(0, cs));
+}
+
+#[cfg_attr(not(feature = "mega2560"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega2560", avr_device::interrupt(atmega2560))]
+fn PCINT1() {
+    avr_device::interrupt::free(|cs| dispatch(1, cs));
+}
+
+#[cfg_attr(not(feature = "mega2560"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega2560", avr_device::interrupt(atmega2560))]
+fn PCINT2() {
+    avr_device::interrupt::free(|cs| dispatch(2, cs));
+}
+
+// Owns the pin-change registers, components attach their pins during setup
+pub struct PinChange {
+    exint: arduino_hal::pac::EXINT,
+}
+
+impl PinChange {
+    pub fn new(exint: arduino_hal::pac::EXINT) -> Self {
+        exint.pcicr.reset();
+        exint.pcmsk0.reset();
+        exint.pcmsk1.reset();
+        exint.pcmsk2.reset();
+        Self { exint }
+    }
+
+    pub fn attach(&mut self, pin: PinId, edge: Edge, action: Action) -> Result<(), PcintError> {
+        avr_device::interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            if slots.iter().flatten().any(|s| s.pin == pin) {
+                return Err(PcintError::AlreadyAttached);
+            }
+            let free = slots
+                .iter_mut()
+                .find(|s| s.is_none())
+                .ok_or(PcintError::Full)?;
+            *free = Some(Slot { pin, edge, action });
+
+            // start from the current level so the first edge is a real one
+            let mut last = LAST.borrow(cs).get();
+            last[pin.group as usize] = board::read_pcint_group(pin.group);
+            LAST.borrow(cs).set(last);
+
+            self.set_mask(pin, true);
+            Ok(())
+        })
+    }
+
+    pub fn detach(&mut self, pin: PinId) {
+        avr_device::interrupt::free(|cs| {
+            let mut slots = SLOTS.borrow(cs).borrow_mut();
+            for slot in slots.iter_mut() {
+                if slot.is_some_and(|s| s.pin == pin) {
+                    *slot = None;
+                }
+            }
+            self.set_mask(pin, false);
+        })
+    }
+
+    fn set_mask(&mut self, pin: PinId, enabled: bool) {
+        let update = |bits: u8| match enabled {
+            true => bits | pin.mask(),
+            false => bits & !pin.mask(),
+        };
+        let mask = match pin.group {
+            0 => {
+                self.exint.pcmsk0.modify(|r, w| w.bits(update(r.bits())));
+                self.exint.pcmsk0.read().bits()
+            }
+            1 => {
+                self.exint.pcmsk1.modify(|r, w| w.bits(update(r.bits())));
+                self.exint.pcmsk1.read().bits()
+            }
+            _ => {
+                self.exint.pcmsk2.modify(|r, w| w.bits(update(r.bits())));
+                self.exint.pcmsk2.read().bits()
+            }
+        };
+
+        // a group stays enabled in PCICR while any of its pins is
+        let group_bit = 1 << pin.group;
+        self.exint.pcicr.modify(|r, w| {
+            let bits = match mask != 0 {
+                true => r.bits() | group_bit,
+                false => r.bits() & !group_bit,
+            };
+            // SAFETY: only PCIE0..2 are written
+            unsafe { w.bits(bits) }
+        });
+    }
+}
@@ -0,0 +1,138 @@
+// Cooperative scheduler for the main loop. Tasks are plain values handed back
+// to the caller when due, so they can borrow whatever the caller owns.
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Periodic(u32),
+    OneShot,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TaskStats {
+    pub runs: u32,
+    // ran a whole period late, or took longer than its period
+    pub overruns: u16,
+    pub max_lateness_ms: u16,
+    pub max_duration_ms: u16,
+}
+
+#[derive(Clone, Copy)]
+struct Slot<T> {
+    task: T,
+    kind: Kind,
+    deadline: u32,
+    started: u32,
+    stats: TaskStats,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TaskId(usize);
+
+pub struct Scheduler<T: Copy, const N: usize> {
+    slots: [Option<Slot<T>>; N],
+}
+
+// deadlines are compared with wrapping arithmetic so the millis rollover is harmless
+fn is_due(deadline: u32, now: u32) -> bool {
+    now.wrapping_sub(deadline) as i32 >= 0
+}
+
+fn saturate(ms: u32) -> u16 {
+    ms.min(u16::MAX as u32) as u16
+}
+
+impl<T: Copy, const N: usize> Scheduler<T, N> {
+    pub const fn new() -> Self {
+        Self { slots: [None; N] }
+    }
+
+    fn insert(&mut self, task: T, kind: Kind, deadline: u32) -> Option<TaskId> {
+        let index = self.slots.iter().position(|s| s.is_none())?;
+        self.slots[index] = Some(Slot {
+            task,
+            kind,
+            deadline,
+            started: deadline,
+            stats: TaskStats::default(),
+        });
+        Some(TaskId(index))
+    }
+
+    pub fn every(&mut self, task: T, period_ms: u32, now: u32) -> Option<TaskId> {
+        self.insert(task, Kind::Periodic(period_ms), now.wrapping_add(period_ms))
+    }
+
+    pub fn once(&mut self, task: T, delay_ms: u32, now: u32) -> Option<TaskId> {
+        self.insert(task, Kind::OneShot, now.wrapping_add(delay_ms))
+    }
+
+    pub fn cancel(&mut self, id: TaskId) {
+        self.slots[id.0] = None;
+    }
+
+    // Returns the most overdue task, the caller runs it and reports back with `done`
+    pub fn next(&mut self, now: u32) -> Option<(TaskId, T)> {
+        let (index, slot) = self
+            .slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, s)| s.as_mut().map(|s| (i, s)))
+            .filter(|(_, s)| is_due(s.deadline, now))
+            .max_by_key(|(_, s)| now.wrapping_sub(s.deadline))?;
+
+        let lateness = now.wrapping_sub(slot.deadline);
+        slot.stats.runs = slot.stats.runs.wrapping_add(1);
+        slot.stats.max_lateness_ms = slot.stats.max_lateness_ms.max(saturate(lateness));
+        slot.started = now;
+
+        if let Kind::Periodic(period) = slot.kind {
+            if lateness >= period {
+                // skip the missed slots instead of running the task back to back
+                slot.stats.overruns = slot.stats.overruns.saturating_add(1);
+                slot.deadline = now.wrapping_add(period);
+            } else {
+                slot.deadline = slot.deadline.wrapping_add(period);
+            }
+        }
+
+        Some((TaskId(index), slot.task))
+    }
+
+    pub fn done(&mut self, id: TaskId, now: u32) {
+        let Some(slot) = self.slots[id.0].as_mut() else {
+            return;
+        };
+
+        let duration = now.wrapping_sub(slot.started);
+        slot.stats.max_duration_ms = slot.stats.max_duration_ms.max(saturate(duration));
+
+        match slot.kind {
+            Kind::Periodic(period) if duration > period => {
+                slot.stats.overruns = slot.stats.overruns.saturating_add(1);
+            }
+            Kind::OneShot => self.slots[id.0] = None,
+            _ => {}
+        }
+    }
+
+    pub fn stats(&self, id: TaskId) -> Option<TaskStats> {
+        self.slots[id.0].as_ref().map(|s| s.stats)
+    }
+
+    pub fn overruns(&self) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|s| s.stats.overruns as u32)
+            .sum()
+    }
+
+    pub fn max_lateness(&self) -> u16 {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|s| s.stats.max_lateness_ms)
+            .max()
+            .unwrap_or(0)
+    }
+}
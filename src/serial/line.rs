@@ -0,0 +1,72 @@
+use ufmt::{uDisplay, uwrite};
+
+#[derive(Clone, Copy)]
+pub enum LineError {
+    // the line did not fit in the buffer
+    TooLong,
+    // bytes were lost in the receive buffer while the line was assembled
+    Overrun,
+}
+
+impl uDisplay for LineError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: avr_hal_generic::prelude::_ufmt_uWrite + ?Sized,
+    {
+        match self {
+            LineError::TooLong => uwrite!(f, "TooLong"),
+            LineError::Overrun => uwrite!(f, "Overrun"),
+        }
+    }
+}
+
+pub struct LineAssembler<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    error: Option<LineError>,
+}
+
+impl<const N: usize> LineAssembler<N> {
+    const BACKSPACE: u8 = 0x08;
+    const DELETE: u8 = 0x7F;
+
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            error: None,
+        }
+    }
+
+    // Marks the current line as corrupt, it is reported once it ends
+    pub fn overrun(&mut self) {
+        self.error = Some(LineError::Overrun);
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], LineError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                match self.error.take() {
+                    Some(e) => Some(Err(e)),
+                    // skip empty lines, and the \n of a \r\n pair
+                    None if len == 0 => None,
+                    None => Some(Ok(&self.buf[..len])),
+                }
+            }
+            Self::BACKSPACE | Self::DELETE => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ if self.len < N => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                None
+            }
+            _ => {
+                self.error = Some(LineError::TooLong);
+                None
+            }
+        }
+    }
+}
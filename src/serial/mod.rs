@@ -0,0 +1,193 @@
+use core::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+};
+
+use arduino_hal::prelude::*;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use avr_hal_generic::usart::Event;
+
+use self::ring::RingBuffer;
+
+#[cfg(feature = "serial-shell")]
+pub mod line;
+pub mod ring;
+
+type Console = arduino_hal::hal::usart::Usart0<arduino_hal::DefaultClock>;
+type Container = avr_device::interrupt::Mutex<RefCell<Option<Console>>>;
+pub static CONSOLE: Container = avr_device::interrupt::Mutex::new(RefCell::new(None));
+
+// ~22ms of output at 57600 baud
+const TX_LEN: usize = 128;
+static TX: Mutex<RefCell<RingBuffer<TX_LEN>>> = Mutex::new(RefCell::new(RingBuffer::new()));
+static TX_DROPPED: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static TX_POLICY: Mutex<Cell<FullPolicy>> = Mutex::new(Cell::new(FullPolicy::Drop));
+
+// ~11ms of input at 57600 baud, the main loop drains it far more often
+const RX_LEN: usize = 64;
+static RX: Mutex<RefCell<RingBuffer<RX_LEN>>> = Mutex::new(RefCell::new(RingBuffer::new()));
+static RX_DROPPED: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FullPolicy {
+    // discard the byte and count it, never stalls the caller
+    Drop,
+    // wait for the transmitter to make room, with interrupts enabled
+    Block,
+}
+
+#[allow(unused_macros)]
+#[macro_export]
+macro_rules! print {
+    ($($t:tt)*) => {
+        {
+            let _ = ufmt::uwrite!(&mut crate::serial::TxWriter, $($t)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! println {
+    ($($t:tt)*) => {
+        {
+            let _ = ufmt::uwriteln!(&mut crate::serial::TxWriter, $($t)*);
+        }
+    };
+}
+
+pub fn put_console(mut console: Console) {
+    console.listen(Event::RxComplete);
+    avr_device::interrupt::free(|cs| {
+        *CONSOLE.borrow(cs).borrow_mut() = Some(console);
+    })
+}
+
+pub fn set_full_policy(policy: FullPolicy) {
+    avr_device::interrupt::free(|cs| TX_POLICY.borrow(cs).set(policy));
+}
+
+pub fn full_policy() -> FullPolicy {
+    avr_device::interrupt::free(|cs| TX_POLICY.borrow(cs).get())
+}
+
+pub fn dropped() -> u32 {
+    avr_device::interrupt::free(|cs| TX_DROPPED.borrow(cs).get())
+}
+
+fn interrupts_enabled() -> bool {
+    // SAFETY: read only access to SREG
+    let cpu = unsafe { &*arduino_hal::pac::CPU::ptr() };
+    cpu.sreg.read().i().bit_is_set()
+}
+
+fn start_tx(cs: CriticalSection) {
+    if let Some(console) = CONSOLE.borrow(cs).borrow_mut().as_mut() {
+        console.listen(Event::DataRegisterEmpty);
+    }
+}
+
+pub fn write_byte(byte: u8) {
+    loop {
+        let queued = avr_device::interrupt::free(|cs| {
+            let queued = TX.borrow(cs).borrow_mut().push(byte);
+            if queued {
+                start_tx(cs);
+            }
+            queued
+        });
+        if queued {
+            return;
+        }
+
+        let policy = full_policy();
+        // waiting with interrupts disabled would never drain the buffer
+        if policy == FullPolicy::Drop || !interrupts_enabled() {
+            avr_device::interrupt::free(|cs| {
+                let dropped = TX_DROPPED.borrow(cs);
+                dropped.set(dropped.get().wrapping_add(1));
+            });
+            return;
+        }
+    }
+}
+
+pub fn flush() {
+    while interrupts_enabled()
+        && !avr_device::interrupt::free(|cs| TX.borrow(cs).borrow().is_empty())
+    {}
+}
+
+pub struct TxWriter;
+
+impl ufmt::uWrite for TxWriter {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        for byte in s.bytes() {
+            write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+pub fn read_byte() -> Option<u8> {
+    avr_device::interrupt::free(|cs| RX.borrow(cs).borrow_mut().pop())
+}
+
+pub fn rx_dropped() -> u32 {
+    avr_device::interrupt::free(|cs| RX_DROPPED.borrow(cs).get())
+}
+
+#[cfg(not(feature = "mega2560"))]
+#[avr_device::interrupt(atmega328p)]
+fn USART_UDRE() {
+    on_data_register_empty();
+}
+
+#[cfg(feature = "mega2560")]
+#[avr_device::interrupt(atmega2560)]
+fn USART0_UDRE() {
+    on_data_register_empty();
+}
+
+fn on_data_register_empty() {
+    avr_device::interrupt::free(|cs| {
+        let mut console = CONSOLE.borrow(cs).borrow_mut();
+        let Some(console) = console.as_mut() else {
+            return;
+        };
+
+        match TX.borrow(cs).borrow_mut().pop() {
+            Some(byte) => console.write_byte(byte),
+            None => console.unlisten(Event::DataRegisterEmpty),
+        }
+    })
+}
+
+#[cfg(not(feature = "mega2560"))]
+#[avr_device::interrupt(atmega328p)]
+fn USART_RX() {
+    on_receive_complete();
+}
+
+#[cfg(feature = "mega2560")]
+#[avr_device::interrupt(atmega2560)]
+fn USART0_RX() {
+    on_receive_complete();
+}
+
+fn on_receive_complete() {
+    avr_device::interrupt::free(|cs| {
+        let mut console = CONSOLE.borrow(cs).borrow_mut();
+        let Some(console) = console.as_mut() else {
+            return;
+        };
+
+        if let Ok(byte) = console.read() {
+            if !RX.borrow(cs).borrow_mut().push(byte) {
+                let dropped = RX_DROPPED.borrow(cs);
+                dropped.set(dropped.get().wrapping_add(1));
+            }
+        }
+    })
+}
@@ -0,0 +1,34 @@
+use dolly_protocol::shell::Command;
+
+use crate::serial::line::{LineAssembler, LineError};
+
+const LINE_LEN: usize = 32;
+
+// Assembles the shell's lines, dolly_protocol::shell parses them
+pub struct Shell {
+    lines: LineAssembler<LINE_LEN>,
+}
+
+impl Shell {
+    pub const fn new() -> Self {
+        Self {
+            lines: LineAssembler::new(),
+        }
+    }
+
+    pub fn overrun(&mut self) {
+        self.lines.overrun();
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Result<Command, LineError>> {
+        let line = match self.lines.push(byte)? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        let command = match core::str::from_utf8(line) {
+            Ok(line) => Command::parse(line),
+            Err(_) => Command::Unknown,
+        };
+        Some(Ok(command))
+    }
+}
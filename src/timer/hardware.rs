@@ -0,0 +1,217 @@
+use core::marker::PhantomData;
+
+pub const CPU_FREQ: u32 = 16_000_000; // 16 MHz
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Prescaler {
+    Direct,
+    Div8,
+    Div32,
+    Div64,
+    Div128,
+    Div256,
+    Div1024,
+}
+
+impl Prescaler {
+    pub const fn divisor(self) -> u32 {
+        match self {
+            Prescaler::Direct => 1,
+            Prescaler::Div8 => 8,
+            Prescaler::Div32 => 32,
+            Prescaler::Div64 => 64,
+            Prescaler::Div128 => 128,
+            Prescaler::Div256 => 256,
+            Prescaler::Div1024 => 1024,
+        }
+    }
+}
+
+// A timer running in CTC mode, firing its compare A interrupt at a fixed rate
+pub trait HardwareTimer: Sized {
+    // in the order the hardware offers them
+    const PRESCALERS: &'static [Prescaler];
+    const MAX_COUNT: u32;
+
+    fn start_ctc(&self, cfg: &TimerConfig<Self>);
+    fn stop(&self);
+}
+
+// Prescaler and compare value for one timer, worked out at compile time
+pub struct TimerConfig<T> {
+    pub target_hz: u32,
+    pub prescaler: Prescaler,
+    pub compare: u16,
+    timer: PhantomData<T>,
+}
+
+impl<T: HardwareTimer> TimerConfig<T> {
+    // Picks the prescaler that gets closest to the target, the smallest one on
+    // a tie for the finest resolution. Use it in a const so an impossible
+    // target fails the build instead of the board.
+    pub const fn ctc(target_hz: u32) -> Self {
+        assert!(target_hz > 0, "timer frequency must be positive");
+
+        let mut best: Option<(Prescaler, u32, u32)> = None;
+        let mut i = 0;
+        while i < T::PRESCALERS.len() {
+            let prescaler = T::PRESCALERS[i];
+            let step = target_hz as u64 * prescaler.divisor() as u64;
+            // counts per period, rounded to nearest
+            let counts = ((CPU_FREQ as u64 + step / 2) / step) as u32;
+
+            if counts >= 1 && counts - 1 <= T::MAX_COUNT {
+                let error = abs_diff(CPU_FREQ / (prescaler.divisor() * counts), target_hz);
+                let better = match best {
+                    Some((_, _, best_error)) => error < best_error,
+                    None => true,
+                };
+                if better {
+                    best = Some((prescaler, counts - 1, error));
+                }
+            }
+            i += 1;
+        }
+
+        let Some((prescaler, compare, _)) = best else {
+            panic!("timer frequency out of range for this timer");
+        };
+
+        Self {
+            target_hz,
+            prescaler,
+            compare: compare as u16,
+            timer: PhantomData,
+        }
+    }
+
+    // achieved frequency in mHz, integer hertz hide too much at low rates
+    pub const fn actual_millihertz(&self) -> u32 {
+        let divisor = self.prescaler.divisor() as u64 * (self.compare as u64 + 1);
+        (CPU_FREQ as u64 * 1000 / divisor) as u32
+    }
+
+    // signed deviation from the target in parts per million
+    pub const fn error_ppm(&self) -> i32 {
+        let target = self.target_hz as i64 * 1000;
+        ((self.actual_millihertz() as i64 - target) * 1_000_000 / target) as i32
+    }
+}
+
+const fn abs_diff(a: u32, b: u32) -> u32 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+impl HardwareTimer for arduino_hal::pac::TC0 {
+    const PRESCALERS: &'static [Prescaler] = &[
+        Prescaler::Direct,
+        Prescaler::Div8,
+        Prescaler::Div64,
+        Prescaler::Div256,
+        Prescaler::Div1024,
+    ];
+    const MAX_COUNT: u32 = u8::MAX as u32;
+
+    fn start_ctc(&self, cfg: &TimerConfig<Self>) {
+        self.tccr0a.reset();
+        self.tccr0b.reset();
+        self.tcnt0.reset();
+
+        self.tccr0a.write(|w| w.wgm0().ctc());
+        self.tccr0b.write(|w| match cfg.prescaler {
+            Prescaler::Direct => w.cs0().direct(),
+            Prescaler::Div8 => w.cs0().prescale_8(),
+            Prescaler::Div64 => w.cs0().prescale_64(),
+            Prescaler::Div256 => w.cs0().prescale_256(),
+            Prescaler::Div1024 => w.cs0().prescale_1024(),
+            // not in PRESCALERS, ctc() never picks them
+            Prescaler::Div32 | Prescaler::Div128 => unreachable!(),
+        });
+        self.ocr0a.write(|w| w.bits(cfg.compare as u8));
+        self.timsk0.write(|w| w.ocie0a().set_bit());
+    }
+
+    fn stop(&self) {
+        self.timsk0.reset();
+        self.tccr0b.reset();
+    }
+}
+
+impl HardwareTimer for arduino_hal::pac::TC1 {
+    const PRESCALERS: &'static [Prescaler] = &[
+        Prescaler::Direct,
+        Prescaler::Div8,
+        Prescaler::Div64,
+        Prescaler::Div256,
+        Prescaler::Div1024,
+    ];
+    const MAX_COUNT: u32 = u16::MAX as u32;
+
+    fn start_ctc(&self, cfg: &TimerConfig<Self>) {
+        self.tccr1a.reset();
+        self.tccr1b.reset();
+        self.tcnt1.reset();
+
+        // CTC with OCR1A as top is mode 4, WGM12 lives in TCCR1B
+        const WGM13_12_CTC: u8 = 0b01;
+        self.tccr1b.write(|w| {
+            let w = w.wgm1().bits(WGM13_12_CTC);
+            match cfg.prescaler {
+                Prescaler::Direct => w.cs1().direct(),
+                Prescaler::Div8 => w.cs1().prescale_8(),
+                Prescaler::Div64 => w.cs1().prescale_64(),
+                Prescaler::Div256 => w.cs1().prescale_256(),
+                Prescaler::Div1024 => w.cs1().prescale_1024(),
+                Prescaler::Div32 | Prescaler::Div128 => unreachable!(),
+            }
+        });
+        self.ocr1a.write(|w| w.bits(cfg.compare));
+        self.timsk1.write(|w| w.ocie1a().set_bit());
+    }
+
+    fn stop(&self) {
+        self.timsk1.reset();
+        self.tccr1b.reset();
+    }
+}
+
+impl HardwareTimer for arduino_hal::pac::TC2 {
+    const PRESCALERS: &'static [Prescaler] = &[
+        Prescaler::Direct,
+        Prescaler::Div8,
+        Prescaler::Div32,
+        Prescaler::Div64,
+        Prescaler::Div128,
+        Prescaler::Div256,
+        Prescaler::Div1024,
+    ];
+    const MAX_COUNT: u32 = u8::MAX as u32;
+
+    fn start_ctc(&self, cfg: &TimerConfig<Self>) {
+        self.tccr2a.reset();
+        self.tccr2b.reset();
+        self.tcnt2.reset();
+
+        self.tccr2a.write(|w| w.wgm2().ctc());
+        self.tccr2b.write(|w| match cfg.prescaler {
+            Prescaler::Direct => w.cs2().direct(),
+            Prescaler::Div8 => w.cs2().prescale_8(),
+            Prescaler::Div32 => w.cs2().prescale_32(),
+            Prescaler::Div64 => w.cs2().prescale_64(),
+            Prescaler::Div128 => w.cs2().prescale_128(),
+            Prescaler::Div256 => w.cs2().prescale_256(),
+            Prescaler::Div1024 => w.cs2().prescale_1024(),
+        });
+        self.ocr2a.write(|w| w.bits(cfg.compare as u8));
+        self.timsk2.write(|w| w.ocie2a().set_bit());
+    }
+
+    fn stop(&self) {
+        self.timsk2.reset();
+        self.tccr2b.reset();
+    }
+}
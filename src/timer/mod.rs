@@ -1,2 +1,56 @@
+/*
+* Timer allocation
+* - TC0: monotonic clock and IR polling, fanned out from one vector
+* - TC1: step pulse generation
+* - TC2: spare, for PWM or a buzzer
+*
+* Each peripheral is moved into exactly one owner by `Timers::claim`, and
+* every compare vector is defined here and nowhere else. A second claim is a
+* use of a moved value and a second vector a duplicate symbol, both fail the
+* build.
+*/
+
+use arduino_hal::pac::{TC0, TC1, TC2};
+
+#[cfg(feature = "ir")]
+use crate::dolly::components::irremote;
+
+use self::{tc0::ClockTC0, tc1::StepTimerTC1};
+
+pub mod hardware;
 pub mod tc0;
 pub mod tc1;
+
+pub struct Timers {
+    pub clock: ClockTC0,
+    pub step: StepTimerTC1,
+    pub spare: TC2,
+}
+
+impl Timers {
+    pub fn claim(tc0: TC0, tc1: TC1, tc2: TC2) -> Self {
+        Self {
+            clock: ClockTC0::new(tc0),
+            step: StepTimerTC1::new(tc1),
+            spare: tc2,
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "mega2560"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega2560", avr_device::interrupt(atmega2560))]
+fn TIMER0_COMPA() {
+    ClockTC0::tick();
+    #[cfg(feature = "ir")]
+    irremote::poll();
+}
+
+#[cfg_attr(not(feature = "mega2560"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega2560", avr_device::interrupt(atmega2560))]
+fn TIMER1_COMPA() {
+    StepTimerTC1::tick();
+}
+
+pub fn millis() -> u32 {
+    ClockTC0::millis()
+}
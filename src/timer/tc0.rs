@@ -6,68 +6,65 @@
 
 use core::cell::Cell;
 
+use arduino_hal::pac::TC0;
 use avr_device::interrupt::Mutex;
 
-use crate::println;
+use super::hardware::{self, HardwareTimer, TimerConfig};
 
-static COUNTER_MICRO: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
-static CLOCK_TC0: ClockTC0 = ClockTC0::new();
+static SUBTICKS: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+static MILLIS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
 
-// TODO: Test with TIMER0, TIMER1, TIMER0 and TIMER1 for implementing this Clock
-
-// #[avr_device::interrupt(atmega328p)]
-// fn TIMER0_COMPA() {
-//     CLOCK_TC0.tick();
-// }
-
-pub struct ClockTC0;
+// Monotonic clock on TC0. Its rate is also the IR sample rate, both are
+// driven from the shared TIMER0_COMPA vector in timer/mod.rs.
+pub struct ClockTC0 {
+    tc0: TC0,
+}
 
 impl ClockTC0 {
-    pub const CPU_FREQ: u32 = 16_000_000; // 16 MHz
+    pub const CPU_FREQ: u32 = hardware::CPU_FREQ;
     pub const TARGET_FREQ: u32 = 20_000; // 20 KHz
-    pub const PRESCALER: u32 = 8;
-    pub const TIMER_COUNTS: u32 = (Self::CPU_FREQ / Self::TARGET_FREQ / Self::PRESCALER) - 1;
-    pub const TIME_PER_OVERFLOW: u32 =
-        Self::PRESCALER * Self::TIMER_COUNTS as u32 / (Self::CPU_FREQ / 1000);
+    pub const TIMER: TimerConfig<TC0> = TimerConfig::ctc(Self::TARGET_FREQ);
+    pub const TICKS_PER_MS: u8 = (Self::TARGET_FREQ / 1000) as u8;
+    pub const US_PER_TICK: u32 = 1_000_000 / Self::TARGET_FREQ;
 
-    pub const fn new() -> Self {
-        Self {}
+    pub fn new(tc0: TC0) -> Self {
+        Self { tc0 }
     }
 
-    pub fn start(&self, tc0: arduino_hal::pac::TC0) {
-        if Self::TIMER_COUNTS > u8::max_value() as u32 {
-            panic!();
-        }
-
-        tc0.tccr0a.reset();
-        tc0.tccr0a.reset();
-        tc0.tcnt0.reset();
-
-        // Configure the timer for the above interval (in CTC mode)
-        tc0.tccr0a.write(|w| w.wgm0().ctc());
-        tc0.tccr0b.write(|w| match Self::PRESCALER {
-            1 => w.cs0().direct(),
-            8 => w.cs0().prescale_8(),
-            64 => w.cs0().prescale_64(),
-            256 => w.cs0().prescale_256(),
-            1024 => w.cs0().prescale_1024(),
-            _ => panic!(),
-        });
-        tc0.ocr0a.write(|w| w.bits(Self::TIMER_COUNTS as u8));
+    pub fn start(&self) {
+        self.tc0.start_ctc(&Self::TIMER);
+    }
 
-        // Enable interrupt
-        tc0.timsk0.write(|w| w.ocie0a().set_bit());
+    // wraps after ~49 days
+    pub fn millis() -> u32 {
+        avr_device::interrupt::free(|cs| MILLIS.borrow(cs).get())
     }
 
-    pub fn now(&self) -> u32 {
-        avr_device::interrupt::free(|cs| COUNTER_MICRO.borrow(cs).get())
+    // wraps after ~71 minutes
+    pub fn micros() -> u32 {
+        avr_device::interrupt::free(|cs| {
+            let ms = MILLIS.borrow(cs).get();
+            let sub = SUBTICKS.borrow(cs).get() as u32;
+            ms.wrapping_mul(1000).wrapping_add(sub * Self::US_PER_TICK)
+        })
     }
 
-    pub fn tick(&self) {
+    // only from the TIMER0_COMPA fan-out
+    pub(super) fn tick() {
         avr_device::interrupt::free(|cs| {
-            let c = COUNTER_MICRO.borrow(cs);
-            let v = c.get();
-            c.set(v.wrapping_add(1));
+            let sub = SUBTICKS.borrow(cs);
+            if sub.get() + 1 < Self::TICKS_PER_MS {
+                sub.set(sub.get() + 1);
+                return;
+            }
+
+            sub.set(0);
+            let ms = MILLIS.borrow(cs);
+            ms.set(ms.get().wrapping_add(1));
         });
     }
 }
+
+// millis() counts whole ticks per millisecond, any error would show up as drift
+const _: () = assert!(ClockTC0::TIMER.error_ppm() == 0);
+const _: () = assert!(ClockTC0::TARGET_FREQ % 1000 == 0);
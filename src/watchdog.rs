@@ -0,0 +1,151 @@
+/*
+* REFERENCE
+* - ATmega328P datasheet, 10.8 Watchdog Timer and 11.9.1 MCUSR
+*/
+
+use core::cell::Cell;
+
+use arduino_hal::hal::wdt::{Timeout, Wdt};
+use avr_device::interrupt::Mutex;
+use ufmt::{uDisplay, uwrite};
+
+use crate::{crash, error};
+
+static SEEN: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+
+// Runs one timeout before the reset in interrupt and reset mode, the last
+// chance to leave a note about what hung
+#[cfg_attr(not(feature = "mega2560"), avr_device::interrupt(atmega328p))]
+#[cfg_attr(feature = "mega2560", avr_device::interrupt(atmega2560))]
+fn WDT() {
+    let seen = avr_device::interrupt::free(|cs| SEEN.borrow(cs).get());
+    crash::record(crash::Report::new(
+        crash::Code::Watchdog,
+        "watchdog",
+        seen as u32,
+        0,
+    ));
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResetCause {
+    PowerOn,
+    External,
+    BrownOut,
+    Watchdog,
+    // flags already cleared, e.g. by a bootloader
+    Unknown,
+}
+
+impl ResetCause {
+    // Must run before the watchdog is set up, that clears WDRF
+    pub fn take(mcusr: &arduino_hal::pac::cpu::MCUSR) -> Self {
+        let flags = mcusr.read();
+        let cause = if flags.porf().bit_is_set() {
+            ResetCause::PowerOn
+        } else if flags.borf().bit_is_set() {
+            ResetCause::BrownOut
+        } else if flags.wdrf().bit_is_set() {
+            ResetCause::Watchdog
+        } else if flags.extrf().bit_is_set() {
+            ResetCause::External
+        } else {
+            ResetCause::Unknown
+        };
+
+        // clear them so the next boot does not see stale flags
+        mcusr.reset();
+        cause
+    }
+}
+
+impl uDisplay for ResetCause {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: avr_hal_generic::prelude::_ufmt_uWrite + ?Sized,
+    {
+        let name = match self {
+            ResetCause::PowerOn => "power-on",
+            ResetCause::External => "external",
+            ResetCause::BrownOut => "brown-out",
+            ResetCause::Watchdog => "watchdog",
+            ResetCause::Unknown => "unknown",
+        };
+        uwrite!(f, "{}", name)
+    }
+}
+
+// Feeds the watchdog only once every required checkpoint was passed since the
+// last feed, so a single starved task is enough to reset the board
+pub struct Watchdog {
+    wdt: Wdt,
+    timeout: Timeout,
+    required: u8,
+    running: bool,
+}
+
+impl Watchdog {
+    pub fn new(wdt: Wdt, timeout: Timeout) -> Self {
+        Self {
+            wdt,
+            timeout,
+            required: 0,
+            running: false,
+        }
+    }
+
+    pub fn require(&mut self, checkpoint: u8) {
+        self.required |= 1 << checkpoint;
+    }
+
+    pub fn check_in(&self, checkpoint: u8) {
+        avr_device::interrupt::free(|cs| {
+            let seen = SEEN.borrow(cs);
+            seen.set(seen.get() | 1 << checkpoint);
+        });
+    }
+
+    pub fn start(&mut self) {
+        avr_device::interrupt::free(|cs| SEEN.borrow(cs).set(0));
+        if self.wdt.start(self.timeout).is_err() {
+            error!("Watchdog timeout not supported");
+            return;
+        }
+        Self::arm_interrupt();
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.wdt.stop();
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Returns false when a checkpoint is still missing
+    pub fn service(&mut self) -> bool {
+        let fed = avr_device::interrupt::free(|cs| {
+            let seen = SEEN.borrow(cs);
+            if seen.get() & self.required != self.required {
+                return false;
+            }
+            seen.set(0);
+            true
+        });
+
+        if fed && self.running {
+            self.wdt.feed();
+            // hardware clears WDIE when the interrupt fires
+            Self::arm_interrupt();
+        }
+        fed
+    }
+
+    fn arm_interrupt() {
+        // SAFETY: WDIE can be set without the timed sequence, WDE stays as is
+        let wdt = unsafe { &*arduino_hal::pac::WDT::ptr() };
+        wdt.wdtcsr.modify(|_, w| w.wdie().set_bit());
+    }
+}